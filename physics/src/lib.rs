@@ -1,2 +1,3 @@
 pub mod logic;
+pub mod query;
 pub mod render;
@@ -0,0 +1,496 @@
+//! collision detection between entities, independent of any game rule, so the
+//! server, the client and the tools can share it.
+//!
+//! every collider belongs to an entity, has a shape and is in some layers,
+//! see `CollisionLayers`. colliders are sensors, the engine only reports
+//! which of them intersect.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use rapier3d::prelude::*;
+
+use crate::query::{collider_entity, SpatialQuery};
+
+//////////////////////// Types ////////////////////////
+
+/// the shape of a collider, centered at its translation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CollisionShape {
+    Ball { radius: f32 },
+    Cuboid { half_extents: Vec3 },
+}
+
+impl CollisionShape {
+    fn builder(self) -> ColliderBuilder {
+        match self {
+            Self::Ball { radius } => ColliderBuilder::ball(radius),
+            Self::Cuboid { half_extents } => {
+                ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+            }
+        }
+    }
+}
+
+/// the layers a collider is in, and the layers it collides with, as bit
+/// masks. two colliders interact if each one is in a layer the other one
+/// collides with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CollisionLayers {
+    pub memberships: u32,
+    pub filter: u32,
+}
+
+impl CollisionLayers {
+    /// in all layers and collides with all layers
+    pub const ALL: Self = Self::new(u32::MAX, u32::MAX);
+
+    pub const fn new(memberships: u32, filter: u32) -> Self {
+        Self {
+            memberships,
+            filter,
+        }
+    }
+
+    /// a filter of spatial queries, only colliders in any of `layers` are
+    /// included.
+    pub const fn selecting(layers: u32) -> Self {
+        Self::new(u32::MAX, layers)
+    }
+
+    /// test if two colliders in these layers interact
+    pub fn interacts(self, other: Self) -> bool {
+        self.memberships & other.filter != 0 && other.memberships & self.filter != 0
+    }
+
+    fn groups(self) -> InteractionGroups {
+        InteractionGroups::new(self.memberships.into(), self.filter.into())
+    }
+
+    fn from_groups(groups: InteractionGroups) -> Self {
+        Self::new(groups.memberships.bits(), groups.filter.bits())
+    }
+}
+
+/// a handle of collider in `CollisionEngine`
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CollisionHandle(ColliderHandle);
+
+/// the owner of a collider and its layers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColliderRef {
+    pub entity: Entity,
+    pub layers: CollisionLayers,
+}
+
+impl ColliderRef {
+    fn of(collider: &Collider) -> Self {
+        Self {
+            entity: collider_entity(collider),
+            layers: CollisionLayers::from_groups(collider.collision_groups()),
+        }
+    }
+}
+
+/// an intersection started or stopped during a step. an intersection with a
+/// removed collider is stopped in the step after the removal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactEvent {
+    Started(ColliderRef, ColliderRef),
+    Stopped(ColliderRef, ColliderRef),
+}
+
+/// the result of a swept query, see `CollisionEngine::sweep`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    /// the collider hit
+    pub other: ColliderRef,
+    /// the time of impact, as a fraction of the motion in [0, 1]
+    pub toi: f32,
+    /// the position of the moving collider at the time of impact
+    pub position: Vec3,
+}
+
+/// collect the events of rapier during a step
+#[derive(Default)]
+struct EventCollector(Mutex<Vec<CollisionEvent>>);
+
+impl EventHandler for EventCollector {
+    fn handle_collision_event(
+        &self,
+        _bodies: &RigidBodySet,
+        _colliders: &ColliderSet,
+        event: CollisionEvent,
+        _contact_pair: Option<&ContactPair>,
+    ) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    fn handle_contact_force_event(
+        &self,
+        _dt: Real,
+        _bodies: &RigidBodySet,
+        _colliders: &ColliderSet,
+        _contact_pair: &ContactPair,
+        _total_force_magnitude: Real,
+    ) {
+    }
+}
+
+// all colliders are sensors, so no contact needs to be predicted. a large
+// distance inflates every bounding box and makes the broad phase quadratic.
+const PREDICTION_DISTANCE: Real = 0.0;
+
+//////////////////////// Engine ////////////////////////
+
+/// the engine for collision detection, intersections are updated once per
+/// `step`.
+#[derive(Resource, Default)]
+pub struct CollisionEngine {
+    pipeline: CollisionPipeline,
+
+    colliders: ColliderSet,
+    narrow_phase: NarrowPhase,
+    broad_phase: BroadPhase,
+
+    // colliders are never attached to a rigid body, these two are always empty
+    // and only exist to satisfy the rapier api.
+    rigid_bodies: RigidBodySet,
+    islands: IslandManager,
+    query: SpatialQuery,
+
+    /// the colliders removed since the last step, rapier reports their stopped
+    /// intersections in the next step.
+    removed: HashMap<ColliderHandle, ColliderRef>,
+    events: Vec<ContactEvent>,
+}
+
+impl CollisionEngine {
+    /// create a empty collision engine.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the number of colliders in the engine
+    pub fn len(&self) -> usize {
+        self.colliders.len()
+    }
+
+    /// test if the engine contains no collider
+    pub fn is_empty(&self) -> bool {
+        self.colliders.is_empty()
+    }
+
+    /// insert a collider owned by `entity`
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        shape: CollisionShape,
+        trans: Vec3,
+        layers: CollisionLayers,
+    ) -> CollisionHandle {
+        let collider = shape
+            .builder()
+            .sensor(true)
+            .active_collision_types(ActiveCollisionTypes::FIXED_FIXED)
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .collision_groups(layers.groups())
+            .user_data(entity.to_bits() as u128)
+            .translation(trans.into())
+            .build();
+        let handle = CollisionHandle(self.colliders.insert(collider));
+        info!("[physics] spawn handle: {:?}, {:?}", entity, handle);
+        handle
+    }
+
+    /// remove a collider from the engine
+    pub fn remove(&mut self, handle: CollisionHandle) {
+        info!("[physics] remove handle: {:?}", handle);
+
+        let removed =
+            self.colliders
+                .remove(handle.0, &mut self.islands, &mut self.rigid_bodies, false);
+        if let Some(collider) = removed {
+            self.removed.insert(handle.0, ColliderRef::of(&collider));
+        }
+    }
+
+    /// update the next position of a collider. please only call this function
+    /// if the collider indeed move, because it will invalid the collider cache
+    /// in the engine.
+    ///
+    /// no effect if the handle is invalid
+    pub fn update_transform(&mut self, handle: CollisionHandle, dest: Vec3) {
+        if let Some(collider) = self.colliders.get_mut(handle.0) {
+            collider.set_translation(dest.into());
+        } else {
+            error!("[physics] invalid handle {:?}", handle);
+        }
+    }
+
+    /// move a collider to other layers, the intersections which no longer
+    /// interact are stopped in the next step.
+    ///
+    /// no effect if the handle is invalid
+    pub fn set_layers(&mut self, handle: CollisionHandle, layers: CollisionLayers) {
+        if let Some(collider) = self.colliders.get_mut(handle.0) {
+            collider.set_collision_groups(layers.groups());
+        } else {
+            error!("[physics] invalid handle {:?}", handle);
+        }
+    }
+
+    /// get the owner and the layers of a collider, including the one removed
+    /// since the last step.
+    pub fn get(&self, handle: CollisionHandle) -> Option<ColliderRef> {
+        self.get_raw(handle.0)
+    }
+
+    fn get_raw(&self, handle: ColliderHandle) -> Option<ColliderRef> {
+        let collider = self
+            .colliders
+            .get(handle)
+            .map(ColliderRef::of)
+            .or_else(|| self.removed.get(&handle).copied());
+        if collider.is_none() {
+            error!("[physics] invalid handle {:?}", handle);
+        }
+        collider
+    }
+
+    /// run one step of collision simulation, the intersections started or
+    /// stopped in this step are collected until `drain_events`.
+    pub fn step(&mut self) {
+        let collector = EventCollector::default();
+        self.pipeline.step(
+            PREDICTION_DISTANCE,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_bodies,
+            &mut self.colliders,
+            None,
+            &(),
+            &collector,
+        );
+
+        for event in collector.0.into_inner().unwrap() {
+            let (Some(a), Some(b)) = (
+                self.get_raw(event.collider1()),
+                self.get_raw(event.collider2()),
+            ) else {
+                continue;
+            };
+
+            self.events.push(match event {
+                CollisionEvent::Started(..) => ContactEvent::Started(a, b),
+                CollisionEvent::Stopped(..) => ContactEvent::Stopped(a, b),
+            });
+        }
+        self.removed.clear();
+        self.query.update(&self.colliders);
+    }
+
+    /// take the events collected by `step`, in the order they happened.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = ContactEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// get all intersections at the last step, invalid handles are skipped.
+    pub fn intersections(&self) -> impl Iterator<Item = (ColliderRef, ColliderRef)> + '_ {
+        self.narrow_phase
+            .intersection_graph()
+            .interactions_with_endpoints()
+            .filter(|pair| pair.2.intersecting)
+            .filter_map(|pair| Some((self.get_raw(pair.0)?, self.get_raw(pair.1)?)))
+    }
+
+    /// all entities whose collider intersects the sphere and interacts with
+    /// `filter`.
+    ///
+    /// like other spatial queries, colliders are at their positions of the
+    /// last step.
+    pub fn intersect_sphere(&self, center: Vec3, r: f32, filter: CollisionLayers) -> Vec<Entity> {
+        self.query
+            .intersect_sphere(&self.colliders, center, r, filter.groups().into())
+    }
+
+    /// all entities whose collider intersects the axis aligned box between
+    /// `min` and `max` and interacts with `filter`.
+    pub fn intersect_box(&self, min: Vec3, max: Vec3, filter: CollisionLayers) -> Vec<Entity> {
+        self.query
+            .intersect_box(&self.colliders, min, max, filter.groups().into())
+    }
+
+    /// the nearest `k` entities to `point` whose collider interacts with
+    /// `filter`, sorted by the distance to their collider.
+    pub fn nearest(&self, point: Vec3, k: usize, filter: CollisionLayers) -> Vec<Entity> {
+        self.query
+            .nearest(&self.colliders, point, k, filter.groups().into())
+    }
+
+    /// the first entity hit by the ray whose collider interacts with
+    /// `filter`, and the hit point.
+    pub fn cast_ray(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_toi: f32,
+        filter: CollisionLayers,
+    ) -> Option<(Entity, Vec3)> {
+        self.query.cast_ray(
+            &self.colliders,
+            origin,
+            dir,
+            max_toi,
+            filter.groups().into(),
+        )
+    }
+
+    /// continuous detection for the collider of `handle` moving linearly from
+    /// its current position to `dest`, which a single `update_transform`
+    /// may tunnel through. return the earliest collider touched.
+    ///
+    /// colliders interacting with `filter` are tested, or those interacting
+    /// with the moving collider if `None`.
+    pub fn sweep(
+        &self,
+        handle: CollisionHandle,
+        dest: Vec3,
+        filter: Option<CollisionLayers>,
+    ) -> Option<SweepHit> {
+        let Some(collider) = self.colliders.get(handle.0) else {
+            error!("[physics] invalid handle {:?}", handle);
+            return None;
+        };
+
+        let groups = filter.map_or(collider.collision_groups(), CollisionLayers::groups);
+        let from = Vec3::from(*collider.translation());
+        let (hit, toi) = self.query.sweep(
+            &self.colliders,
+            collider.shape(),
+            from,
+            dest,
+            QueryFilter::from(groups).exclude_collider(handle.0),
+        )?;
+
+        Some(SweepHit {
+            other: self.get_raw(hit)?,
+            toi,
+            position: from.lerp(dest, toi),
+        })
+    }
+
+    /// like `update_transform`, but the collider stops at the earliest
+    /// collider touched during the motion, see `sweep`.
+    pub fn update_transform_swept(
+        &mut self,
+        handle: CollisionHandle,
+        dest: Vec3,
+        filter: Option<CollisionLayers>,
+    ) -> Option<SweepHit> {
+        let hit = self.sweep(handle, dest, filter);
+        self.update_transform(handle, hit.map_or(dest, |hit| hit.position));
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: CollisionLayers = CollisionLayers::new(0b01, 0b10);
+    const B: CollisionLayers = CollisionLayers::new(0b10, 0b01);
+
+    fn ball(radius: f32) -> CollisionShape {
+        CollisionShape::Ball { radius }
+    }
+
+    fn entities(a: ColliderRef, b: ColliderRef) -> (Entity, Entity) {
+        (a.entity.min(b.entity), a.entity.max(b.entity))
+    }
+
+    #[test]
+    fn layers() {
+        assert!(A.interacts(B));
+        assert!(!A.interacts(A));
+        assert!(CollisionLayers::ALL.interacts(A));
+        assert!(CollisionLayers::selecting(0b01).interacts(A));
+        assert!(!CollisionLayers::selecting(0b01).interacts(B));
+    }
+
+    #[test]
+    fn events_follow_layers() {
+        let mut engine = CollisionEngine::new();
+        let (a, b, c) = (
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        engine.insert(a, ball(1.0), Vec3::ZERO, A);
+        let handle_b = engine.insert(b, ball(1.0), Vec3::X * 1.5, B);
+        let half_extents = Vec3::splat(0.5);
+        engine.insert(c, CollisionShape::Cuboid { half_extents }, Vec3::X, A);
+
+        engine.step();
+        let mut started: Vec<_> = engine
+            .drain_events()
+            .map(|event| match event {
+                ContactEvent::Started(x, y) => entities(x, y),
+                ContactEvent::Stopped(..) => panic!("unexpected {event:?}"),
+            })
+            .collect();
+        started.sort();
+        assert_eq!(started, [(a, b), (b, c)]);
+        assert_eq!(engine.intersections().count(), 2);
+
+        // b no longer interacts with anything
+        engine.set_layers(handle_b, CollisionLayers::new(0b10, 0));
+        engine.step();
+        let stopped = engine.drain_events().count();
+        assert_eq!(stopped, 2);
+        assert_eq!(engine.intersections().count(), 0);
+
+        engine.set_layers(handle_b, B);
+        engine.step();
+        engine.drain_events().for_each(drop);
+        engine.remove(handle_b);
+        engine.step();
+        let events: Vec<_> = engine.drain_events().collect();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| match event {
+            ContactEvent::Stopped(x, y) => x.entity == b || y.entity == b,
+            ContactEvent::Started(..) => false,
+        }));
+        assert_eq!(engine.get(handle_b), None);
+    }
+
+    #[test]
+    fn queries_and_sweep() {
+        let mut engine = CollisionEngine::new();
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+        engine.insert(a, ball(1.0), Vec3::X * 10.0, A);
+        let handle_b = engine.insert(b, ball(0.5), Vec3::X * -10.0, B);
+        engine.step();
+
+        let everything = CollisionLayers::ALL;
+        assert_eq!(engine.intersect_sphere(Vec3::X * 9.0, 0.5, everything), [a]);
+        let selected = CollisionLayers::selecting(B.memberships);
+        assert!(engine
+            .intersect_box(Vec3::splat(-20.0), Vec3::splat(20.0), selected)
+            .contains(&b));
+        assert_eq!(engine.nearest(Vec3::X * -5.0, 1, everything), [b]);
+        let (hit, _) = engine
+            .cast_ray(Vec3::ZERO, Vec3::X, 100.0, everything)
+            .unwrap();
+        assert_eq!(hit, a);
+
+        let hit = engine
+            .update_transform_swept(handle_b, Vec3::X * 100.0, None)
+            .unwrap();
+        assert_eq!(hit.other.entity, a);
+        assert!((hit.position - Vec3::X * 8.5).length() < 1e-3);
+        // a does not interact with its own layers
+        assert!(engine.sweep(handle_b, Vec3::X * 100.0, Some(A)).is_none());
+    }
+}
@@ -0,0 +1,322 @@
+//! point & range queries over a set of colliders, shared by the collision
+//! engine of `logic::collision` and the ray cast engine of `render::raycast`.
+//!
+//! colliders are never attached to a rigid body, and the lower 64 bits of
+//! their user data must be the bits of the entity owning them.
+
+use bevy::prelude::{Entity, Vec3};
+use rapier3d::parry::bounding_volume::{Aabb, BoundingVolume};
+use rapier3d::prelude::*;
+
+/// a wrapper of `QueryPipeline` returning the entities owning the colliders.
+///
+/// the results reflect the colliders at the last `update`.
+#[derive(Clone, Default)]
+pub struct SpatialQuery {
+    pipeline: QueryPipeline,
+    // always empty, only exists to satisfy the rapier api.
+    rigid_bodies: RigidBodySet,
+    /// the bounding box of all colliders, `None` if there is no collider
+    bounds: Option<Aabb>,
+}
+
+/// get the entity owning a collider
+pub fn collider_entity(collider: &Collider) -> Entity {
+    Entity::from_bits(collider.user_data as u64)
+}
+
+impl SpatialQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// rebuild the acceleration structure, must be called after colliders are
+    /// inserted, moved or removed.
+    pub fn update(&mut self, colliders: &ColliderSet) {
+        self.pipeline.update(&self.rigid_bodies, colliders);
+        self.bounds = colliders
+            .iter_enabled()
+            .map(|(_, collider)| collider.compute_aabb())
+            .reduce(|a, b| a.merged(&b));
+    }
+
+    /// all entities whose collider intersects the sphere.
+    pub fn intersect_sphere(
+        &self,
+        colliders: &ColliderSet,
+        center: Vec3,
+        r: f32,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        self.intersect_shape(colliders, center, &Ball::new(r), filter)
+    }
+
+    /// all entities whose collider intersects the axis aligned box between
+    /// `min` and `max`.
+    pub fn intersect_box(
+        &self,
+        colliders: &ColliderSet,
+        min: Vec3,
+        max: Vec3,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        let cuboid = Cuboid::new(((max - min) / 2.0).into());
+        self.intersect_shape(colliders, (min + max) / 2.0, &cuboid, filter)
+    }
+
+    fn intersect_shape(
+        &self,
+        colliders: &ColliderSet,
+        center: Vec3,
+        shape: &dyn Shape,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        self.pipeline.intersections_with_shape(
+            &self.rigid_bodies,
+            colliders,
+            &Isometry::translation(center.x, center.y, center.z),
+            shape,
+            filter,
+            |handle| {
+                entities.push(collider_entity(&colliders[handle]));
+                true
+            },
+        );
+        entities
+    }
+
+    /// the nearest `k` entities to `point`, sorted by distance. the distance
+    /// is measured to the surface of the collider, and is 0 if `point` is
+    /// inside.
+    pub fn nearest(
+        &self,
+        colliders: &ColliderSet,
+        point: Vec3,
+        k: usize,
+        filter: QueryFilter,
+    ) -> Vec<Entity> {
+        let Some(bounds) = self.bounds.filter(|_| k > 0) else {
+            return Vec::new();
+        };
+
+        // every collider is within this distance
+        let target = Point::from(Vector::from(point));
+        let cover = (bounds.center() - target).norm() + bounds.half_extents().norm();
+
+        // grow the search sphere until it contains k colliders. colliders
+        // outside of the sphere are farther than those inside, so the k
+        // nearest ones in the sphere are the k nearest ones overall.
+        let mut r = (cover / 64.0).max(f32::EPSILON);
+        loop {
+            let mut found = Vec::new();
+            self.pipeline.intersections_with_shape(
+                &self.rigid_bodies,
+                colliders,
+                &Isometry::translation(point.x, point.y, point.z),
+                &Ball::new(r),
+                filter,
+                |handle| {
+                    let collider = &colliders[handle];
+                    let distance =
+                        collider
+                            .shape()
+                            .distance_to_point(collider.position(), &target, true);
+                    found.push((distance, collider_entity(collider)));
+                    true
+                },
+            );
+
+            if found.len() >= k || r >= cover {
+                found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                return found.into_iter().take(k).map(|(_, e)| e).collect();
+            }
+            r *= 2.0;
+        }
+    }
+
+    /// the first entity hit by the ray and the hit point, `dir` does not need
+    /// to be normalized, the length of the ray is `dir.length() * max_toi`.
+    pub fn cast_ray(
+        &self,
+        colliders: &ColliderSet,
+        origin: Vec3,
+        dir: Vec3,
+        max_toi: f32,
+        filter: QueryFilter,
+    ) -> Option<(Entity, Vec3)> {
+        let ray = Ray::new(origin.into(), dir.into());
+        let (handle, toi) =
+            self.pipeline
+                .cast_ray(&self.rigid_bodies, colliders, &ray, max_toi, true, filter)?;
+        Some((collider_entity(&colliders[handle]), origin + dir * toi))
+    }
+
+    /// move `shape` linearly from `from` to `to`, return the first collider it
+    /// touches and the time of impact as a fraction of the motion in [0, 1].
+    ///
+    /// colliders already intersecting `shape` at `from` are ignored.
+    pub fn sweep(
+        &self,
+        colliders: &ColliderSet,
+        shape: &dyn Shape,
+        from: Vec3,
+        to: Vec3,
+        filter: QueryFilter,
+    ) -> Option<(ColliderHandle, f32)> {
+        let from_pos = Isometry::translation(from.x, from.y, from.z);
+
+        // the shape cast of balls always stops at penetration, so exclude
+        // them explicitly.
+        let mut inside = Vec::new();
+        self.pipeline.intersections_with_shape(
+            &self.rigid_bodies,
+            colliders,
+            &from_pos,
+            shape,
+            filter,
+            |handle| {
+                inside.push(handle);
+                true
+            },
+        );
+        let predicate = |handle, collider: &Collider| {
+            !inside.contains(&handle) && filter.predicate.iter().all(|f| f(handle, collider))
+        };
+
+        let (handle, toi) = self.pipeline.cast_shape(
+            &self.rigid_bodies,
+            colliders,
+            &from_pos,
+            &(to - from).into(),
+            shape,
+            1.0,
+            false,
+            QueryFilter {
+                predicate: Some(&predicate),
+                ..filter
+            },
+        )?;
+        Some((handle, toi.toi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 5 balls of radius 0.5 at x = 0, 2, 4, 6, 8, the entity index is x
+    fn balls() -> (ColliderSet, SpatialQuery) {
+        let mut colliders = ColliderSet::new();
+        for i in 0..5 {
+            let x = 2.0 * i as f32;
+            colliders.insert(
+                ColliderBuilder::ball(0.5)
+                    .translation(vector![x, 0.0, 0.0])
+                    .user_data(Entity::from_raw(x as u32).to_bits() as u128)
+                    .build(),
+            );
+        }
+        let mut query = SpatialQuery::new();
+        query.update(&colliders);
+        (colliders, query)
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<u32> {
+        entities.sort();
+        entities.into_iter().map(|e| e.index()).collect()
+    }
+
+    #[test]
+    fn range_queries() {
+        let (colliders, query) = balls();
+        let filter = QueryFilter::default;
+
+        let found = query.intersect_sphere(&colliders, Vec3::new(3.0, 0.0, 0.0), 1.0, filter());
+        assert_eq!(sorted(found), [2, 4]);
+
+        let found = query.intersect_box(&colliders, Vec3::splat(-1.0), Vec3::ONE, filter());
+        assert_eq!(sorted(found), [0]);
+
+        let found = query.intersect_sphere(&colliders, Vec3::Y * 5.0, 1.0, filter());
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn nearest_k() {
+        let (colliders, query) = balls();
+        let index = |entities: Vec<Entity>| -> Vec<u32> {
+            entities.into_iter().map(|e| e.index()).collect()
+        };
+        let filter = QueryFilter::default;
+
+        let found = query.nearest(&colliders, Vec3::new(4.6, 0.0, 0.0), 3, filter());
+        assert_eq!(index(found), [4, 6, 2]);
+        let found = query.nearest(&colliders, Vec3::new(100.0, 0.0, 0.0), 2, filter());
+        assert_eq!(index(found), [8, 6]);
+        let found = query.nearest(&colliders, Vec3::ZERO, 10, filter());
+        assert_eq!(index(found), [0, 2, 4, 6, 8]);
+        assert!(query
+            .nearest(&colliders, Vec3::ZERO, 0, filter())
+            .is_empty());
+        assert!(SpatialQuery::new()
+            .nearest(&colliders, Vec3::ZERO, 1, filter())
+            .is_empty());
+    }
+
+    #[test]
+    fn ray() {
+        let (colliders, query) = balls();
+        let filter = QueryFilter::default;
+
+        let (entity, hit) = query
+            .cast_ray(
+                &colliders,
+                Vec3::new(-10.0, 0.0, 0.0),
+                Vec3::X,
+                100.0,
+                filter(),
+            )
+            .unwrap();
+        assert_eq!(entity.index(), 0);
+        assert!((hit - Vec3::new(-0.5, 0.0, 0.0)).length() < 1e-4);
+
+        let missed = query.cast_ray(
+            &colliders,
+            Vec3::new(-10.0, 0.0, 0.0),
+            Vec3::X,
+            5.0,
+            filter(),
+        );
+        assert!(missed.is_none());
+        let missed = query.cast_ray(
+            &colliders,
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::X,
+            100.0,
+            filter(),
+        );
+        assert!(missed.is_none());
+    }
+
+    #[test]
+    fn sweep_ball() {
+        let (colliders, query) = balls();
+        let ball = Ball::new(0.5);
+        let filter = QueryFilter::default;
+
+        // tunnel through all balls in one motion, stop at the first one
+        let (handle, toi) = query
+            .sweep(&colliders, &ball, Vec3::X * -11.0, Vec3::X * 9.0, filter())
+            .unwrap();
+        assert_eq!(collider_entity(&colliders[handle]).index(), 0);
+        assert!((toi - 0.5).abs() < 1e-4);
+
+        // leave the ball at x = 4 without hitting anything else
+        let (from, to) = (Vec3::X * 4.5, Vec3::new(9.5, 0.0, 10.0));
+        let hit = query.sweep(&colliders, &ball, from, to, filter());
+        assert!(hit.is_none());
+        let hit = query.sweep(&colliders, &ball, Vec3::X * -11.0, Vec3::X * -2.0, filter());
+        assert!(hit.is_none());
+    }
+}
@@ -0,0 +1,231 @@
+//! interpolation between the snapshots of a fixed tick simulation, so the
+//! client can render smooth motion at any frame rate.
+
+use bevy::prelude::*;
+
+//////////////////////// Plugin ////////////////////////
+
+/// a plugin to sample the `Transform` of entities with `Interpolated` every
+/// frame.
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(system_interpolate_transform);
+    }
+}
+
+/// a system to overwrite the `Transform` of interpolated entities with the
+/// sample at the current time.
+///
+/// schedule requirement:
+/// - must run after any `Interpolated::push` of the frame
+pub fn system_interpolate_transform(
+    time: Res<Time>,
+    mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for (mut transform, interpolated) in query.iter_mut() {
+        *transform = interpolated.sample(now);
+    }
+}
+
+//////////////////////// Math ////////////////////////
+
+/// interpolate translation and scale linearly, and rotation spherically.
+/// `t` in [0, 1].
+pub fn lerp_transform(a: &Transform, b: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: a.translation.lerp(b.translation, t),
+        rotation: a.rotation.slerp(b.rotation, t),
+        scale: a.scale.lerp(b.scale, t),
+    }
+}
+
+/// cubic hermite interpolation from `p0` with velocity `v0` to `p1` with
+/// velocity `v1` in `dt` seconds. `t` in [0, 1].
+pub fn hermite(p0: Vec3, v0: Vec3, p1: Vec3, v1: Vec3, dt: f32, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+    let h10 = t3 - 2.0 * t2 + t;
+    let h01 = -2.0 * t3 + 3.0 * t2;
+    let h11 = t3 - t2;
+    p0 * h00 + v0 * (h10 * dt) + p1 * h01 + v1 * (h11 * dt)
+}
+
+/// move `p` with velocity `v` for `elapsed` seconds, but no longer than
+/// `limit` seconds.
+pub fn extrapolate(p: Vec3, v: Vec3, elapsed: f32, limit: f32) -> Vec3 {
+    p + v * elapsed.clamp(0.0, limit)
+}
+
+//////////////////////// Component ////////////////////////
+
+/// the state of an entity at a simulation tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// the time of the tick, in seconds of `Time::elapsed_seconds_f64`
+    pub time: f64,
+    pub transform: Transform,
+    /// the velocity of the translation, per second
+    pub velocity: Vec3,
+}
+
+/// how the translation is interpolated between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterpolationMode {
+    #[default]
+    Linear,
+    /// use the velocities of snapshots, smooth for curved motion
+    Hermite,
+}
+
+/// the last two snapshots of an entity, its `Transform` is sampled between
+/// them by `system_interpolate_transform`.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Interpolated {
+    previous: Snapshot,
+    next: Snapshot,
+    mode: InterpolationMode,
+    /// the maximum time to extrapolate after the last snapshot, in seconds
+    max_extrapolation: f32,
+}
+
+impl Interpolated {
+    /// start at `snapshot` without any motion
+    pub fn new(snapshot: Snapshot, mode: InterpolationMode, max_extrapolation: f32) -> Self {
+        Self {
+            previous: snapshot,
+            next: snapshot,
+            mode,
+            max_extrapolation,
+        }
+    }
+
+    /// add the snapshot of a new tick, older snapshots are ignored.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if snapshot.time > self.next.time {
+            self.previous = self.next;
+            self.next = snapshot;
+        }
+    }
+
+    /// the transform at `time`. it stays at the first snapshot before it, and
+    /// is extrapolated for at most `max_extrapolation` after the last one.
+    pub fn sample(&self, time: f64) -> Transform {
+        let (previous, next) = (&self.previous, &self.next);
+        if time >= next.time {
+            let elapsed = (time - next.time) as f32;
+            return Transform {
+                translation: extrapolate(
+                    next.transform.translation,
+                    next.velocity,
+                    elapsed,
+                    self.max_extrapolation,
+                ),
+                ..next.transform
+            };
+        }
+        if time <= previous.time {
+            return previous.transform;
+        }
+
+        let dt = (next.time - previous.time) as f32;
+        let t = (time - previous.time) as f32 / dt;
+        let mut transform = lerp_transform(&previous.transform, &next.transform, t);
+        if self.mode == InterpolationMode::Hermite {
+            transform.translation = hermite(
+                previous.transform.translation,
+                previous.velocity,
+                next.transform.translation,
+                next.velocity,
+                dt,
+                t,
+            );
+        }
+        transform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+    use std::time::{Duration, Instant};
+
+    fn snapshot(time: f64, x: f32, velocity: f32) -> Snapshot {
+        Snapshot {
+            time,
+            transform: Transform::from_xyz(x, 0.0, 0.0),
+            velocity: Vec3::X * velocity,
+        }
+    }
+
+    #[test]
+    fn interpolation_math() {
+        let a = Transform::from_xyz(0.0, 0.0, 0.0);
+        let b = Transform::from_xyz(2.0, 0.0, 0.0).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+        let half = lerp_transform(&a, &b, 0.5);
+        assert!((half.translation - Vec3::X).length() < 1e-6);
+        assert!(
+            half.rotation
+                .angle_between(Quat::from_rotation_y(FRAC_PI_2 / 2.0))
+                < 1e-4
+        );
+
+        // hermite passes the end points, and follows a constant velocity
+        let (p0, p1, v) = (Vec3::ZERO, Vec3::X * 2.0, Vec3::X);
+        assert_eq!(hermite(p0, v, p1, v, 2.0, 0.0), p0);
+        assert_eq!(hermite(p0, v, p1, v, 2.0, 1.0), p1);
+        assert!((hermite(p0, v, p1, v, 2.0, 0.25) - Vec3::X * 0.5).length() < 1e-6);
+
+        assert_eq!(extrapolate(p0, v, 10.0, 0.5), Vec3::X * 0.5);
+        assert_eq!(extrapolate(p0, v, -1.0, 0.5), p0);
+    }
+
+    #[test]
+    fn sample_between_snapshots() {
+        let mut interpolated = Interpolated::new(snapshot(0.0, 0.0, 0.0), default(), 0.25);
+        interpolated.push(snapshot(1.0, 4.0, 4.0));
+        interpolated.push(snapshot(0.5, 100.0, 0.0));
+
+        let at = |interpolated: &Interpolated, time, x: f32| {
+            (interpolated.sample(time).translation.x - x).abs() < 1e-5
+        };
+        assert!(at(&interpolated, -1.0, 0.0));
+        assert!(at(&interpolated, 0.5, 2.0));
+        assert!(at(&interpolated, 1.0, 4.0));
+        // bounded extrapolation
+        assert!(at(&interpolated, 1.1, 4.4));
+        assert!(at(&interpolated, 5.0, 5.0));
+
+        interpolated.push(snapshot(2.0, 6.0, 0.0));
+        assert!(at(&interpolated, 1.5, 5.0));
+        interpolated.mode = InterpolationMode::Hermite;
+        assert!(at(&interpolated, 1.5, 5.5));
+    }
+
+    #[test]
+    fn system_follows_time() {
+        let mut world = World::new();
+        let start = Instant::now();
+        let mut time = Time::new(start);
+        time.update_with_instant(start);
+        world.insert_resource(time);
+
+        let mut interpolated = Interpolated::new(snapshot(0.0, 0.0, 0.0), default(), 0.0);
+        interpolated.push(snapshot(1.0, 10.0, 0.0));
+        let entity = world.spawn((Transform::default(), interpolated)).id();
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_interpolate_transform);
+        world
+            .resource_mut::<Time>()
+            .update_with_instant(start + Duration::from_millis(250));
+        schedule.run(&mut world);
+
+        let x = world.get::<Transform>(entity).unwrap().translation.x;
+        assert!((x - 2.5).abs() < 1e-4);
+    }
+}
@@ -1,13 +1,128 @@
+use bevy::math::Ray;
 use bevy::prelude::*;
-use rapier3d::prelude::*;
+use bevy::window::PrimaryWindow;
+use rapier3d::prelude::{Collider, ColliderBuilder, ColliderHandle, ColliderSet, QueryFilter};
 
+use crate::query::SpatialQuery;
+
+//////////////////////// Plugin ////////////////////////
+
+/// a plugin for picking the entity under the cursor, the result is in the
+/// `CursorPick` resource.
+pub struct RayCastPlugin;
+
+impl Plugin for RayCastPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RayCastEngine>()
+            .init_resource::<CursorPick>()
+            .add_systems(
+                (
+                    system_raycast_sync_transform,
+                    system_raycast_update,
+                    system_cursor_ray,
+                    system_cursor_pick,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// a system to move the colliders of entities whose `GlobalTransform`
+/// changed.
+///
+/// schedule requirement:
+/// - must run before `system_raycast_update`
+pub fn system_raycast_sync_transform(
+    mut engine: ResMut<RayCastEngine>,
+    query: Query<(&RayCastHandle, &GlobalTransform), Changed<GlobalTransform>>,
+) {
+    for (handle, transform) in query.iter() {
+        engine.update_transform(*handle, transform.translation());
+    }
+}
+
+/// a system to rebuild the query pipeline if any collider changed.
+///
+/// schedule requirement:
+/// - must run before any `cast_ray` of the frame
+pub fn system_raycast_update(mut engine: ResMut<RayCastEngine>) {
+    engine.update();
+}
+
+/// a system to compute the ray under the cursor of the primary window, from
+/// the camera marked by `RayCastCamera`.
+pub fn system_cursor_ray(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<RayCastCamera>>,
+    mut pick: ResMut<CursorPick>,
+) {
+    let cursor = windows.get_single().ok().and_then(Window::cursor_position);
+    pick.ray = cursor
+        .zip(cameras.get_single().ok())
+        .and_then(|(cursor, (camera, transform))| {
+            let viewport = camera.logical_viewport_size()?;
+            viewport_to_ray(transform, camera.projection_matrix(), viewport, cursor)
+        });
+}
+
+/// a system to pick the entity hit by the cursor ray.
+///
+/// schedule requirement:
+/// - must run after `system_cursor_ray` and `system_raycast_update`
+pub fn system_cursor_pick(engine: Res<RayCastEngine>, mut pick: ResMut<CursorPick>) {
+    pick.hit = pick
+        .ray
+        .and_then(|ray| engine.cast_ray(ray.origin, ray.direction, f32::MAX));
+}
+
+/// convert a position in the viewport into a ray in the world, same as
+/// `Camera::viewport_to_world` but without the need of a render target.
+///
+/// `position` is in logical pixels, the origin is the bottom left corner.
+pub fn viewport_to_ray(
+    camera_transform: &GlobalTransform,
+    projection: Mat4,
+    viewport_size: Vec2,
+    position: Vec2,
+) -> Option<Ray> {
+    let ndc = position * 2.0 / viewport_size - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * projection.inverse();
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(f32::EPSILON));
+    Some(Ray {
+        origin: near,
+        direction: (far - near).try_normalize()?,
+    })
+}
+
+//////////////////////// Component ////////////////////////
+
+/// a handle for ray casting, used by clickable entities.
 #[derive(Component, Debug, Copy, Clone)]
-struct RayCastHandle(ColliderHandle);
+pub struct RayCastHandle(ColliderHandle);
+
+/// the marker of the camera used to cast the cursor ray
+#[derive(Component, Debug, Copy, Clone, Default)]
+pub struct RayCastCamera;
+
+//////////////////////// Resource ////////////////////////
 
+/// the ray under the cursor, and the entity it hits.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct CursorPick {
+    /// `None` if the cursor is outside of the window
+    pub ray: Option<Ray>,
+    /// the entity hit by `ray` and the hit point
+    pub hit: Option<(Entity, Vec3)>,
+}
+
+/// the engine for picking objects by ray casting.
 #[derive(Resource, Clone, Default)]
-struct RayCastEngine {
-    pipeline: QueryPipeline,
+pub struct RayCastEngine {
+    query: SpatialQuery,
     colliders: ColliderSet,
+    /// whether the colliders changed since the last `update`
+    dirty: bool,
 }
 
 impl RayCastEngine {
@@ -15,18 +130,147 @@ impl RayCastEngine {
         Self::default()
     }
 
-    pub fn spawner(&mut self, collider: Collider, entity: Entity) -> RayCastHandle {
-        todo!()
-        // return RayCasterSpawner {
-        //     engine: self,
-        //     builder: ColliderBuilder::new(SharedShape::ball(radius)),
-        // };
+    /// the number of colliders in the engine
+    pub fn len(&self) -> usize {
+        self.colliders.len()
+    }
+
+    /// test if the engine contains no collider
+    pub fn is_empty(&self) -> bool {
+        self.colliders.is_empty()
+    }
+
+    /// register a clickable entity, the user data of `collider` is replaced
+    /// by the entity.
+    pub fn spawn(&mut self, mut collider: Collider, entity: Entity) -> RayCastHandle {
+        collider.user_data = entity.to_bits() as u128;
+        self.dirty = true;
+        RayCastHandle(self.colliders.insert(collider))
     }
 
-    // pub fn cast_ray(&mut self) -> Option<Entity> {
-    //     static rigid_bodies: RigidBodySet = RigidBodySet::new();
-    //     self.pipeline.update(&rigid_bodies, &self.colliders);
-    //     self.pipeline
-    //         .cast_ray(&rigid_bodies, &self.colliders, ray, max_toi, true, filter)
-    // }
+    /// register a clickable entity with a ball collider
+    pub fn spawn_ball(&mut self, entity: Entity, trans: Vec3, r: f32) -> RayCastHandle {
+        let collider = ColliderBuilder::ball(r).translation(trans.into()).build();
+        self.spawn(collider, entity)
+    }
+
+    /// unregister a clickable entity
+    pub fn remove(&mut self, handle: RayCastHandle) {
+        let mut islands = Default::default();
+        let mut bodies = Default::default();
+        let removed = self
+            .colliders
+            .remove(handle.0, &mut islands, &mut bodies, false);
+        self.dirty |= removed.is_some();
+    }
+
+    /// move the collider of a clickable entity.
+    ///
+    /// no effect if the handle is invalid
+    pub fn update_transform(&mut self, handle: RayCastHandle, dest: Vec3) {
+        if let Some(collider) = self.colliders.get_mut(handle.0) {
+            collider.set_translation(dest.into());
+            self.dirty = true;
+        } else {
+            error!("[physics] invalid handle {:?}", handle);
+        }
+    }
+
+    /// rebuild the query pipeline if any collider changed.
+    pub fn update(&mut self) {
+        if self.dirty {
+            self.query.update(&self.colliders);
+            self.dirty = false;
+        }
+    }
+
+    /// the first entity hit by the ray and the hit point, `dir` does not need
+    /// to be normalized, the length of the ray is `dir.length() * max_toi`.
+    ///
+    /// colliders are at their positions of the last `update`.
+    pub fn cast_ray(&self, origin: Vec3, dir: Vec3, max_toi: f32) -> Option<(Entity, Vec3)> {
+        self.query
+            .cast_ray(&self.colliders, origin, dir, max_toi, QueryFilter::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    #[test]
+    fn spawn_move_remove() {
+        let mut engine = RayCastEngine::new();
+        let star = Entity::from_raw(1);
+        let handle = engine.spawn_ball(star, Vec3::new(0.0, 0.0, -10.0), 1.0);
+        engine.update();
+
+        let (entity, hit) = engine.cast_ray(Vec3::ZERO, -Vec3::Z, 100.0).unwrap();
+        assert_eq!(entity, star);
+        assert!((hit - Vec3::new(0.0, 0.0, -9.0)).length() < 1e-4);
+
+        engine.update_transform(handle, Vec3::new(5.0, 0.0, -10.0));
+        engine.update();
+        assert!(engine.cast_ray(Vec3::ZERO, -Vec3::Z, 100.0).is_none());
+
+        engine.remove(handle);
+        engine.update();
+        assert!(engine.is_empty());
+        assert!(engine.cast_ray(Vec3::X * 5.0, -Vec3::Z, 100.0).is_none());
+    }
+
+    #[test]
+    fn viewport_center_is_camera_forward() {
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(0.0, 0.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
+        );
+        let projection = Mat4::perspective_infinite_reverse_rh(1.0, 2.0, 0.1);
+        let viewport = Vec2::new(800.0, 400.0);
+
+        let ray = viewport_to_ray(&transform, projection, viewport, viewport / 2.0).unwrap();
+        assert!((ray.direction - -Vec3::Z).length() < 1e-4);
+        assert!((ray.origin - Vec3::new(0.0, 0.0, 9.9)).length() < 1e-3);
+
+        // the right half of the viewport looks at +x
+        let ray = viewport_to_ray(&transform, projection, viewport, Vec2::new(600.0, 200.0));
+        assert!(ray.unwrap().direction.x > 0.0);
+    }
+
+    #[test]
+    fn pick_synced_entity() {
+        let mut world = World::new();
+        world.init_resource::<RayCastEngine>();
+        world.init_resource::<CursorPick>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems(
+            (
+                system_raycast_sync_transform,
+                system_raycast_update,
+                system_cursor_pick,
+            )
+                .chain(),
+        );
+
+        let star = world.spawn(GlobalTransform::default()).id();
+        let handle = world
+            .resource_mut::<RayCastEngine>()
+            .spawn_ball(star, Vec3::ZERO, 1.0);
+        world.entity_mut(star).insert(handle);
+        world.resource_mut::<CursorPick>().ray = Some(Ray {
+            origin: Vec3::new(4.0, 0.0, 10.0),
+            direction: -Vec3::Z,
+        });
+
+        schedule.run(&mut world);
+        assert!(world.resource::<CursorPick>().hit.is_none());
+
+        world
+            .entity_mut(star)
+            .insert(GlobalTransform::from_xyz(4.0, 0.0, 0.0));
+        schedule.run(&mut world);
+        let (entity, hit) = world.resource::<CursorPick>().hit.unwrap();
+        assert_eq!(entity, star);
+        assert!((hit - Vec3::new(4.0, 0.0, 1.0)).length() < 1e-4);
+    }
 }
@@ -0,0 +1,45 @@
+//! measure the route search on generated maps, a search must fit in the
+//! budget of a tick.
+//!
+//! run with `cargo bench -p server --bench route`
+
+use bevy::prelude::Vec3;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use server::fleet::route::{generate_route_map, route_map_size, RouteParams, RoutePlanner};
+
+fn bench_route(c: &mut Criterion) {
+    let mut group = c.benchmark_group("route");
+    group.sample_size(10);
+
+    let params = RouteParams {
+        acceleration: 0.01,
+        max_speed: 0.5,
+        hyper_speed: Some(2.0),
+        jump_range: 30.0,
+    };
+    for n in [10_000, 100_000] {
+        let (systems, fortifications) = generate_route_map(48, n);
+        let size = route_map_size(n);
+        let mut planner = RoutePlanner::default();
+        planner.rebuild(systems, fortifications);
+
+        // between opposite corners, the longest search on the map
+        let from = planner.nearest(Vec3::ZERO).unwrap();
+        let to = planner.nearest(Vec3::new(size, 0.0, size)).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("search", n), &n, |b, _| {
+            b.iter(|| {
+                // drop the cache, every iteration searches
+                planner.clear_cache();
+                planner.find_route(from, to, &params)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("nearest", n), &n, |b, _| {
+            b.iter(|| planner.nearest(Vec3::new(size * 0.3, 0.0, size * 0.7)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_route);
+criterion_main!(benches);
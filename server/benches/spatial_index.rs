@@ -0,0 +1,78 @@
+//! compare the spatial indices on generated galaxies.
+//!
+//! run with `cargo bench -p server --bench spatial_index`
+
+use bevy::prelude::Vec3;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use server::map::rapier_collider::{ColliderKind, CollisionObject, RapierCollisionEngine};
+use server::map::spatial_index::{generate_galaxy, GridIndex, SpatialIndex};
+use server::map::SpaceLayer;
+
+fn build<I: SpatialIndex>(
+    mut index: I,
+    galaxy: &[(CollisionObject, Vec3, f32, SpaceLayer)],
+) -> (I, Vec<I::Handle>) {
+    let handles = galaxy
+        .iter()
+        .map(|&(object, center, radius, layer)| index.insert(object, center, radius, layer))
+        .collect();
+    index.update();
+    (index, handles)
+}
+
+/// move every fleet a bit, then update the index
+fn tick<I: SpatialIndex>(
+    index: &mut I,
+    handles: &[I::Handle],
+    galaxy: &[(CollisionObject, Vec3, f32, SpaceLayer)],
+    offset: Vec3,
+) {
+    for (handle, (object, center, ..)) in handles.iter().zip(galaxy) {
+        if object.kind == ColliderKind::Fleet {
+            index.update_transform(*handle, *center + offset);
+        }
+    }
+    index.update();
+}
+
+fn bench_spatial_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spatial_index");
+    group.sample_size(10);
+
+    for n in [10_000, 100_000] {
+        let galaxy = generate_galaxy(20230401, n);
+
+        group.bench_with_input(BenchmarkId::new("rapier/build", n), &galaxy, |b, galaxy| {
+            b.iter(|| build(RapierCollisionEngine::new(), galaxy))
+        });
+        group.bench_with_input(BenchmarkId::new("grid/build", n), &galaxy, |b, galaxy| {
+            b.iter(|| build(GridIndex::new(4.0), galaxy))
+        });
+
+        let mut offset = Vec3::ZERO;
+        group.bench_with_input(BenchmarkId::new("rapier/tick", n), &galaxy, |b, galaxy| {
+            b.iter_batched_ref(
+                || build(RapierCollisionEngine::new(), galaxy),
+                |(index, handles)| {
+                    offset.x += 0.1;
+                    tick(index, handles, galaxy, offset)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("grid/tick", n), &galaxy, |b, galaxy| {
+            b.iter_batched_ref(
+                || build(GridIndex::new(4.0), galaxy),
+                |(index, handles)| {
+                    offset.x += 0.1;
+                    tick(index, handles, galaxy, offset)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_spatial_index);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+//! a module for factions in the galaxy, a faction is controlled either by a
+//! player or by the AI.
+
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::oid::Oid;
+
+/// the faction controlled by the local player.
+///
+/// S/L data
+#[derive(Resource, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlayerFaction {
+    id: Oid,
+    name: String,
+}
+
+impl PlayerFaction {
+    pub fn new(id: Oid, name: impl Into<String>) -> Self {
+        Self {
+            id,
+            name: name.into(),
+        }
+    }
+
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
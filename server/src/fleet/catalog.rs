@@ -0,0 +1,383 @@
+//! ship classes, modules and templates loaded from `*.ships.ron` asset files,
+//! so the designers can tune the balance without recompiling.
+//!
+//! the base ship data in `server/data` is compiled in and merged first, then
+//! the files in `assets/ships`, then the ones in `assets/mods/<mod>/ships` in
+//! the order of the mod names. an entry with the same name (or class) as an
+//! earlier one overrides it. the catalog is rebuilt when any file is reloaded,
+//! and the designs of existing ships are updated if they stay valid.
+
+use core::fmt;
+use std::path::{Path, PathBuf};
+
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use fxhash::FxHashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::class::ClassRules;
+use super::design::ShipDesign;
+use super::module::ShipModule;
+use super::ship::{ShipClass, ShipTemplate};
+
+/// the folder of ship data files, relative to the asset folder or a mod folder
+pub const SHIP_DATA_FOLDER: &str = "ships";
+
+/// the folder of mods, relative to the asset folder
+pub const MOD_FOLDER: &str = "mods";
+
+/// the extension of ship data files
+pub const SHIP_DATA_EXTENSION: &str = "ships.ron";
+
+/// the ship data of the base game, it defines every class.
+const BASE_SHIP_DATA: &[u8] = include_bytes!("../../data/base.ships.ron");
+
+static BASE: Lazy<ShipDataFile> = Lazy::new(|| {
+    parse_ship_data(Path::new("base.ships.ron"), BASE_SHIP_DATA).expect("invalid base ship data")
+});
+
+/// the ship data of the base game compiled in, merged before the asset files.
+pub fn base_ship_data() -> &'static ShipDataFile {
+    &BASE
+}
+
+//////////////////////// Plugin ////////////////////////
+
+/// a plugin to load the ship data files into `ShipCatalog`. hot reload needs
+/// `AssetPlugin::watch_for_changes`.
+pub struct ShipDataPlugin;
+
+impl Plugin for ShipDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ShipDataFile>()
+            .add_asset_loader(ShipDataLoader)
+            .insert_resource(ShipCatalog::merge([base_ship_data()]))
+            .add_startup_system(system_load_ship_data)
+            .add_systems((system_ship_catalog, system_refresh_designs).chain());
+    }
+}
+
+/// a startup system to load the ship data files of the game and all mods,
+/// both folders are optional.
+pub fn system_load_ship_data(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let io = asset_server.asset_io();
+    let mut folders = vec![PathBuf::from(SHIP_DATA_FOLDER)];
+    if let Ok(mods) = io.read_directory(Path::new(MOD_FOLDER)) {
+        let mut mods: Vec<PathBuf> = mods.map(|dir| dir.join(SHIP_DATA_FOLDER)).collect();
+        mods.sort();
+        folders.extend(mods);
+    }
+    folders.retain(|dir| io.is_dir(dir));
+
+    let mut handles = Vec::new();
+    for folder in folders {
+        let Ok(files) = io.read_directory(&folder) else {
+            warn!("[ship_data] fail to read folder {:?}", folder);
+            continue;
+        };
+        let mut files: Vec<PathBuf> = files.filter(|path| is_ship_data(path)).collect();
+        files.sort();
+        handles.extend(files.into_iter().map(|path| asset_server.load(path)));
+    }
+
+    info!("[ship_data] load {} ship data files", handles.len());
+    commands.insert_resource(ShipDataHandles(handles));
+}
+
+fn is_ship_data(path: &Path) -> bool {
+    let name = path.file_name().and_then(|name| name.to_str());
+    matches!(name, Some(name) if name.ends_with(SHIP_DATA_EXTENSION))
+}
+
+/// a system to rebuild the `ShipCatalog` if any ship data file is loaded,
+/// reloaded or removed.
+///
+/// schedule requirement:
+/// - must run before `system_refresh_designs`
+pub fn system_ship_catalog(
+    mut events: EventReader<AssetEvent<ShipDataFile>>,
+    files: Res<Assets<ShipDataFile>>,
+    handles: Option<Res<ShipDataHandles>>,
+    mut catalog: ResMut<ShipCatalog>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    let Some(handles) = handles else {
+        return;
+    };
+
+    let loaded = handles.0.iter().filter_map(|handle| files.get(handle));
+    *catalog = ShipCatalog::merge(std::iter::once(base_ship_data()).chain(loaded));
+    info!(
+        "[ship_data] rebuild catalog, {} classes, {} modules, {} templates",
+        catalog.classes.len(),
+        catalog.modules.len(),
+        catalog.templates.len()
+    );
+}
+
+/// a system to update the designs of existing ships from the `ShipCatalog`
+/// after it changed. the designs are validated against the rules of the
+/// catalog, an update breaking a design is skipped.
+pub fn system_refresh_designs(catalog: Res<ShipCatalog>, mut designs: Query<&mut ShipDesign>) {
+    if !catalog.is_changed() {
+        return;
+    }
+    for mut design in designs.iter_mut() {
+        let refreshed = catalog.refresh(&design);
+        let latest = refreshed.as_ref().unwrap_or(&design);
+        if let Err(e) = latest.validate(catalog.rules(latest.template.class())) {
+            warn!(
+                "[ship_data] design {} is invalid with the reloaded data: {}",
+                latest.name, e
+            );
+            continue;
+        }
+        if let Some(refreshed) = refreshed {
+            *design = refreshed;
+        }
+    }
+}
+
+//////////////////////// Asset ////////////////////////
+
+/// the content of a ship data file, every field is optional.
+#[derive(TypeUuid, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[uuid = "d9ce9cb7-a0c9-46aa-889e-863c61ef6d5a"]
+pub struct ShipDataFile {
+    #[serde(default)]
+    pub classes: FxHashMap<ShipClass, ClassRules>,
+    #[serde(default)]
+    pub modules: Vec<ShipModule>,
+    #[serde(default)]
+    pub templates: Vec<ShipTemplate>,
+}
+
+/// the error when a ship data file can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShipDataError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ShipDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ShipDataError {}
+
+/// parse a ship data file, `path` is only used in the error.
+pub fn parse_ship_data(path: &Path, bytes: &[u8]) -> Result<ShipDataFile, ShipDataError> {
+    ron::de::from_bytes(bytes).map_err(|e| ShipDataError {
+        path: path.to_path_buf(),
+        line: e.position.line,
+        column: e.position.col,
+        message: e.code.to_string(),
+    })
+}
+
+/// the asset loader of `ShipDataFile`
+#[derive(Debug, Default)]
+pub struct ShipDataLoader;
+
+impl AssetLoader for ShipDataLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let data = parse_ship_data(load_context.path(), bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(data));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[SHIP_DATA_EXTENSION]
+    }
+}
+
+//////////////////////// Resource ////////////////////////
+
+/// the handles of all ship data files, in the order they are merged.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ShipDataHandles(pub Vec<Handle<ShipDataFile>>);
+
+/// all ship classes, modules and templates, merged from the ship data files.
+///
+/// CON data, reconstructed from the ship data files
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct ShipCatalog {
+    classes: FxHashMap<ShipClass, ClassRules>,
+    modules: FxHashMap<String, ShipModule>,
+    templates: FxHashMap<String, ShipTemplate>,
+}
+
+impl ShipCatalog {
+    /// merge the files in order, a later entry overrides an earlier one with
+    /// the same name or class.
+    pub fn merge<'a>(files: impl IntoIterator<Item = &'a ShipDataFile>) -> Self {
+        let mut catalog = Self::default();
+        for file in files {
+            catalog
+                .classes
+                .extend(file.classes.iter().map(|(k, v)| (*k, v.clone())));
+            catalog.modules.extend(
+                file.modules
+                    .iter()
+                    .map(|module| (module.name.clone(), module.clone())),
+            );
+            catalog.templates.extend(
+                file.templates
+                    .iter()
+                    .map(|template| (template.name().to_string(), template.clone())),
+            );
+        }
+        catalog
+    }
+
+    /// the rules of `class`, or the ones of the base game if no file defines
+    /// it.
+    pub fn rules(&self, class: ShipClass) -> &ClassRules {
+        self.classes.get(&class).unwrap_or_else(|| class.rules())
+    }
+
+    pub fn module(&self, name: &str) -> Option<&ShipModule> {
+        self.modules.get(name)
+    }
+
+    pub fn template(&self, name: &str) -> Option<&ShipTemplate> {
+        self.templates.get(name)
+    }
+
+    /// all templates, in no particular order
+    pub fn templates(&self) -> impl Iterator<Item = &ShipTemplate> + '_ {
+        self.templates.values()
+    }
+
+    /// the design with its template and modules replaced by the entries with
+    /// the same names in the catalog. `None` if nothing changes.
+    pub fn refresh(&self, design: &ShipDesign) -> Option<ShipDesign> {
+        let mut refreshed = design.clone();
+        if let Some(template) = self.template(design.template.name()) {
+            refreshed.template = template.clone();
+        }
+        for (_, module) in refreshed.modules.iter_mut() {
+            if let Some(latest) = self.module(&module.name) {
+                *module = latest.clone();
+            }
+        }
+        (refreshed != *design).then_some(refreshed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::ship::SlotKind;
+
+    fn base() -> ShipDataFile {
+        base_ship_data().clone()
+    }
+
+    #[test]
+    fn base_data_is_valid() {
+        let catalog = ShipCatalog::merge([&base()]);
+        assert!(catalog.templates().count() > 0);
+
+        // every class is defined, `ShipClass::rules` never fails
+        for class in ShipClass::ALL {
+            assert!(catalog.classes.contains_key(&class), "{:?}", class);
+        }
+        for template in catalog.templates() {
+            let rules = catalog.rules(template.class());
+            let design = ShipDesign::new(template.name(), template.clone());
+            assert_eq!(design.validate(rules), Ok(()), "{}", template.name());
+        }
+    }
+
+    #[test]
+    fn error_reports_line() {
+        let text = "(\n    modules: [\n        (name: \"laser\", kind: Laser),\n    ],\n)";
+        let error = parse_ship_data(Path::new("ships/bad.ships.ron"), text.as_bytes());
+        let error = error.unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(error.to_string().starts_with("ships/bad.ships.ron:3:"));
+    }
+
+    #[test]
+    fn mods_override_and_refresh() {
+        let base = base();
+        let laser = base
+            .modules
+            .iter()
+            .find(|module| module.name == "laser")
+            .unwrap()
+            .clone();
+        let reactor = base
+            .modules
+            .iter()
+            .find(|module| module.name == "reactor")
+            .unwrap()
+            .clone();
+        let design = ShipDesign::new("test", base.templates[0].clone())
+            .with_module(SlotKind::Power, reactor)
+            .with_module(SlotKind::Weapon, laser.clone());
+        assert_eq!(design.validate(base.templates[0].class().rules()), Ok(()));
+
+        let mut modded = laser.clone();
+        modded.cost += 1;
+        let extra = ShipTemplate::new("mod_hull", ShipClass::Cruiser, 1.0, 1.0);
+        let mod_file = ShipDataFile {
+            modules: vec![modded.clone()],
+            templates: vec![extra],
+            ..default()
+        };
+        let modded_catalog = ShipCatalog::merge([&base, &mod_file]);
+        assert_eq!(modded_catalog.module("laser"), Some(&modded));
+        assert!(modded_catalog.template("mod_hull").is_some());
+
+        let mut world = World::new();
+        world.insert_resource(ShipCatalog::merge([&base]));
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_refresh_designs);
+
+        let ship = world.spawn(design.clone()).id();
+        schedule.run(&mut world);
+        assert_eq!(world.get::<ShipDesign>(ship), Some(&design));
+
+        // hot reload of a mod
+        world.insert_resource(modded_catalog);
+        schedule.run(&mut world);
+        let refreshed = world.get::<ShipDesign>(ship).unwrap();
+        assert_eq!(refreshed.modules[1].1, modded);
+        let refreshed = refreshed.clone();
+
+        // a reload breaking the design is not applied
+        let class = refreshed.template.class();
+        let mut rules = base.classes[&class].clone();
+        rules.allowed_modules = Vec::new().into();
+        let mut breaking = mod_file.clone();
+        breaking.modules[0].cost += 1;
+        breaking.classes.insert(class, rules);
+        world.insert_resource(ShipCatalog::merge([&base, &breaking]));
+        schedule.run(&mut world);
+        assert_eq!(world.get::<ShipDesign>(ship), Some(&refreshed));
+    }
+}
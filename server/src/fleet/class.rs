@@ -0,0 +1,56 @@
+//! the rules of ship classes, military hulls (frigate to battleship) can mount
+//! weapons, civilian hulls can't.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use super::catalog::base_ship_data;
+use super::module::ModuleKind;
+use super::ship::{ShipClass, SlotKind};
+
+/// the rules and limits of a ship class, defined in ship data files, see
+/// `ShipCatalog`. `ShipClass::rules` are the ones of the base game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassRules {
+    /// the module kinds can be fitted
+    pub allowed_modules: Cow<'static, [ModuleKind]>,
+    /// the maximum number of slots of each kind in a template, in the order of
+    /// `SlotKind::ALL`
+    pub max_slots: [u32; 6],
+    /// the multiplier of the base mass and the integrity of templates
+    pub hull_size: f32,
+    /// whether ships of the class can enter hyper space
+    pub hyperspace: bool,
+    /// whether ships of the class count as armed
+    pub armed: bool,
+}
+
+impl ClassRules {
+    /// test if modules of `kind` can be fitted
+    pub fn allows(&self, kind: ModuleKind) -> bool {
+        self.allowed_modules.contains(&kind)
+    }
+
+    /// the maximum number of slots of `kind` in a template
+    pub fn max_slots(&self, kind: SlotKind) -> u32 {
+        self.max_slots[kind as usize]
+    }
+}
+
+impl ShipClass {
+    /// the rules and limits of the class in the base ship data compiled into
+    /// the game, used when no loaded ship data file defines the class, see
+    /// `ShipCatalog::rules`.
+    pub fn rules(self) -> &'static ClassRules {
+        base_ship_data()
+            .classes
+            .get(&self)
+            .expect("the base ship data defines every class")
+    }
+
+    /// test if the class is a civilian hull
+    pub fn is_civilian(self) -> bool {
+        matches!(self, Self::LargeCarrier | Self::MultiPurpose)
+    }
+}
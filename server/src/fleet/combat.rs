@@ -0,0 +1,775 @@
+//! the combat between hostile fleets.
+//!
+//! two fleets of different owners are engaged while their colliders intersect
+//! in normal space. the engagements are resolved in discrete rounds, every
+//! `COMBAT_ROUND_TICKS` ticks:
+//! - every armed ship picks a target in the enemy fleets by the
+//!   `TargetPriority` of its fleet, and fires once
+//! - a hit deals `weapon_dps` for each tick of the round, it depletes the
+//!   outer armor, the inner armor, then the integrity of the target, and may
+//!   disable a module of the target, see `ShipHealth`
+//! - all ships fire before the damage is applied, a ship destroyed in the
+//!   round still fires
+//! - a fleet whose health is below the `retreat_below` of its stance after
+//!   the round retreats away from its enemies, through hyper space if it can
+//!   enter it. it's marked `Retreating` until it's no longer engaged, and
+//!   doesn't retreat again meanwhile
+//!
+//! all random rolls come from a RNG seeded with the `GalaxySeed` and the
+//! `GameTime`, and the fleets and ships are visited in `Oid` order, so the
+//! same inputs always produce the same outcome.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+use serde::{Deserialize, Serialize};
+
+use super::damage::ShipHealth;
+use super::design::ShipDesign;
+use super::fleet::{FleetMarker, FleetOwner, FleetShips, FleetStats};
+use super::order::{FleetOrder, OrderQueue};
+use super::stats::ShipStats;
+use crate::map::generate::GalaxySeed;
+use crate::map::rapier_collider::{ColliderKind, RapierCollisionEngine};
+use crate::map::SpaceLayer;
+use crate::utils::oid::Oid;
+use crate::utils::time::GameTime;
+
+/// the number of ticks between two combat rounds
+pub const COMBAT_ROUND_TICKS: u64 = 8;
+
+/// the chance of a shot to hit its target
+pub const HIT_CHANCE: f32 = 0.75;
+
+/// the damage of a hit varies by up to this fraction
+pub const DAMAGE_SPREAD: f32 = 0.2;
+
+/// a ship picks its target randomly among the first `TARGET_SPREAD`
+/// candidates by priority, to spread the fire
+pub const TARGET_SPREAD: usize = 3;
+
+/// the distance a retreating fleet moves away from its enemies
+pub const RETREAT_DISTANCE: f32 = 50.0;
+
+//////////////////////// Component ////////////////////////
+
+/// the ships a fleet shoots first
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum TargetPriority {
+    /// the ship with the highest weapon dps
+    #[default]
+    MostDangerous,
+    /// the ship with the lowest remaining health
+    Weakest,
+    /// the ship with the highest mass
+    Largest,
+}
+
+/// the combat behaviour of a fleet, a fleet without stance uses the default.
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CombatStance {
+    pub priority: TargetPriority,
+    /// the fleet retreats once its health drops below this fraction of the
+    /// undamaged health, `0.0` to fight to the last ship
+    pub retreat_below: f32,
+}
+
+impl Default for CombatStance {
+    fn default() -> Self {
+        Self {
+            priority: TargetPriority::default(),
+            retreat_below: 0.25,
+        }
+    }
+}
+
+/// a marker of a fleet retreating from combat, removed once the fleet is no
+/// longer engaged.
+///
+/// CON data, reset on load, the fleet may retreat again
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retreating;
+
+//////////////////////// Event ////////////////////////
+
+/// an entry of the combat log, `time` is the `GameTime` of the round.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CombatLog {
+    Hit {
+        time: u64,
+        attacker: Oid,
+        target: Oid,
+        damage: f32,
+    },
+    Miss {
+        time: u64,
+        attacker: Oid,
+        target: Oid,
+    },
+    ModuleDisabled {
+        time: u64,
+        ship: Oid,
+        /// the index of the module in `ShipDesign::modules`
+        module: usize,
+    },
+    Destroyed {
+        time: u64,
+        ship: Oid,
+        fleet: Oid,
+    },
+    Retreat {
+        time: u64,
+        fleet: Oid,
+    },
+}
+
+//////////////////////// System ////////////////////////
+
+/// a system to resolve a combat round between all hostile fleets intersecting
+/// in normal space, see the module documentation.
+///
+/// the damage is written to `ShipHealth`, destroyed ships are left to
+/// `system_ship_destruction`.
+///
+/// schedule requirement:
+/// - must run after `system_collision_step`
+#[allow(clippy::type_complexity)]
+pub fn system_combat(
+    mut commands: Commands,
+    time: Res<GameTime>,
+    seed: Option<Res<GalaxySeed>>,
+    engine: Res<RapierCollisionEngine>,
+    mut fleets: Query<
+        (
+            Entity,
+            &Oid,
+            &FleetOwner,
+            &Transform,
+            &SpaceLayer,
+            &FleetShips,
+            &FleetStats,
+            Option<&CombatStance>,
+            Option<&Retreating>,
+            &mut OrderQueue,
+        ),
+        With<FleetMarker>,
+    >,
+    mut ships: Query<(
+        &Oid,
+        &ShipStats,
+        Option<&ShipDesign>,
+        Option<&mut ShipHealth>,
+    )>,
+    mut log: EventWriter<CombatLog>,
+) {
+    if !time.ticks().is_multiple_of(COMBAT_ROUND_TICKS) {
+        return;
+    }
+
+    // the hostile fleets engaged with each fleet, by `Oid`
+    let mut engaged: BTreeMap<Oid, (Entity, Vec<Oid>)> = BTreeMap::new();
+    for (a, b) in engine.intersections() {
+        if a.kind != ColliderKind::Fleet || b.kind != ColliderKind::Fleet {
+            continue;
+        }
+        let (Ok(fa), Ok(fb)) = (fleets.get(a.entity), fleets.get(b.entity)) else {
+            continue;
+        };
+        let hostile = fa.2 != fb.2;
+        let normal = *fa.4 == SpaceLayer::Normal && *fb.4 == SpaceLayer::Normal;
+        if hostile && normal {
+            let entry = engaged
+                .entry(*fa.1)
+                .or_insert_with(|| (a.entity, Vec::new()));
+            entry.1.push(*fb.1);
+            let entry = engaged
+                .entry(*fb.1)
+                .or_insert_with(|| (b.entity, Vec::new()));
+            entry.1.push(*fa.1);
+        }
+    }
+    for (fleet, id, .., retreating, _) in fleets.iter() {
+        if retreating.is_some() && !engaged.contains_key(id) {
+            commands.entity(fleet).remove::<Retreating>();
+        }
+    }
+    if engaged.is_empty() {
+        return;
+    }
+
+    let index: BTreeMap<Oid, usize> = engaged.keys().enumerate().map(|(i, id)| (*id, i)).collect();
+    let mut battle = Battle::default();
+    let mut fleet_entities = Vec::new();
+    let mut ship_entities = Vec::new();
+    for (id, (fleet, enemies)) in engaged.iter() {
+        let Ok((_, _, _, transform, _, members, _, stance, retreating, _)) = fleets.get(*fleet)
+        else {
+            continue;
+        };
+        let mut enemies: Vec<usize> = enemies.iter().map(|enemy| index[enemy]).collect();
+        enemies.sort_unstable();
+        enemies.dedup();
+        battle.fleets.push(BattleFleet {
+            id: *id,
+            stance: stance.copied().unwrap_or_default(),
+            enemies,
+            retreating: retreating.is_some(),
+        });
+        fleet_entities.push((*fleet, transform.translation));
+
+        // `FleetShips` keeps the order the ships joined, visit them by `Oid`
+        let mut members: Vec<(Oid, Entity)> = members
+            .iter()
+            .filter_map(|ship| Some((*ships.get(ship).ok()?.0, ship)))
+            .collect();
+        members.sort_unstable();
+        for (_, ship) in members {
+            let Ok((oid, stats, design, health)) = ships.get(ship) else {
+                continue;
+            };
+            let modules = design.map_or(0, |design| design.modules.len());
+            battle.ships.push(
+                Combatant::new(*oid, fleet_entities.len() - 1, stats, health.cloned())
+                    .with_modules(modules),
+            );
+            ship_entities.push(ship);
+        }
+    }
+
+    let seed = seed.map_or(0, |seed| u64::from(*seed));
+    let mut rng = combat_rng(seed, time.ticks());
+    let entries = battle.round(time.ticks(), &mut rng);
+
+    for (combatant, ship) in battle.ships.into_iter().zip(ship_entities) {
+        match ships.get_mut(ship) {
+            Ok((.., Some(mut health))) => health.set_if_neq(combatant.health),
+            Ok((.., None)) => {
+                commands.entity(ship).insert(combatant.health);
+            }
+            Err(_) => {}
+        }
+    }
+
+    for entry in entries.iter() {
+        let CombatLog::Retreat { fleet, .. } = entry else {
+            continue;
+        };
+        let i = index[fleet];
+        let (entity, position) = fleet_entities[i];
+        let enemies = &battle.fleets[i].enemies;
+        let center = enemies
+            .iter()
+            .map(|enemy| fleet_entities[*enemy].1)
+            .sum::<Vec3>()
+            / enemies.len() as f32;
+        let away = (position - center).try_normalize().unwrap_or(Vec3::X);
+        if let Ok((.., stats, _, _, mut orders)) = fleets.get_mut(entity) {
+            orders.replace(FleetOrder::MoveToPoint {
+                position: position + away * RETREAT_DISTANCE,
+                hyperspace: stats.hyper_speed.is_some(),
+            });
+        }
+        commands.entity(entity).insert(Retreating);
+        info!("[combat] fleet {:?} retreats", fleet);
+    }
+
+    log.send_batch(entries);
+}
+
+/// the RNG of the combat round at `time`
+pub fn combat_rng(seed: u64, time: u64) -> Xoshiro256StarStar {
+    Xoshiro256StarStar::seed_from_u64(seed ^ time.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+//////////////////////// Resolution ////////////////////////
+
+/// a ship in a battle
+#[derive(Debug, Clone, PartialEq)]
+pub struct Combatant {
+    pub ship: Oid,
+    /// the index of the fleet in `Battle::fleets`
+    pub fleet: usize,
+    pub weapon_dps: f32,
+    pub mass: f32,
+    /// the total health of the undamaged ship
+    pub max_health: f32,
+    pub max_integrity: f32,
+    /// the number of modules which can be disabled
+    pub modules: usize,
+    pub health: ShipHealth,
+}
+
+impl Combatant {
+    /// a ship with `health`, or undamaged if `None`
+    pub fn new(ship: Oid, fleet: usize, stats: &ShipStats, health: Option<ShipHealth>) -> Self {
+        let full = ShipHealth::full(stats);
+        Self {
+            ship,
+            fleet,
+            weapon_dps: stats.weapon_dps,
+            mass: stats.mass,
+            max_health: full.total(),
+            max_integrity: full.integrity,
+            modules: 0,
+            health: health.unwrap_or(full),
+        }
+    }
+
+    /// set the number of modules of the design
+    pub fn with_modules(mut self, modules: usize) -> Self {
+        self.modules = modules;
+        self
+    }
+}
+
+/// a fleet in a battle
+#[derive(Debug, Clone, PartialEq)]
+pub struct BattleFleet {
+    pub id: Oid,
+    pub stance: CombatStance,
+    /// the indices of the hostile fleets in `Battle::fleets`
+    pub enemies: Vec<usize>,
+    /// whether the fleet is already retreating
+    pub retreating: bool,
+}
+
+/// the fleets and the ships engaged in combat, independent of the ECS.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Battle {
+    pub fleets: Vec<BattleFleet>,
+    pub ships: Vec<Combatant>,
+}
+
+impl Battle {
+    /// resolve a round, return the combat log of the round. the health of
+    /// the ships is updated in place, destroyed ships are kept.
+    pub fn round(&mut self, time: u64, rng: &mut impl Rng) -> Vec<CombatLog> {
+        let mut entries = Vec::new();
+        let mut damage = vec![0.0; self.ships.len()];
+
+        for shooter in self.ships.iter() {
+            if shooter.weapon_dps <= 0.0 || shooter.health.is_destroyed() {
+                continue;
+            }
+            let Some(target) = self.pick_target(shooter, rng) else {
+                continue;
+            };
+            let target_id = self.ships[target].ship;
+            if rng.gen::<f32>() < HIT_CHANCE {
+                let spread = rng.gen_range(1.0 - DAMAGE_SPREAD..=1.0 + DAMAGE_SPREAD);
+                let amount = shooter.weapon_dps * COMBAT_ROUND_TICKS as f32 * spread;
+                damage[target] += amount;
+                entries.push(CombatLog::Hit {
+                    time,
+                    attacker: shooter.ship,
+                    target: target_id,
+                    damage: amount,
+                });
+            } else {
+                entries.push(CombatLog::Miss {
+                    time,
+                    attacker: shooter.ship,
+                    target: target_id,
+                });
+            }
+        }
+
+        for (ship, amount) in self.ships.iter_mut().zip(damage) {
+            if amount <= 0.0 {
+                continue;
+            }
+            let taken = ship.health.apply_damage(amount);
+            let disabled = ship
+                .health
+                .damage_modules(ship.modules, taken, ship.max_integrity, rng);
+            if let Some(module) = disabled {
+                entries.push(CombatLog::ModuleDisabled {
+                    time,
+                    ship: ship.ship,
+                    module,
+                });
+            }
+            if ship.health.is_destroyed() {
+                entries.push(CombatLog::Destroyed {
+                    time,
+                    ship: ship.ship,
+                    fleet: self.fleets[ship.fleet].id,
+                });
+            }
+        }
+        let after: Vec<f32> = (0..self.fleets.len()).map(|i| self.fraction(i)).collect();
+        for (fleet, after) in self.fleets.iter_mut().zip(after) {
+            if !fleet.retreating && after < fleet.stance.retreat_below && after > 0.0 {
+                fleet.retreating = true;
+                entries.push(CombatLog::Retreat {
+                    time,
+                    fleet: fleet.id,
+                });
+            }
+        }
+        entries
+    }
+
+    /// the ships of the fleet which are not destroyed
+    pub fn survivors(&self, fleet: usize) -> impl Iterator<Item = &Combatant> + '_ {
+        self.ships
+            .iter()
+            .filter(move |ship| ship.fleet == fleet && !ship.health.is_destroyed())
+    }
+
+    /// the remaining health of a fleet as a fraction of its undamaged health
+    fn fraction(&self, fleet: usize) -> f32 {
+        let (health, max) = self
+            .ships
+            .iter()
+            .filter(|ship| ship.fleet == fleet)
+            .fold((0.0, 0.0), |(health, max), ship| {
+                (health + ship.health.total().max(0.0), max + ship.max_health)
+            });
+        if max > 0.0 {
+            health / max
+        } else {
+            0.0
+        }
+    }
+
+    fn pick_target(&self, shooter: &Combatant, rng: &mut impl Rng) -> Option<usize> {
+        let fleet = &self.fleets[shooter.fleet];
+        let mut candidates: Vec<usize> = (0..self.ships.len())
+            .filter(|i| {
+                let ship = &self.ships[*i];
+                fleet.enemies.contains(&ship.fleet) && !ship.health.is_destroyed()
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let key = |i: &usize| {
+            let ship = &self.ships[*i];
+            match fleet.stance.priority {
+                TargetPriority::MostDangerous => -ship.weapon_dps,
+                TargetPriority::Weakest => ship.health.total(),
+                TargetPriority::Largest => -ship.mass,
+            }
+        };
+        candidates.sort_by(|a, b| {
+            key(a)
+                .total_cmp(&key(b))
+                .then_with(|| self.ships[*a].ship.cmp(&self.ships[*b].ship))
+        });
+        let spread = candidates.len().min(TARGET_SPREAD);
+        Some(candidates[rng.gen_range(0..spread)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::damage::system_ship_destruction;
+    use crate::fleet::fleet::spawn_fleet;
+    use crate::fleet::stats::ArmorLayers;
+    use crate::map::rapier_collider::system_collision_step;
+    use bevy::ecs::schedule::Schedule;
+
+    fn stats(dps: f32, mass: f32) -> ShipStats {
+        ShipStats {
+            mass,
+            weapon_dps: dps,
+            integrity: 100.0,
+            armor: ArmorLayers {
+                outer: 40.0,
+                inner: 20.0,
+            },
+            ..default()
+        }
+    }
+
+    fn battle(priority: TargetPriority, retreat_below: f32) -> Battle {
+        let stance = CombatStance {
+            priority,
+            retreat_below,
+        };
+        let mut battle = Battle {
+            fleets: vec![
+                BattleFleet {
+                    id: Oid::v5(b"attacker"),
+                    stance,
+                    enemies: vec![1],
+                    retreating: false,
+                },
+                BattleFleet {
+                    id: Oid::v5(b"defender"),
+                    stance,
+                    enemies: vec![0],
+                    retreating: false,
+                },
+            ],
+            ships: Vec::new(),
+        };
+        for i in 0..6u8 {
+            let fleet = usize::from(i % 2);
+            let ship = Oid::v5(&[b's', i]);
+            let stats = stats(1.0 + f32::from(i), 10.0 * f32::from(6 - i));
+            battle
+                .ships
+                .push(Combatant::new(ship, fleet, &stats, None).with_modules(2));
+        }
+        battle
+    }
+
+    fn fight(mut battle: Battle, seed: u64) -> (Battle, Vec<CombatLog>) {
+        let mut entries = Vec::new();
+        for round in 1..=100 {
+            let time = round * COMBAT_ROUND_TICKS;
+            entries.extend(battle.round(time, &mut combat_rng(seed, time)));
+            if battle.survivors(0).count() == 0 || battle.survivors(1).count() == 0 {
+                break;
+            }
+        }
+        (battle, entries)
+    }
+
+    #[test]
+    fn same_seed_same_outcome() {
+        let (a, log_a) = fight(battle(TargetPriority::MostDangerous, 0.0), 42);
+        let (b, log_b) = fight(battle(TargetPriority::MostDangerous, 0.0), 42);
+        assert_eq!(a, b);
+        assert_eq!(log_a, log_b);
+
+        // fights to the last ship, the stronger odd ships win
+        assert_eq!(a.survivors(0).count(), 0);
+        assert!(a.survivors(1).count() > 0);
+        let destroyed = log_a
+            .iter()
+            .filter(|e| matches!(e, CombatLog::Destroyed { .. }))
+            .count();
+        assert_eq!(destroyed, 3 + 3 - a.survivors(1).count());
+        assert!(log_a.iter().any(|e| matches!(e, CombatLog::Miss { .. })));
+        assert!(log_a
+            .iter()
+            .any(|e| matches!(e, CombatLog::ModuleDisabled { module, .. } if *module < 2)));
+        assert!(!log_a.iter().any(|e| matches!(e, CombatLog::Retreat { .. })));
+
+        let (_, log_c) = fight(battle(TargetPriority::MostDangerous, 0.0), 43);
+        assert_ne!(log_a, log_c);
+    }
+
+    #[test]
+    fn targeting_priority() {
+        let targets = |priority| {
+            let stance = CombatStance {
+                priority,
+                retreat_below: 0.0,
+            };
+            let mut battle = Battle {
+                fleets: vec![
+                    BattleFleet {
+                        id: Oid::v5(b"attacker"),
+                        stance,
+                        enemies: vec![1],
+                        retreating: false,
+                    },
+                    BattleFleet {
+                        id: Oid::v5(b"defender"),
+                        stance,
+                        enemies: vec![0],
+                        retreating: false,
+                    },
+                ],
+                ships: vec![Combatant::new(Oid::v5(b"a"), 0, &stats(1.0, 10.0), None)],
+            };
+            // dps 1 to 5, mass 50 to 10, the ship 0 is damaged
+            for i in 0..5u8 {
+                let stats = stats(1.0 + f32::from(i), 10.0 * f32::from(5 - i));
+                let health = (i == 0).then_some(ShipHealth {
+                    integrity: 1.0,
+                    ..default()
+                });
+                battle
+                    .ships
+                    .push(Combatant::new(Oid::v5(&[i]), 1, &stats, health));
+            }
+
+            let mut targets = Vec::new();
+            for seed in 0..64 {
+                let entries = battle.clone().round(0, &mut combat_rng(seed, 0));
+                for entry in entries {
+                    if let CombatLog::Hit {
+                        attacker, target, ..
+                    }
+                    | CombatLog::Miss {
+                        attacker, target, ..
+                    } = entry
+                    {
+                        if attacker == Oid::v5(b"a") && !targets.contains(&target) {
+                            targets.push(target);
+                        }
+                    }
+                }
+            }
+            targets.sort();
+            targets
+        };
+        let ships = |ids: &[u8]| {
+            let mut ships: Vec<Oid> = ids.iter().map(|i| Oid::v5(&[*i])).collect();
+            ships.sort();
+            ships
+        };
+
+        assert_eq!(targets(TargetPriority::MostDangerous), ships(&[2, 3, 4]));
+        assert_eq!(targets(TargetPriority::Largest), ships(&[0, 1, 2]));
+        assert_eq!(targets(TargetPriority::Weakest), ships(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn retreat_once_below_threshold() {
+        let (battle, entries) = fight(battle(TargetPriority::MostDangerous, 0.5), 1);
+        let retreats: Vec<_> = entries
+            .iter()
+            .filter_map(|e| match e {
+                CombatLog::Retreat { time, fleet } => Some((*time, *fleet)),
+                _ => None,
+            })
+            .collect();
+        assert!(retreats
+            .iter()
+            .any(|(_, fleet)| *fleet == battle.fleets[0].id));
+        // each fleet retreats at most once
+        let mut fleets: Vec<_> = retreats.iter().map(|(_, fleet)| *fleet).collect();
+        fleets.sort_unstable();
+        fleets.dedup();
+        assert_eq!(fleets.len(), retreats.len());
+    }
+
+    #[test]
+    fn retreat_when_engaged_below_threshold() {
+        let mut battle = battle(TargetPriority::MostDangerous, 0.5);
+        for ship in battle.ships.iter_mut().filter(|ship| ship.fleet == 0) {
+            ship.health.integrity = 10.0;
+        }
+        let entries = battle.round(COMBAT_ROUND_TICKS, &mut combat_rng(1, 0));
+        assert!(entries.contains(&CombatLog::Retreat {
+            time: COMBAT_ROUND_TICKS,
+            fleet: battle.fleets[0].id,
+        }));
+        assert!(battle.fleets[0].retreating);
+
+        // an already retreating fleet doesn't retreat again
+        let entries = battle.round(2 * COMBAT_ROUND_TICKS, &mut combat_rng(1, 1));
+        assert!(!entries
+            .iter()
+            .any(|e| matches!(e, CombatLog::Retreat { .. })));
+    }
+
+    fn run_combat(
+        seed: u64,
+        hyper_speed: Option<f32>,
+    ) -> (Vec<CombatLog>, usize, usize, Vec<FleetOrder>) {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<Events<CombatLog>>();
+        world.init_resource::<Events<crate::map::rapier_collider::ProximityStarted>>();
+        world.init_resource::<Events<crate::map::rapier_collider::ProximityStopped>>();
+        world.insert_resource(GameTime::new(0));
+        world.insert_resource(GalaxySeed::new(seed));
+
+        let spawn = |world: &mut World, owner: &[u8], name: &[u8], dps: f32, position: Vec3| {
+            let ships: Vec<Entity> = (0..3u8)
+                .map(|i| {
+                    let oid = Oid::v5(&[name, &[i]].concat());
+                    let stats = ShipStats {
+                        hyper_speed,
+                        ..stats(dps, 10.0)
+                    };
+                    world.spawn((oid, stats)).id()
+                })
+                .collect();
+            let fleet = spawn_fleet(world, Oid::v5(owner), position, SpaceLayer::Normal, ships);
+            world.entity_mut(fleet).insert(Oid::v5(name));
+            fleet
+        };
+        let weak = spawn(&mut world, b"weak", b"weak", 1.0, Vec3::ZERO);
+        let strong = spawn(
+            &mut world,
+            b"strong",
+            b"strong",
+            4.0,
+            Vec3::new(1.5, 0.0, 0.0),
+        );
+        // a friendly fleet of the same owner never fights
+        let allied = spawn(
+            &mut world,
+            b"strong",
+            b"allied",
+            4.0,
+            Vec3::new(3.0, 0.0, 0.0),
+        );
+        world.entity_mut(weak).insert(CombatStance {
+            priority: TargetPriority::Weakest,
+            retreat_below: 0.5,
+        });
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_collision_step);
+        schedule.add_system(system_combat.after(system_collision_step));
+        schedule.add_system(system_ship_destruction.after(system_combat));
+        let mut entries = Vec::new();
+        // the last orders of the weak fleet, which is despawned if destroyed
+        let mut orders = Vec::new();
+        // the ships destroyed in the last round are despawned in the next tick
+        for tick in 1..=20 * COMBAT_ROUND_TICKS + 1 {
+            world.insert_resource(GameTime::new(tick));
+            schedule.run(&mut world);
+            let mut events = world.resource_mut::<Events<CombatLog>>();
+            entries.extend(events.drain());
+            if let Some(queue) = world.get::<OrderQueue>(weak) {
+                orders = queue.iter().cloned().collect();
+            }
+        }
+
+        let count = |fleet| world.get::<FleetShips>(fleet).map_or(0, FleetShips::len);
+        assert_eq!(count(allied), 3);
+        (entries, count(weak), count(strong), orders)
+    }
+
+    #[test]
+    fn combat_in_ecs_is_deterministic() {
+        let (entries, weak, strong, orders) = run_combat(5, Some(2.0));
+        assert_eq!(
+            run_combat(5, Some(2.0)),
+            (entries.clone(), weak, strong, orders.clone())
+        );
+
+        assert!(weak < 3);
+        assert_eq!(strong, 3);
+        let destroyed = entries
+            .iter()
+            .filter(|e| matches!(e, CombatLog::Destroyed { .. }))
+            .count();
+        assert_eq!(destroyed, 3 - weak);
+        // the weak fleet retreats once, away from the strong fleet
+        let retreats = entries
+            .iter()
+            .filter(|e| matches!(e, CombatLog::Retreat { fleet, .. } if *fleet == Oid::v5(b"weak")))
+            .count();
+        assert_eq!(retreats, 1);
+        assert!(matches!(
+            orders.as_slice(),
+            [FleetOrder::MoveToPoint { position, hyperspace: true }] if position.x < 0.0
+        ));
+    }
+
+    #[test]
+    fn retreat_without_hyper_drive() {
+        let (_, _, _, orders) = run_combat(5, None);
+        assert!(matches!(
+            orders.as_slice(),
+            [FleetOrder::MoveToPoint { position, hyperspace: false }] if position.x < 0.0
+        ));
+    }
+}
@@ -0,0 +1,534 @@
+//! the damage state of ships, and its lifecycle.
+//!
+//! a ship without `ShipHealth` is undamaged. the damage depletes the outer
+//! armor, the inner armor, then the integrity of a ship, and a hit on the
+//! structure may disable a module, e.g. an engine or a hyper drive. the
+//! effects of disabled modules are removed from the `ShipStats`, except the
+//! armor which is tracked by the layers.
+//!
+//! damaged ships are repaired every tick:
+//! - by the `Repair` effects of their working modules, anywhere
+//! - by `DOCK_REPAIR_RATE` while their fleet is in the hill sphere of a
+//!   friendly solar system. the galaxy has no territory yet, a solar system is
+//!   friendly to a fleet when no hostile fleet is in its hill sphere.
+//!
+//! the structure is repaired first, then the inner and the outer armor. once
+//! the structure is intact, a disabled module is restored each tick. a fully
+//! repaired ship loses its `ShipHealth`.
+//!
+//! a ship whose integrity is depleted is destroyed by `destroy_ship`.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::design::ShipDesign;
+use super::fleet::{disband_fleet, FleetMarker, FleetOwner, FleetShips};
+use super::module::ModuleEffect;
+use super::stats::{ArmorLayers, ShipStats};
+use crate::map::rapier_collider::{ColliderKind, RapierCollider, RapierCollisionEngine};
+use crate::map::SpaceLayer;
+use crate::utils::oid::{Oid, OidTable};
+
+/// the chance of a hit on the structure to disable a module, per fraction of
+/// the integrity lost in the hit
+pub const MODULE_DAMAGE_CHANCE: f32 = 2.0;
+
+/// the structure and armor points repaired per tick at a friendly solar
+/// system
+pub const DOCK_REPAIR_RATE: f32 = 2.0;
+
+//////////////////////// Component ////////////////////////
+
+/// the remaining armor and structure points of a ship, and its disabled
+/// modules.
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ShipHealth {
+    pub armor: ArmorLayers,
+    pub integrity: f32,
+    /// the indices of the disabled modules in `ShipDesign::modules`, sorted
+    pub disabled: Vec<usize>,
+}
+
+impl ShipHealth {
+    /// the health of an undamaged ship
+    pub fn full(stats: &ShipStats) -> Self {
+        Self {
+            armor: stats.armor,
+            integrity: stats.integrity,
+            disabled: Vec::new(),
+        }
+    }
+
+    /// the sum of armor and structure points
+    pub fn total(&self) -> f32 {
+        self.armor.outer + self.armor.inner + self.integrity
+    }
+
+    /// the ship is destroyed once the integrity is depleted
+    pub fn is_destroyed(&self) -> bool {
+        self.integrity <= 0.0
+    }
+
+    /// test if the module at `index` of the design is disabled
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.disabled.binary_search(&index).is_ok()
+    }
+
+    /// apply `damage` layer by layer, return the damage taken by the
+    /// integrity.
+    pub fn apply_damage(&mut self, damage: f32) -> f32 {
+        let mut damage = damage.max(0.0);
+        for layer in [&mut self.armor.outer, &mut self.armor.inner] {
+            let absorbed = damage.min(*layer);
+            *layer -= absorbed;
+            damage -= absorbed;
+        }
+        let taken = damage.min(self.integrity.max(0.0));
+        self.integrity -= taken;
+        taken
+    }
+
+    /// roll for a module of `modules` to be disabled by a hit which took
+    /// `taken` of `max_integrity`, return the index of the disabled module.
+    pub fn damage_modules(
+        &mut self,
+        modules: usize,
+        taken: f32,
+        max_integrity: f32,
+        rng: &mut impl Rng,
+    ) -> Option<usize> {
+        if taken <= 0.0 || max_integrity <= 0.0 || self.is_destroyed() {
+            return None;
+        }
+        let chance = taken / max_integrity * MODULE_DAMAGE_CHANCE;
+        if rng.gen::<f32>() >= chance {
+            return None;
+        }
+        let working: Vec<usize> = (0..modules).filter(|i| !self.is_disabled(*i)).collect();
+        if working.is_empty() {
+            return None;
+        }
+        let module = working[rng.gen_range(0..working.len())];
+        let at = self.disabled.binary_search(&module).unwrap_or_else(|at| at);
+        self.disabled.insert(at, module);
+        Some(module)
+    }
+
+    /// repair `points`, the structure first, then the inner and the outer
+    /// armor, up to `full`. a disabled module is restored if the structure is
+    /// intact.
+    pub fn repair(&mut self, points: f32, full: &ShipHealth) {
+        if self.is_destroyed() {
+            return;
+        }
+        let mut points = points.max(0.0);
+        for (value, max) in [
+            (&mut self.integrity, full.integrity),
+            (&mut self.armor.inner, full.armor.inner),
+            (&mut self.armor.outer, full.armor.outer),
+        ] {
+            let repaired = points.min((max - *value).max(0.0));
+            *value += repaired;
+            points -= repaired;
+        }
+        if self.integrity >= full.integrity && !self.disabled.is_empty() {
+            self.disabled.remove(0);
+        }
+    }
+
+    /// test if nothing is left to repair
+    pub fn is_full(&self, full: &ShipHealth) -> bool {
+        self.integrity >= full.integrity
+            && self.armor.inner >= full.armor.inner
+            && self.armor.outer >= full.armor.outer
+            && self.disabled.is_empty()
+    }
+}
+
+/// the structure points a design repairs per tick, with its working modules
+pub fn repair_rate(design: &ShipDesign, health: &ShipHealth) -> f32 {
+    design
+        .modules
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !health.is_disabled(*i))
+        .flat_map(|(_, (_, module))| module.effects.iter())
+        .map(|effect| match effect {
+            ModuleEffect::Repair(value) => *value,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+//////////////////////// System ////////////////////////
+
+/// a system to repair the damaged ships in fleets, see the module
+/// documentation.
+///
+/// schedule requirement:
+/// - should run after `system_ship_destruction`
+pub fn system_ship_repair(
+    mut commands: Commands,
+    engine: Res<RapierCollisionEngine>,
+    fleets: Query<(Entity, &FleetOwner, &SpaceLayer, &FleetShips), With<FleetMarker>>,
+    mut ships: Query<(&ShipStats, Option<&ShipDesign>, &mut ShipHealth)>,
+) {
+    // the owners of the fleets in the hill sphere of each solar system
+    let mut systems: HashMap<Entity, Vec<(Entity, Oid)>> = HashMap::new();
+    for (a, b) in engine.intersections() {
+        let (system, fleet) = match (a.kind, b.kind) {
+            (ColliderKind::SolarSystem, ColliderKind::Fleet) => (a.entity, b.entity),
+            (ColliderKind::Fleet, ColliderKind::SolarSystem) => (b.entity, a.entity),
+            _ => continue,
+        };
+        if let Ok((_, owner, SpaceLayer::Normal, _)) = fleets.get(fleet) {
+            systems.entry(system).or_default().push((fleet, owner.0));
+        }
+    }
+    let docked: HashSet<Entity> = systems
+        .values()
+        .filter(|fleets| fleets.iter().all(|(_, owner)| *owner == fleets[0].1))
+        .flat_map(|fleets| fleets.iter().map(|(fleet, _)| *fleet))
+        .collect();
+
+    for (fleet, _, _, members) in fleets.iter() {
+        let dock = match docked.contains(&fleet) {
+            true => DOCK_REPAIR_RATE,
+            false => 0.0,
+        };
+        for ship in members.iter() {
+            let Ok((stats, design, mut health)) = ships.get_mut(ship) else {
+                continue;
+            };
+            let full = ShipHealth::full(stats);
+            let rate = dock + design.map_or(0.0, |design| repair_rate(design, &health));
+            if rate > 0.0 {
+                health.repair(rate, &full);
+            }
+            if health.is_full(&full) {
+                commands.entity(ship).remove::<ShipHealth>();
+            }
+        }
+    }
+}
+
+/// a system to destroy the ships whose integrity is depleted.
+///
+/// schedule requirement:
+/// - should run after `system_combat`
+pub fn system_ship_destruction(world: &mut World) {
+    let mut query = world.query::<(Entity, &ShipHealth)>();
+    let destroyed: Vec<Entity> = query
+        .iter(world)
+        .filter(|(_, health)| health.is_destroyed())
+        .map(|(ship, _)| ship)
+        .collect();
+    for ship in destroyed {
+        destroy_ship(world, ship);
+    }
+}
+
+//////////////////////// Operation ////////////////////////
+
+/// despawn a ship, remove its collider and its `Oid` from the `OidTable`,
+/// and remove it from its fleet. the fleet is disbanded if no ship is left.
+pub fn destroy_ship(world: &mut World, ship: Entity) {
+    let mut fleets = world.query_filtered::<(Entity, &FleetShips), With<FleetMarker>>();
+    let fleet = fleets
+        .iter(world)
+        .find(|(_, members)| members.contains(ship))
+        .map(|(fleet, _)| fleet);
+    if let Some(fleet) = fleet {
+        let mut members = world.get_mut::<FleetShips>(fleet).unwrap();
+        members.remove(ship);
+        if members.is_empty() {
+            info!("[fleet] fleet {:?} lost all ships", world.get::<Oid>(fleet));
+            disband_fleet(world, fleet);
+        }
+    }
+
+    if let Some(collider) = world.get::<RapierCollider>(ship).copied() {
+        if let Some(mut engine) = world.get_resource_mut::<RapierCollisionEngine>() {
+            engine.remove(collider);
+        }
+    }
+    if let Some(oid) = world.get::<Oid>(ship).copied() {
+        if let Some(mut table) = world.get_resource_mut::<OidTable>() {
+            table.remove(&oid);
+        }
+    }
+    world.despawn(ship);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::catalog::ShipCatalog;
+    use crate::fleet::fleet::{spawn_fleet, system_fleet_stats, FleetStats};
+    use crate::fleet::module::{ModuleKind, ShipModule};
+    use crate::fleet::ship::{ShipClass, ShipTemplate, SlotKind};
+    use crate::fleet::stats::system_ship_stats;
+    use crate::map::rapier_collider::system_collision_step;
+    use crate::map::rapier_collider::{ProximityStarted, ProximityStopped};
+    use bevy::ecs::schedule::Schedule;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256StarStar;
+
+    fn stats() -> ShipStats {
+        ShipStats {
+            integrity: 100.0,
+            armor: ArmorLayers {
+                outer: 30.0,
+                inner: 20.0,
+            },
+            ..default()
+        }
+    }
+
+    fn module(kind: ModuleKind, effects: Vec<ModuleEffect>) -> ShipModule {
+        ShipModule {
+            name: format!("{:?}", kind),
+            kind,
+            mass: 10.0,
+            power: 0.0,
+            cost: 0,
+            effects,
+        }
+    }
+
+    fn design(repair: f32) -> ShipDesign {
+        let template = ShipTemplate::new("tug", ShipClass::Special, 100.0, 100.0);
+        ShipDesign::new("tug", template)
+            .with_module(
+                SlotKind::Engine,
+                module(
+                    ModuleKind::Engine,
+                    vec![ModuleEffect::Thrust(2.0), ModuleEffect::HyperDrive(50.0)],
+                ),
+            )
+            .with_module(
+                SlotKind::General,
+                module(ModuleKind::Engineering, vec![ModuleEffect::Repair(repair)]),
+            )
+    }
+
+    #[test]
+    fn armor_layers_deplete_in_order() {
+        let mut health = ShipHealth::full(&stats());
+        assert_eq!(health.total(), 150.0);
+
+        assert_eq!(health.apply_damage(25.0), 0.0);
+        assert_eq!(health.armor.outer, 5.0);
+        assert_eq!(health.armor.inner, 20.0);
+
+        assert_eq!(health.apply_damage(15.0), 0.0);
+        assert_eq!(health.armor.outer, 0.0);
+        assert_eq!(health.armor.inner, 10.0);
+
+        assert_eq!(health.apply_damage(40.0), 30.0);
+        assert_eq!(health.armor.inner, 0.0);
+        assert_eq!(health.integrity, 70.0);
+        assert!(!health.is_destroyed());
+
+        assert_eq!(health.apply_damage(1000.0), 70.0);
+        assert_eq!(health.integrity, 0.0);
+        assert!(health.is_destroyed());
+        assert_eq!(health.apply_damage(-5.0), 0.0);
+    }
+
+    #[test]
+    fn module_damage_and_repair() {
+        let full = ShipHealth::full(&stats());
+        let mut health = full.clone();
+        let mut rng = Xoshiro256StarStar::seed_from_u64(3);
+        assert_eq!(health.damage_modules(2, 0.0, 100.0, &mut rng), None);
+
+        // a hit taking half of the integrity always disables a module
+        health.apply_damage(100.0);
+        let first = health.damage_modules(2, 50.0, 100.0, &mut rng).unwrap();
+        let second = health.damage_modules(2, 50.0, 100.0, &mut rng).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(health.disabled, vec![0, 1]);
+        assert_eq!(health.damage_modules(2, 50.0, 100.0, &mut rng), None);
+
+        // the structure first, then the inner and the outer armor
+        health.repair(40.0, &full);
+        assert_eq!((health.integrity, health.armor.inner), (90.0, 0.0));
+        assert_eq!(health.disabled.len(), 2);
+        health.repair(25.0, &full);
+        assert_eq!(health.integrity, 100.0);
+        assert_eq!(health.armor.inner, 15.0);
+        assert_eq!(health.disabled, vec![1]);
+        health.repair(100.0, &full);
+        assert_eq!(health.armor.outer, 30.0);
+        assert!(health.is_full(&full));
+
+        // no repair once destroyed
+        health.apply_damage(1000.0);
+        health.repair(100.0, &full);
+        assert!(health.is_destroyed());
+    }
+
+    #[test]
+    fn disabled_modules_change_stats() {
+        let design = design(1.0);
+        let rules = ShipClass::Special.rules();
+        let stats = ShipStats::from_design(&design, rules);
+        assert!(stats.acceleration > 0.0 && stats.hyper_speed.is_some());
+
+        let damaged = ShipStats::from_damaged_design(&design, rules, &[0]);
+        assert_eq!(damaged.acceleration, 0.0);
+        assert_eq!(damaged.hyper_speed, None);
+        assert_eq!(damaged.mass, stats.mass);
+        assert_eq!(damaged.integrity, stats.integrity);
+
+        // a disabled module neither outputs nor draws power
+        let mut powered = design.clone();
+        powered.modules[0].1.power = -3.0;
+        powered.modules[1].1.power = 5.0;
+        let surplus = |disabled: &[usize]| {
+            ShipStats::from_damaged_design(&powered, rules, disabled).power_surplus
+        };
+        assert_eq!(surplus(&[]), 2.0);
+        assert_eq!(surplus(&[0]), 5.0);
+        assert_eq!(surplus(&[1]), -3.0);
+
+        let health = ShipHealth {
+            disabled: vec![1],
+            ..default()
+        };
+        assert_eq!(repair_rate(&design, &ShipHealth::default()), 1.0);
+        assert_eq!(repair_rate(&design, &health), 0.0);
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<OidTable>();
+        world.init_resource::<ShipCatalog>();
+        world.init_resource::<Events<ProximityStarted>>();
+        world.init_resource::<Events<ProximityStopped>>();
+        world
+    }
+
+    fn ship(world: &mut World, repair: f32) -> Entity {
+        let oid = Oid::v4();
+        let ship = world.spawn((oid, design(repair))).id();
+        world.resource_mut::<OidTable>().insert(oid, ship);
+        ship
+    }
+
+    #[test]
+    fn destruction_cleans_up() {
+        let mut world = world();
+        let a = ship(&mut world, 0.0);
+        let b = ship(&mut world, 0.0);
+        let owner = Oid::v4();
+        let fleet = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            vec![a, b],
+        );
+        let fleet_oid = *world.get::<Oid>(fleet).unwrap();
+        let ship_oid = *world.get::<Oid>(a).unwrap();
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 1);
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_ship_stats);
+        schedule.add_system(system_fleet_stats.after(system_ship_stats));
+        schedule.add_system(system_ship_destruction.after(system_fleet_stats));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<FleetStats>(fleet).unwrap().ships, 2);
+
+        world.entity_mut(a).insert(ShipHealth::default());
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert!(world.get_entity(a).is_none());
+        assert_eq!(world.resource::<OidTable>().query(&ship_oid), None);
+        assert_eq!(world.get::<FleetShips>(fleet).unwrap().len(), 1);
+        assert_eq!(world.get::<FleetStats>(fleet).unwrap().ships, 1);
+
+        // the last ship takes the fleet with it
+        destroy_ship(&mut world, b);
+        assert!(world.get_entity(b).is_none());
+        assert!(world.get_entity(fleet).is_none());
+        assert_eq!(world.resource::<OidTable>().query(&fleet_oid), None);
+        assert!(world.resource::<RapierCollisionEngine>().is_empty());
+    }
+
+    #[test]
+    fn repair_with_engineering_and_at_friendly_system() {
+        let mut world = world();
+        let system = world.spawn_empty().id();
+        let collider = world
+            .resource_mut::<RapierCollisionEngine>()
+            .spawn_solar_system(system, Vec3::ZERO, 10.0);
+        world.entity_mut(system).insert(collider);
+
+        let owner = Oid::v4();
+        let spawn = |world: &mut World, owner: Oid, position: Vec3, repair: f32| {
+            let ship = ship(world, repair);
+            spawn_fleet(world, owner, position, SpaceLayer::Normal, vec![ship]);
+            ship
+        };
+        let engineer = spawn(&mut world, owner, Vec3::new(100.0, 0.0, 0.0), 1.0);
+        let docked = spawn(&mut world, owner, Vec3::ZERO, 0.0);
+        let stranded = spawn(&mut world, owner, Vec3::new(-100.0, 0.0, 0.0), 0.0);
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_ship_stats);
+        schedule.add_system(system_collision_step);
+        schedule.add_system(system_ship_repair.after(system_collision_step));
+        schedule.run(&mut world);
+
+        // the engines of every ship are disabled
+        for ship in [engineer, docked, stranded] {
+            let stats = *world.get::<ShipStats>(ship).unwrap();
+            let mut health = ShipHealth::full(&stats);
+            health.apply_damage(stats.armor.outer + stats.armor.inner + 10.0);
+            health.disabled = vec![0];
+            world.entity_mut(ship).insert(health);
+        }
+        schedule.run(&mut world);
+        assert_eq!(world.get::<ShipStats>(docked).unwrap().acceleration, 0.0);
+
+        let integrity = |world: &World, ship| world.get::<ShipHealth>(ship).map(|h| h.integrity);
+        assert_eq!(integrity(&world, stranded), Some(90.0));
+        assert_eq!(integrity(&world, engineer), Some(91.0));
+        assert_eq!(integrity(&world, docked), Some(90.0 + DOCK_REPAIR_RATE));
+
+        for _ in 0..4 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(integrity(&world, stranded), Some(90.0));
+        assert_eq!(integrity(&world, engineer), Some(95.0));
+        // the structure and the engine are repaired, the design has no armor
+        assert_eq!(integrity(&world, docked), None);
+        schedule.run(&mut world);
+        let stats = *world.get::<ShipStats>(docked).unwrap();
+        assert!(stats.acceleration > 0.0);
+
+        // a hostile fleet contests the system
+        spawn(&mut world, Oid::v4(), Vec3::ONE, 0.0);
+        let mut health = ShipHealth::full(&stats);
+        health.integrity = 50.0;
+        world.entity_mut(docked).insert(health);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(integrity(&world, docked), Some(50.0));
+
+        // fully repaired ships lose the damage state
+        for _ in 0..100 {
+            schedule.run(&mut world);
+        }
+        assert!(world.get::<ShipHealth>(engineer).is_none());
+    }
+}
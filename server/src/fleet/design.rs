@@ -0,0 +1,293 @@
+use core::fmt;
+
+use bevy::prelude::Component;
+use serde::{Deserialize, Serialize};
+
+use super::class::ClassRules;
+use super::module::{ModuleEffect, ShipModule};
+use super::ship::{ShipClass, ShipTemplate, SlotKind};
+
+/// the error when a ship design is invalid
+#[derive(Debug, Clone, PartialEq)]
+pub enum DesignError {
+    /// the template has more slots of the kind than the class allows
+    SlotLimit {
+        slot: SlotKind,
+        limit: u32,
+        slots: u32,
+    },
+    /// the class can't fit the kind of the module, e.g. weapons on civilian
+    /// hulls
+    ModuleNotAllowed { module: String, class: ShipClass },
+    /// the module has a hyper drive, but the class can't enter hyper space
+    HyperDriveNotAllowed { module: String, class: ShipClass },
+    /// the module can't be put into the slot
+    WrongSlot { module: String, slot: SlotKind },
+    /// more modules than slots of the kind in the template
+    Overfilled {
+        slot: SlotKind,
+        capacity: u32,
+        count: u32,
+    },
+    /// the modules draw more power than they output, by the amount
+    PowerDeficit(f32),
+}
+
+impl fmt::Display for DesignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SlotLimit { slot, limit, slots } => {
+                write!(f, "{} {:?} slots exceed the limit {}", slots, slot, limit)
+            }
+            Self::ModuleNotAllowed { module, class } => {
+                write!(f, "module `{}` is not allowed on {:?}", module, class)
+            }
+            Self::HyperDriveNotAllowed { module, class } => write!(
+                f,
+                "module `{}` has a hyper drive, but {:?} can't enter hyper space",
+                module, class
+            ),
+            Self::WrongSlot { module, slot } => {
+                write!(f, "module `{}` can't be put into {:?} slot", module, slot)
+            }
+            Self::Overfilled {
+                slot,
+                capacity,
+                count,
+            } => write!(f, "{} modules in {} {:?} slots", count, capacity, slot),
+            Self::PowerDeficit(deficit) => write!(f, "power deficit of {}", deficit),
+        }
+    }
+}
+
+impl std::error::Error for DesignError {}
+
+/// a ship design fills the slots of a template with modules, the statistics of
+/// a ship entity are derived from its design, see `ShipStats`.
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipDesign {
+    pub name: String,
+    pub template: ShipTemplate,
+    /// the modules and the slots they are put into
+    pub modules: Vec<(SlotKind, ShipModule)>,
+}
+
+impl ShipDesign {
+    /// a design without any module
+    pub fn new(name: impl Into<String>, template: ShipTemplate) -> Self {
+        Self {
+            name: name.into(),
+            template,
+            modules: Vec::new(),
+        }
+    }
+
+    /// put a module into a slot of `slot`, see `validate`
+    pub fn with_module(mut self, slot: SlotKind, module: ShipModule) -> Self {
+        self.modules.push((slot, module));
+        self
+    }
+
+    /// the mass of the template scaled by the hull size, and all modules.
+    /// `rules` are the rules of the template class.
+    pub fn mass(&self, rules: &ClassRules) -> f32 {
+        let hull = self.template.base_mass() * rules.hull_size;
+        hull + self.modules.iter().map(|(_, m)| m.mass).sum::<f32>()
+    }
+
+    /// the power output minus the power draw of all modules
+    pub fn power_budget(&self) -> f32 {
+        self.modules.iter().map(|(_, m)| m.power).sum()
+    }
+
+    /// the cost of all modules
+    pub fn cost(&self) -> u32 {
+        self.modules.iter().map(|(_, m)| m.cost).sum()
+    }
+
+    /// test if the design can be built under `rules`, the rules of the
+    /// template class. the first problem found is returned.
+    pub fn validate(&self, rules: &ClassRules) -> Result<(), DesignError> {
+        let class = self.template.class();
+        for slot in SlotKind::ALL {
+            let (limit, slots) = (rules.max_slots(slot), self.template.slots(slot));
+            if slots > limit {
+                return Err(DesignError::SlotLimit { slot, limit, slots });
+            }
+        }
+
+        for (_, module) in self.modules.iter() {
+            if !rules.allows(module.kind) {
+                return Err(DesignError::ModuleNotAllowed {
+                    module: module.name.clone(),
+                    class,
+                });
+            }
+            let hyper_drive = module
+                .effects
+                .iter()
+                .any(|effect| matches!(effect, ModuleEffect::HyperDrive(_)));
+            if hyper_drive && !rules.hyperspace {
+                return Err(DesignError::HyperDriveNotAllowed {
+                    module: module.name.clone(),
+                    class,
+                });
+            }
+        }
+
+        if let Some((slot, module)) = self
+            .modules
+            .iter()
+            .find(|(slot, module)| !module.kind.fits(*slot))
+        {
+            return Err(DesignError::WrongSlot {
+                module: module.name.clone(),
+                slot: *slot,
+            });
+        }
+
+        for slot in SlotKind::ALL {
+            let count = self.modules.iter().filter(|(s, _)| *s == slot).count() as u32;
+            let capacity = self.template.slots(slot);
+            if count > capacity {
+                return Err(DesignError::Overfilled {
+                    slot,
+                    capacity,
+                    count,
+                });
+            }
+        }
+
+        let budget = self.power_budget();
+        if budget < 0.0 {
+            return Err(DesignError::PowerDeficit(-budget));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::module::ModuleKind;
+
+    fn rules(design: &ShipDesign) -> &'static ClassRules {
+        design.template.class().rules()
+    }
+
+    fn module(name: &str, kind: ModuleKind, power: f32) -> ShipModule {
+        ShipModule {
+            name: name.to_string(),
+            kind,
+            mass: 10.0,
+            power,
+            cost: 100,
+            effects: vec![ModuleEffect::Thrust(1.0)],
+        }
+    }
+
+    fn design() -> ShipDesign {
+        let template = ShipTemplate::new("hull", ShipClass::Special, 100.0, 50.0)
+            .with_slots(SlotKind::Engine, 1)
+            .with_slots(SlotKind::Power, 1)
+            .with_slots(SlotKind::Weapon, 2);
+        ShipDesign::new("corvette", template)
+            .with_module(SlotKind::Power, module("reactor", ModuleKind::Power, 10.0))
+            .with_module(SlotKind::Engine, module("ion", ModuleKind::Engine, -4.0))
+            .with_module(SlotKind::Weapon, module("laser", ModuleKind::Weapon, -3.0))
+    }
+
+    #[test]
+    fn valid_design() {
+        let design = design();
+        assert_eq!(design.validate(rules(&design)), Ok(()));
+        assert_eq!(design.mass(rules(&design)), 130.0);
+        assert_eq!(design.power_budget(), 3.0);
+        assert_eq!(design.cost(), 300);
+    }
+
+    #[test]
+    fn invalid_designs() {
+        let laser = || module("laser", ModuleKind::Weapon, -3.0);
+
+        let wrong = design().with_module(SlotKind::General, laser());
+        assert_eq!(
+            wrong.validate(rules(&wrong)),
+            Err(DesignError::WrongSlot {
+                module: "laser".to_string(),
+                slot: SlotKind::General
+            })
+        );
+
+        let overfilled = design()
+            .with_module(SlotKind::Weapon, laser())
+            .with_module(SlotKind::Weapon, laser());
+        assert_eq!(
+            overfilled.validate(rules(&overfilled)),
+            Err(DesignError::Overfilled {
+                slot: SlotKind::Weapon,
+                capacity: 2,
+                count: 3
+            })
+        );
+
+        let mut deficit = design()
+            .with_module(SlotKind::Weapon, laser())
+            .with_module(SlotKind::Weapon, laser());
+        deficit.template = deficit.template.with_slots(SlotKind::Weapon, 3);
+        assert_eq!(
+            deficit.validate(rules(&deficit)),
+            Err(DesignError::PowerDeficit(3.0))
+        );
+    }
+
+    #[test]
+    fn class_rules() {
+        let laser = || module("laser", ModuleKind::Weapon, -3.0);
+        let template = |class| {
+            ShipTemplate::new("hull", class, 100.0, 50.0)
+                .with_slots(SlotKind::Power, 1)
+                .with_slots(SlotKind::Weapon, 1)
+        };
+        let reactor = module("reactor", ModuleKind::Power, 10.0);
+
+        let armed = ShipDesign::new("escort", template(ShipClass::Destroyer))
+            .with_module(SlotKind::Power, reactor.clone())
+            .with_module(SlotKind::Weapon, laser());
+        assert_eq!(armed.validate(rules(&armed)), Ok(()));
+        assert_eq!(armed.mass(rules(&armed)), 170.0);
+        assert!(ShipClass::Destroyer.rules().armed);
+
+        // civilian transports can't mount weapons
+        let transport = ShipDesign::new("transport", template(ShipClass::LargeCarrier));
+        assert!(matches!(
+            transport.validate(rules(&transport)),
+            Err(DesignError::SlotLimit {
+                slot: SlotKind::Weapon,
+                ..
+            })
+        ));
+        let mut transport = transport.with_module(SlotKind::Weapon, laser());
+        transport.template = transport.template.with_slots(SlotKind::Weapon, 0);
+        assert_eq!(
+            transport.validate(rules(&transport)),
+            Err(DesignError::ModuleNotAllowed {
+                module: "laser".to_string(),
+                class: ShipClass::LargeCarrier
+            })
+        );
+        assert!(!ShipClass::LargeCarrier.rules().armed);
+
+        let mut drive = module("drive", ModuleKind::Engine, 0.0);
+        drive.effects = vec![ModuleEffect::HyperDrive(1.0)];
+        let frigate = ShipDesign::new(
+            "frigate",
+            template(ShipClass::Frigate).with_slots(SlotKind::Engine, 1),
+        )
+        .with_module(SlotKind::Engine, drive);
+        assert!(matches!(
+            frigate.validate(rules(&frigate)),
+            Err(DesignError::HyperDriveNotAllowed { .. })
+        ));
+    }
+}
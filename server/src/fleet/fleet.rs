@@ -1 +1,756 @@
+//! the fleet, a group of ships moving together in L3 map.
+//!
+//! a fleet entity has the following components:
+//! - Oid: the stable id of the fleet
+//! - FleetOwner: the `Oid` of the faction owns the fleet
+//! - Transform: the position of the fleet
+//! - SpaceLayer: the layer of the map the fleet is in
+//! - FleetRadius: the radius of the fleet collider
+//! - FleetShips: list of Entity for ships
+//! - FleetStats: the aggregate statistics of the ships
+//! - FleetMovement: the velocity and the destination of the fleet
+//! - OrderQueue: the orders of the fleet
+//! - RapierCollider: the handle for rapier physics engine
+//! - FleetMarker: a marker component to indicate this is a fleet
+//!
+//! the ships are entities with `Oid`, `ShipDesign`, `ShipStats` and
+//! `ShipHealth` if damaged. use `spawn_fleet`, `transfer_ships`,
+//! `merge_fleets` and `split_fleet` to change the composition, they keep the
+//! colliders and the `OidTable` consistent. see `destroy_ship` for the loss of
+//! a ship.
+
+use core::fmt;
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::catalog::ShipCatalog;
+use super::combat::CombatStance;
+use super::damage::ShipHealth;
+use super::design::ShipDesign;
+use super::movement::FleetMovement;
+use super::order::OrderQueue;
+use super::stats::ShipStats;
+use crate::map::rapier_collider::{RapierCollider, RapierCollisionEngine};
+use crate::map::SpaceLayer;
+use crate::utils::oid::{Oid, OidTable};
+
+/// the radius of the collider of a new fleet
+pub const DEFAULT_FLEET_RADIUS: f32 = 1.0;
+
+//////////////////////// Component ////////////////////////
+
+/// a marker for fleet
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FleetMarker;
+
+/// the radius of the fleet collider in L3 map
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd)]
+pub struct FleetRadius(f32);
+
+impl FleetRadius {
+    pub fn new(radius: f32) -> Self {
+        Self(radius)
+    }
+}
+
+impl From<FleetRadius> for f32 {
+    fn from(radius: FleetRadius) -> f32 {
+        radius.0
+    }
+}
+
+/// the `Oid` of the faction owns the fleet
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct FleetOwner(pub Oid);
+
+/// the ships in the fleet, in the order they joined.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FleetShips(Vec<Entity>);
+
+impl FleetShips {
+    pub fn new(ships: Vec<Entity>) -> Self {
+        Self(ships)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, ship: Entity) -> bool {
+        self.0.contains(&ship)
+    }
+
+    /// remove a ship, return `false` if it is not in the fleet
+    pub fn remove(&mut self, ship: Entity) -> bool {
+        let len = self.0.len();
+        self.0.retain(|s| *s != ship);
+        self.0.len() != len
+    }
+}
+
+/// the aggregate statistics of the ships in a fleet, the fleet moves at the
+/// speed of its slowest ship.
+///
+/// CON data, reconstructed from the `ShipStats` of ships
+#[derive(Component, Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct FleetStats {
+    /// the number of ships
+    pub ships: usize,
+    /// the lowest acceleration, in map unit per tick²
+    pub acceleration: f32,
+    /// the lowest maximum speed in normal space
+    pub max_speed: f32,
+    /// the lowest speed in hyper space. `None` if any ship can't enter hyper
+    /// space, or the fleet is empty.
+    pub hyper_speed: Option<f32>,
+    /// the sum of weapon dps
+    pub firepower: f32,
+    /// the sum of cargo capacity
+    pub cargo: f32,
+    /// the shortest jump range
+    pub jump_range: f32,
+}
+
+impl FleetStats {
+    /// aggregate the statistics of ships
+    pub fn aggregate<'a>(ships: impl IntoIterator<Item = &'a ShipStats>) -> Self {
+        let mut ships = ships.into_iter();
+        let Some(first) = ships.next() else {
+            return Self::default();
+        };
+
+        let mut stats = Self {
+            ships: 1,
+            acceleration: first.acceleration,
+            max_speed: first.max_speed,
+            hyper_speed: first.hyper_speed,
+            firepower: first.weapon_dps,
+            cargo: first.cargo,
+            jump_range: first.jump_range,
+        };
+        for ship in ships {
+            stats.ships += 1;
+            stats.acceleration = stats.acceleration.min(ship.acceleration);
+            stats.max_speed = stats.max_speed.min(ship.max_speed);
+            stats.hyper_speed = match (stats.hyper_speed, ship.hyper_speed) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                _ => None,
+            };
+            stats.firepower += ship.weapon_dps;
+            stats.cargo += ship.cargo;
+            stats.jump_range = stats.jump_range.min(ship.jump_range);
+        }
+        stats
+    }
+}
+
+//////////////////////// System ////////////////////////
+
+/// a system to recompute the `FleetStats` of fleets whose ships changed, or
+/// whose ships have new `ShipStats`.
+///
+/// schedule requirement:
+/// - should run after `system_ship_stats`
+pub fn system_fleet_stats(
+    mut fleets: Query<(Ref<FleetShips>, &mut FleetStats)>,
+    ships: Query<Ref<ShipStats>>,
+) {
+    for (members, mut stats) in fleets.iter_mut() {
+        let changed = members.is_changed()
+            || members
+                .iter()
+                .any(|ship| matches!(ships.get(ship), Ok(s) if s.is_changed()));
+        if changed {
+            let aggregate = FleetStats::aggregate(
+                members
+                    .iter()
+                    .filter_map(|ship| ships.get(ship).ok().map(Ref::into_inner)),
+            );
+            stats.set_if_neq(aggregate);
+        }
+    }
+}
+
+//////////////////////// Operation ////////////////////////
+
+/// the reason a fleet operation is rejected, the world is unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FleetError {
+    /// the entity is not a fleet
+    NotAFleet(Entity),
+    /// the source and the target are the same fleet
+    SameFleet(Entity),
+    /// the fleets are owned by different factions
+    DifferentOwner,
+    /// the fleets are in different layers of the map
+    DifferentLayer,
+    /// the colliders of the fleets don't touch
+    TooFar,
+    /// no ship is given
+    NoShip,
+    /// the ship is not in the fleet
+    NotInFleet { ship: Entity, fleet: Entity },
+    /// the operation would leave the fleet without ship, merge it instead
+    WouldBeEmpty(Entity),
+}
+
+impl fmt::Display for FleetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAFleet(e) => write!(f, "{:?} is not a fleet", e),
+            Self::SameFleet(e) => write!(f, "can't transfer ships from {:?} to itself", e),
+            Self::DifferentOwner => write!(f, "fleets are owned by different factions"),
+            Self::DifferentLayer => write!(f, "fleets are in different space layers"),
+            Self::TooFar => write!(f, "fleets are too far from each other"),
+            Self::NoShip => write!(f, "no ship is given"),
+            Self::NotInFleet { ship, fleet } => write!(f, "{:?} is not in fleet {:?}", ship, fleet),
+            Self::WouldBeEmpty(e) => write!(f, "fleet {:?} would be left without ship", e),
+        }
+    }
+}
+
+impl std::error::Error for FleetError {}
+
+/// spawn a fleet of `ships` owned by `owner`, with a new `Oid`, a collider
+/// and an entry in the `OidTable` if the resources exist.
+///
+/// the ships should not be in another fleet.
+pub fn spawn_fleet(
+    world: &mut World,
+    owner: Oid,
+    position: Vec3,
+    layer: SpaceLayer,
+    ships: Vec<Entity>,
+) -> Entity {
+    let oid = Oid::v4();
+    let radius = FleetRadius::new(DEFAULT_FLEET_RADIUS);
+    let stats = aggregate_stats(world, &ships);
+    let fleet = world
+        .spawn((
+            FleetMarker,
+            oid,
+            FleetOwner(owner),
+            Transform::from_translation(position),
+            layer,
+            radius,
+            FleetShips::new(ships),
+            stats,
+            FleetMovement::default(),
+            OrderQueue::default(),
+        ))
+        .id();
+
+    let collider = world
+        .get_resource_mut::<RapierCollisionEngine>()
+        .map(|mut engine| engine.spawn_fleet(fleet, position, radius.into(), layer));
+    if let Some(collider) = collider {
+        world.entity_mut(fleet).insert(collider);
+    }
+    if let Some(mut table) = world.get_resource_mut::<OidTable>() {
+        table.insert(oid, fleet);
+    }
+    fleet
+}
+
+/// move `ships` from fleet `from` to fleet `to`. the fleets must have the same
+/// owner, be in the same layer and touch each other. `from` is disbanded if
+/// no ship is left.
+pub fn transfer_ships(
+    world: &mut World,
+    from: Entity,
+    to: Entity,
+    ships: &[Entity],
+) -> Result<(), FleetError> {
+    check_transfer(world, from, to)?;
+    check_ships(world, from, ships)?;
+
+    move_ships(world, from, to, ships);
+    if world.get::<FleetShips>(from).map_or(0, FleetShips::len) == 0 {
+        disband_fleet(world, from);
+    }
+    Ok(())
+}
+
+/// move all ships of fleet `from` into fleet `into`, and disband `from`. see
+/// `transfer_ships` for the requirements.
+pub fn merge_fleets(world: &mut World, into: Entity, from: Entity) -> Result<(), FleetError> {
+    let ships: Vec<Entity> = world
+        .get::<FleetShips>(from)
+        .ok_or(FleetError::NotAFleet(from))?
+        .iter()
+        .collect();
+    if ships.is_empty() {
+        // an empty fleet left by a bug, nothing to move
+        check_transfer(world, from, into)?;
+        disband_fleet(world, from);
+        return Ok(());
+    }
+    transfer_ships(world, from, into, &ships)
+}
+
+/// test if ships can be moved from fleet `from` to fleet `to`
+fn check_transfer(world: &World, from: Entity, to: Entity) -> Result<(), FleetError> {
+    if from == to {
+        return Err(FleetError::SameFleet(from));
+    }
+    let source = fleet_info(world, from)?;
+    let target = fleet_info(world, to)?;
+    if source.owner != target.owner {
+        return Err(FleetError::DifferentOwner);
+    }
+    if source.layer != target.layer {
+        return Err(FleetError::DifferentLayer);
+    }
+    if source.position.distance(target.position) > source.radius + target.radius {
+        return Err(FleetError::TooFar);
+    }
+    Ok(())
+}
+
+/// move `ships` out of `fleet` into a new fleet at the same position, return
+/// the new fleet. at least one ship must be left in `fleet`.
+pub fn split_fleet(
+    world: &mut World,
+    fleet: Entity,
+    ships: &[Entity],
+) -> Result<Entity, FleetError> {
+    let info = fleet_info(world, fleet)?;
+    check_ships(world, fleet, ships)?;
+    if world.get::<FleetShips>(fleet).map_or(0, FleetShips::len) <= ships.len() {
+        return Err(FleetError::WouldBeEmpty(fleet));
+    }
+
+    let split = spawn_fleet(world, info.owner, info.position, info.layer, Vec::new());
+    move_ships(world, fleet, split, ships);
+    Ok(split)
+}
+
+/// despawn a fleet, remove its collider and its `Oid` from the `OidTable`.
+/// the ships are not despawned.
+pub fn disband_fleet(world: &mut World, fleet: Entity) {
+    if let Some(collider) = world.get::<RapierCollider>(fleet).copied() {
+        if let Some(mut engine) = world.get_resource_mut::<RapierCollisionEngine>() {
+            engine.remove(collider);
+        }
+    }
+    if let Some(oid) = world.get::<Oid>(fleet).copied() {
+        if let Some(mut table) = world.get_resource_mut::<OidTable>() {
+            table.remove(&oid);
+        }
+    }
+    world.despawn(fleet);
+}
+
+struct FleetInfo {
+    owner: Oid,
+    layer: SpaceLayer,
+    position: Vec3,
+    radius: f32,
+}
+
+fn fleet_info(world: &World, fleet: Entity) -> Result<FleetInfo, FleetError> {
+    let entity = world
+        .get_entity(fleet)
+        .ok_or(FleetError::NotAFleet(fleet))?;
+    if !entity.contains::<FleetMarker>() {
+        return Err(FleetError::NotAFleet(fleet));
+    }
+    match (
+        entity.get::<FleetOwner>(),
+        entity.get::<SpaceLayer>(),
+        entity.get::<Transform>(),
+        entity.get::<FleetRadius>(),
+    ) {
+        (Some(owner), Some(layer), Some(transform), Some(radius)) => Ok(FleetInfo {
+            owner: owner.0,
+            layer: *layer,
+            position: transform.translation,
+            radius: (*radius).into(),
+        }),
+        _ => Err(FleetError::NotAFleet(fleet)),
+    }
+}
+
+fn check_ships(world: &World, fleet: Entity, ships: &[Entity]) -> Result<(), FleetError> {
+    if ships.is_empty() {
+        return Err(FleetError::NoShip);
+    }
+    let members = world
+        .get::<FleetShips>(fleet)
+        .ok_or(FleetError::NotAFleet(fleet))?;
+    match ships.iter().find(|ship| !members.contains(**ship)) {
+        Some(&ship) => Err(FleetError::NotInFleet { ship, fleet }),
+        None => Ok(()),
+    }
+}
+
+/// move the ships without any check, and update the stats of both fleets
+fn move_ships(world: &mut World, from: Entity, to: Entity, ships: &[Entity]) {
+    if let Some(mut members) = world.get_mut::<FleetShips>(from) {
+        members.0.retain(|ship| !ships.contains(ship));
+    }
+    if let Some(mut members) = world.get_mut::<FleetShips>(to) {
+        for ship in ships {
+            if !members.0.contains(ship) {
+                members.0.push(*ship);
+            }
+        }
+    }
+    for fleet in [from, to] {
+        let members = world.get::<FleetShips>(fleet).map(|m| m.0.clone());
+        if let Some(members) = members {
+            let stats = aggregate_stats(world, &members);
+            world.entity_mut(fleet).insert(stats);
+        }
+    }
+}
+
+fn aggregate_stats(world: &World, ships: &[Entity]) -> FleetStats {
+    FleetStats::aggregate(
+        ships
+            .iter()
+            .filter_map(|ship| world.get::<ShipStats>(*ship)),
+    )
+}
+
+//////////////////////// Serde ////////////////////////
+
+/// the object-oriented representation of a ship, used for serialization.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipSerde {
+    pub id: Oid,
+    pub design: ShipDesign,
+    /// `None` if the ship is undamaged
+    pub health: Option<ShipHealth>,
+}
+
+/// the object-oriented representation of a fleet and its ships, used for
+/// serialization.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FleetSerde {
+    pub id: Oid,
+    pub owner: FleetOwner,
+    pub transform: Transform,
+    pub layer: SpaceLayer,
+    pub radius: FleetRadius,
+    pub movement: FleetMovement,
+    pub orders: OrderQueue,
+    /// the combat stance, `None` for the default stance
+    pub stance: Option<CombatStance>,
+    /// the ships in the order they joined the fleet
+    pub ships: Vec<ShipSerde>,
+}
+
+impl FleetSerde {
+    /// collect the S/L data of `fleet`, `None` if it's not a fleet.
+    pub fn from_world(world: &World, fleet: Entity) -> Option<Self> {
+        let entity = world.get_entity(fleet)?;
+        if !entity.contains::<FleetMarker>() {
+            return None;
+        }
+        let ships = entity
+            .get::<FleetShips>()
+            .into_iter()
+            .flat_map(FleetShips::iter)
+            .filter_map(|ship| {
+                Some(ShipSerde {
+                    id: *world.get::<Oid>(ship)?,
+                    design: world.get::<ShipDesign>(ship)?.clone(),
+                    health: world.get::<ShipHealth>(ship).cloned(),
+                })
+            })
+            .collect();
+
+        Some(Self {
+            id: *entity.get::<Oid>()?,
+            owner: *entity.get::<FleetOwner>()?,
+            transform: *entity.get::<Transform>()?,
+            layer: *entity.get::<SpaceLayer>()?,
+            radius: *entity.get::<FleetRadius>()?,
+            movement: entity.get::<FleetMovement>().copied().unwrap_or_default(),
+            orders: entity.get::<OrderQueue>().cloned().unwrap_or_default(),
+            stance: entity.get::<CombatStance>().copied(),
+            ships,
+        })
+    }
+
+    /// spawn the fleet and its ships in the L3 map, the ships are inserted
+    /// into `table`. the statistics are reconstructed by
+    /// `rebuild_fleet_stats`, and the collider by `rebuild_collision_world`.
+    pub fn spawn(&self, commands: &mut Commands, table: &mut OidTable) -> Entity {
+        let ships = self
+            .ships
+            .iter()
+            .map(|ship| {
+                let mut entity = commands.spawn((ship.id, ship.design.clone()));
+                if let Some(health) = ship.health.as_ref() {
+                    entity.insert(health.clone());
+                }
+                let entity = entity.id();
+                table.insert(ship.id, entity);
+                entity
+            })
+            .collect();
+
+        let mut fleet = commands.spawn((
+            FleetMarker,
+            self.id,
+            self.owner,
+            self.transform,
+            self.layer,
+            self.radius,
+            FleetShips::new(ships),
+            FleetStats::default(),
+            self.movement,
+            self.orders.clone(),
+        ));
+        if let Some(stance) = self.stance {
+            fleet.insert(stance);
+        }
+        fleet.id()
+    }
+}
+
+/// recompute the `ShipStats` of all ships with a design and their damage,
+/// then the `FleetStats` of all fleets, e.g. after loading a save. the rules
+/// of the `ShipCatalog` are used if it exists.
+pub fn rebuild_fleet_stats(world: &mut World) {
+    let catalog = world.get_resource::<ShipCatalog>().cloned();
+    let mut designs = world.query::<(Entity, &ShipDesign, Option<&ShipHealth>)>();
+    let ships: Vec<(Entity, ShipStats)> = designs
+        .iter(world)
+        .map(|(entity, design, health)| {
+            let class = design.template.class();
+            let rules = match catalog.as_ref() {
+                Some(catalog) => catalog.rules(class),
+                None => class.rules(),
+            };
+            let disabled = health.map_or(&[][..], |health| &health.disabled[..]);
+            (
+                entity,
+                ShipStats::from_damaged_design(design, rules, disabled),
+            )
+        })
+        .collect();
+    for (entity, stats) in ships {
+        world.entity_mut(entity).insert(stats);
+    }
+
+    let mut fleets = world.query_filtered::<(Entity, &FleetShips), With<FleetMarker>>();
+    let fleets: Vec<(Entity, FleetStats)> = fleets
+        .iter(world)
+        .map(|(entity, ships)| {
+            let ships: Vec<Entity> = ships.iter().collect();
+            (entity, aggregate_stats(world, &ships))
+        })
+        .collect();
+    for (entity, stats) in fleets {
+        world.entity_mut(entity).insert(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ship(world: &mut World, max_speed: f32, hyper_speed: Option<f32>, dps: f32) -> Entity {
+        let stats = ShipStats {
+            max_speed,
+            hyper_speed,
+            weapon_dps: dps,
+            cargo: 10.0,
+            ..default()
+        };
+        world.spawn((Oid::v4(), stats)).id()
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<OidTable>();
+        world
+    }
+
+    #[test]
+    fn aggregate_stats() {
+        let mut world = world();
+        let a = ship(&mut world, 0.5, Some(2.0), 3.0);
+        let b = ship(&mut world, 0.2, Some(1.5), 4.0);
+        let c = ship(&mut world, 0.8, None, 0.0);
+        world.get_mut::<ShipStats>(a).unwrap().jump_range = 50.0;
+        world.get_mut::<ShipStats>(b).unwrap().jump_range = 30.0;
+        let owner = Oid::v4();
+        let fleet = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            vec![a, b],
+        );
+
+        let stats = *world.get::<FleetStats>(fleet).unwrap();
+        assert_eq!(stats.ships, 2);
+        assert_eq!(stats.max_speed, 0.2);
+        assert_eq!(stats.hyper_speed, Some(1.5));
+        assert_eq!(stats.firepower, 7.0);
+        assert_eq!(stats.cargo, 20.0);
+        assert_eq!(stats.jump_range, 30.0);
+        assert_eq!(FleetStats::aggregate([]), FleetStats::default());
+
+        // a ship without hyper drive holds the fleet in normal space
+        world.get_mut::<FleetShips>(fleet).unwrap().0.push(c);
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_fleet_stats);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<FleetStats>(fleet).unwrap().hyper_speed, None);
+
+        world.get_mut::<ShipStats>(b).unwrap().max_speed = 0.1;
+        schedule.run(&mut world);
+        assert_eq!(world.get::<FleetStats>(fleet).unwrap().max_speed, 0.1);
+    }
+
+    #[test]
+    fn merge_split_transfer() {
+        let mut world = world();
+        let owner = Oid::v4();
+        let ships: Vec<Entity> = (0..4)
+            .map(|i| ship(&mut world, 0.1 * (i + 1) as f32, None, 1.0))
+            .collect();
+        let a = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            ships[..3].to_vec(),
+        );
+        let b = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::X,
+            SpaceLayer::Normal,
+            ships[3..].to_vec(),
+        );
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 2);
+
+        // split
+        let c = split_fleet(&mut world, a, &ships[..1]).unwrap();
+        assert_eq!(world.get::<FleetShips>(c).unwrap().0, ships[..1]);
+        assert_eq!(world.get::<FleetStats>(a).unwrap().max_speed, 0.2);
+        assert_eq!(world.get::<FleetStats>(c).unwrap().ships, 1);
+        let oid = *world.get::<Oid>(c).unwrap();
+        assert_eq!(world.resource::<OidTable>().query(&oid), Some(c));
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 3);
+
+        // transfer
+        transfer_ships(&mut world, a, b, &ships[1..2]).unwrap();
+        assert_eq!(world.get::<FleetShips>(a).unwrap().0, ships[2..3]);
+        assert_eq!(world.get::<FleetStats>(b).unwrap().firepower, 2.0);
+
+        // merge
+        let oid = *world.get::<Oid>(a).unwrap();
+        merge_fleets(&mut world, b, a).unwrap();
+        assert!(world.get_entity(a).is_none());
+        assert_eq!(world.get::<FleetStats>(b).unwrap().ships, 3);
+        assert_eq!(world.resource::<OidTable>().query(&oid), None);
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 2);
+
+        // transfer all the ships disbands the fleet
+        transfer_ships(&mut world, c, b, &ships[..1]).unwrap();
+        assert!(world.get_entity(c).is_none());
+        assert_eq!(world.get::<FleetStats>(b).unwrap().ships, 4);
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 1);
+    }
+
+    #[test]
+    fn rejected_operations() {
+        let mut world = world();
+        let owner = Oid::v4();
+        let ships: Vec<Entity> = (0..4).map(|_| ship(&mut world, 0.5, None, 1.0)).collect();
+        let a = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            ships[..2].to_vec(),
+        );
+        let b = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::ZERO,
+            SpaceLayer::Hyper,
+            ships[2..3].to_vec(),
+        );
+        let c = spawn_fleet(
+            &mut world,
+            Oid::v4(),
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            ships[3..].to_vec(),
+        );
+        let d = spawn_fleet(
+            &mut world,
+            owner,
+            Vec3::X * 10.0,
+            SpaceLayer::Normal,
+            Vec::new(),
+        );
+
+        assert_eq!(
+            merge_fleets(&mut world, a, a),
+            Err(FleetError::SameFleet(a))
+        );
+        assert_eq!(
+            merge_fleets(&mut world, a, b),
+            Err(FleetError::DifferentLayer)
+        );
+        assert_eq!(
+            merge_fleets(&mut world, a, c),
+            Err(FleetError::DifferentOwner)
+        );
+        assert_eq!(
+            transfer_ships(&mut world, a, d, &ships[..1]),
+            Err(FleetError::TooFar)
+        );
+        // an empty fleet is checked the same way
+        assert_eq!(
+            merge_fleets(&mut world, d, d),
+            Err(FleetError::SameFleet(d))
+        );
+        assert_eq!(
+            merge_fleets(&mut world, c, d),
+            Err(FleetError::DifferentOwner)
+        );
+        assert_eq!(merge_fleets(&mut world, a, d), Err(FleetError::TooFar));
+        assert_eq!(
+            transfer_ships(&mut world, a, ships[2], &ships[..1]),
+            Err(FleetError::NotAFleet(ships[2]))
+        );
+        assert_eq!(split_fleet(&mut world, a, &[]), Err(FleetError::NoShip));
+        assert_eq!(
+            split_fleet(&mut world, a, &ships[..2]),
+            Err(FleetError::WouldBeEmpty(a))
+        );
+        assert_eq!(
+            split_fleet(&mut world, a, &ships[2..3]),
+            Err(FleetError::NotInFleet {
+                ship: ships[2],
+                fleet: a
+            })
+        );
+
+        // nothing changed
+        assert_eq!(world.get::<FleetShips>(a).unwrap().0, ships[..2]);
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 4);
+    }
+}
@@ -1,2 +1,12 @@
+pub mod catalog;
+pub mod class;
+pub mod combat;
+pub mod damage;
+pub mod design;
 pub mod fleet;
+pub mod module;
+pub mod movement;
+pub mod order;
+pub mod route;
 pub mod ship;
+pub mod stats;
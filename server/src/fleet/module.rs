@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use super::ship::SlotKind;
+
+/// the kind of ship module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ModuleKind {
+    Power,
+    Engine,
+    Command,
+    Armor,
+    Weapon,
+    Science,
+    Engineering,
+    Storage,
+}
+
+impl ModuleKind {
+    /// test if a module of this kind can be put into a slot of `slot`
+    pub fn fits(self, slot: SlotKind) -> bool {
+        match self {
+            Self::Power => slot == SlotKind::Power,
+            Self::Engine => slot == SlotKind::Engine,
+            Self::Armor => matches!(slot, SlotKind::ArmorOuter | SlotKind::ArmorInner),
+            Self::Weapon => slot == SlotKind::Weapon,
+            Self::Command | Self::Science | Self::Engineering | Self::Storage => {
+                slot == SlotKind::General
+            }
+        }
+    }
+}
+
+/// an effect of a ship module on the ship, the values of the same effect are
+/// summed over all modules, except `Sensor` which takes the maximum. see
+/// `ShipStats`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ModuleEffect {
+    /// the thrust of engines, the acceleration is thrust / mass
+    Thrust(f32),
+    /// the rating of hyper drive, a ship without hyper drive can't enter
+    /// hyper space
+    HyperDrive(f32),
+    /// the armor points of the layer the module is in
+    Armor(f32),
+    /// the damage per second
+    Firepower(f32),
+    /// the detection range
+    Sensor(f32),
+    /// the structure points repaired per tick
+    Repair(f32),
+    /// the capacity of storage
+    Cargo(f32),
+    /// the number of ships can be commanded
+    Command(f32),
+    /// the jump range added by fuel tanks, in map unit
+    Fuel(f32),
+}
+
+/// the definition of a ship module, modules are defined in ship data files,
+/// see `ShipCatalog`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipModule {
+    pub name: String,
+    pub kind: ModuleKind,
+    pub mass: f32,
+    /// the power output if positive, or the power draw if negative
+    pub power: f32,
+    pub cost: u32,
+    #[serde(default)]
+    pub effects: Vec<ModuleEffect>,
+}
@@ -0,0 +1,607 @@
+//! the movement of fleets in L3 map.
+//!
+//! - in normal space, a fleet accelerates toward its target at the
+//!   acceleration of its slowest ship, and brakes to stop at the target. the
+//!   speed never exceeds `FleetStats::max_speed`, which is below light.
+//! - to enter hyper space, the fleet brakes and charges its hyper drives for
+//!   `HYPER_CHARGE_TICKS` ticks. in hyper space, it moves straight to the
+//!   target at its hyper speed, at least the speed of light, and drops back to
+//!   normal space at the target.
+//!
+//! a fleet in hyper space is intercepted by the interdiction sphere of a
+//! fortification. the motion is swept, so the fleet stops at the edge of the
+//! sphere even if it would cross the sphere in one tick, drops to normal space
+//! and forgets its target. a fleet touching an interdiction sphere can't enter
+//! hyper space at all, it is intercepted where it is. an intercepted fleet
+//! fails its current order, see `OrderQueue`.
+//!
+//! a fleet moving to a solar system arrives when it enters the hill sphere of
+//! the system, i.e. its `AstroRadius`.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::fleet::{FleetMarker, FleetRadius, FleetStats};
+use super::order::OrderQueue;
+use crate::map::astronomy::AstroRadius;
+use crate::map::rapier_collider::{ColliderKind, RapierCollider, RapierCollisionEngine};
+use crate::map::solar_system::SolarSystemMarker;
+use crate::map::{SpaceLayer, SPEED_OF_LIGHT};
+use crate::utils::oid::{Oid, OidTable};
+
+/// the number of ticks to charge the hyper drives before entering hyper space
+pub const HYPER_CHARGE_TICKS: u32 = 32;
+
+/// the gap between two colliders still counted as touching, a fleet stopped
+/// by a sweep is not exactly on the edge of the sphere
+const CONTACT_TOLERANCE: f32 = 1e-3;
+
+/// a system to move fleets toward their targets, update their colliders, and
+/// send `FleetArrived` when a fleet reaches its target. the current order of
+/// an intercepted fleet is dropped.
+///
+/// schedule requirement:
+/// - should run after `system_fleet_stats`
+/// - must run before `system_collision_step`
+#[allow(clippy::type_complexity)]
+pub fn system_fleet_movement(
+    mut engine: ResMut<RapierCollisionEngine>,
+    table: Res<OidTable>,
+    systems: Query<(&Transform, &AstroRadius), (With<SolarSystemMarker>, Without<FleetMarker>)>,
+    mut fleets: Query<
+        (
+            Entity,
+            &mut FleetMovement,
+            &mut Transform,
+            &mut SpaceLayer,
+            &FleetStats,
+            &FleetRadius,
+            Option<&RapierCollider>,
+            Option<&mut OrderQueue>,
+        ),
+        With<FleetMarker>,
+    >,
+    mut arrived: EventWriter<FleetArrived>,
+) {
+    for (entity, mut movement, mut transform, mut layer, stats, radius, collider, queue) in
+        fleets.iter_mut()
+    {
+        if movement.is_idle(*layer) {
+            continue;
+        }
+
+        // the solar system of the target, if it still exists
+        let system = movement.target.and_then(|target| {
+            let system = table.query(&target.system?)?;
+            let (transform, radius) = systems.get(system).ok()?;
+            Some((system, transform.translation, f32::from(*radius)))
+        });
+        let goal = match (movement.target, system) {
+            (_, Some((_, center, radius))) => Some(Goal { center, radius }),
+            (Some(target), None) => Some(Goal {
+                center: target.position,
+                radius: 0.0,
+            }),
+            (None, _) => None,
+        };
+
+        let old_position = transform.translation;
+        let old_layer = *layer;
+        let mut arrival = movement.step(&mut transform.translation, &mut layer, stats, goal);
+
+        // the sweep below ignores the spheres the fleet already touches
+        let hyperspace = old_layer == SpaceLayer::Hyper || *layer == SpaceLayer::Hyper;
+        let mut intercepted = hyperspace && is_interdicted(&engine, old_position, (*radius).into());
+        if intercepted {
+            transform.translation = old_position;
+            *layer = SpaceLayer::Normal;
+        }
+
+        if let Some(collider) = collider {
+            if *layer != old_layer {
+                engine.update_fleet_layer(*collider, *layer);
+            }
+            if transform.translation != old_position && old_layer == SpaceLayer::Hyper {
+                // the fleet can't tunnel through an interdiction sphere in one tick
+                let fortification = Some(ColliderKind::Fortification);
+                let hit =
+                    engine.update_transform_swept(*collider, transform.translation, fortification);
+                if let Some(hit) = hit {
+                    transform.translation = hit.position;
+                    *layer = SpaceLayer::Normal;
+                    engine.update_fleet_layer(*collider, *layer);
+                    intercepted = true;
+                }
+            } else if transform.translation != old_position {
+                engine.update_transform(*collider, transform.translation);
+            }
+        }
+        if intercepted {
+            movement.intercept();
+            arrival = false;
+            if let Some(order) = queue.and_then(|mut queue| queue.fail_current()) {
+                info!(
+                    "[fleet] {:?} is intercepted, drop order {:?}",
+                    entity, order
+                );
+            }
+        }
+        if arrival {
+            arrived.send(FleetArrived {
+                fleet: entity,
+                system: system.map(|(system, _, _)| system),
+            });
+        }
+    }
+}
+
+/// test if a fleet at `position` touches the interdiction sphere of a
+/// fortification
+fn is_interdicted(engine: &RapierCollisionEngine, position: Vec3, radius: f32) -> bool {
+    let fortification = Some(ColliderKind::Fortification);
+    !engine
+        .intersect_sphere(position, radius + CONTACT_TOLERANCE, fortification)
+        .is_empty()
+}
+
+//////////////////////// Component ////////////////////////
+
+/// the target of a movement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoveTarget {
+    /// the destination, or the last known position of the solar system
+    pub position: Vec3,
+    /// the solar system to enter, the fleet arrives at its hill sphere
+    pub system: Option<Oid>,
+    /// whether to travel through hyper space, ignored if the fleet can't
+    /// enter hyper space
+    pub hyperspace: bool,
+}
+
+impl MoveTarget {
+    /// move to a point in normal space
+    pub fn point(position: Vec3) -> Self {
+        Self {
+            position,
+            system: None,
+            hyperspace: false,
+        }
+    }
+
+    /// move to the solar system `system` at `position` in normal space
+    pub fn system(system: Oid, position: Vec3) -> Self {
+        Self {
+            position,
+            system: Some(system),
+            hyperspace: false,
+        }
+    }
+
+    /// travel through hyper space instead
+    pub fn via_hyperspace(mut self) -> Self {
+        self.hyperspace = true;
+        self
+    }
+}
+
+/// the motion of a fleet, see the module document.
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FleetMovement {
+    /// in map unit per tick
+    pub velocity: Vec3,
+    pub target: Option<MoveTarget>,
+    /// the ticks left to charge the hyper drives, `None` if not charging
+    pub charge: Option<u32>,
+}
+
+/// where a fleet arrives, a sphere around the target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Goal {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl FleetMovement {
+    /// set a new target, the charge of hyper drives is kept
+    pub fn move_to(&mut self, target: MoveTarget) {
+        self.target = Some(target);
+    }
+
+    /// forget the target, the fleet brakes in normal space, or drops to
+    /// normal space at once in hyper space
+    pub fn stop(&mut self) {
+        self.target = None;
+        self.charge = None;
+    }
+
+    /// stop the fleet at once when it hits an interdiction sphere, the target
+    /// is forgotten
+    pub fn intercept(&mut self) {
+        self.stop();
+        self.velocity = Vec3::ZERO;
+    }
+
+    /// test if the fleet is at rest in normal space without target
+    pub fn is_idle(&self, layer: SpaceLayer) -> bool {
+        self.target.is_none() && self.velocity == Vec3::ZERO && layer == SpaceLayer::Normal
+    }
+
+    /// move the fleet by one tick toward `goal`, return true if the fleet
+    /// arrives in this tick. the target is cleared on arrival.
+    pub fn step(
+        &mut self,
+        position: &mut Vec3,
+        layer: &mut SpaceLayer,
+        stats: &FleetStats,
+        goal: Option<Goal>,
+    ) -> bool {
+        let Some(goal) = goal else {
+            self.charge = None;
+            if *layer == SpaceLayer::Hyper {
+                self.drop_out(layer);
+            } else {
+                self.brake(position, stats.acceleration);
+            }
+            return false;
+        };
+
+        let offset = goal.center - *position;
+        let remaining = (offset.length() - goal.radius).max(0.0);
+        if remaining == 0.0 {
+            self.arrive(layer);
+            return true;
+        }
+        let direction = offset / offset.length();
+
+        if *layer == SpaceLayer::Hyper {
+            let speed = stats.hyper_speed.unwrap_or(0.0).max(SPEED_OF_LIGHT);
+            if remaining <= speed {
+                *position += direction * remaining;
+                self.arrive(layer);
+                return true;
+            }
+            self.velocity = direction * speed;
+            *position += self.velocity;
+            return false;
+        }
+
+        let hyperspace = matches!(self.target, Some(target) if target.hyperspace);
+        if self.charge.is_none() && hyperspace && stats.hyper_speed.is_some() {
+            self.charge = Some(HYPER_CHARGE_TICKS);
+        }
+        if let Some(left) = self.charge {
+            self.brake(position, stats.acceleration);
+            if left <= 1 {
+                // jump at the hyper speed, the fleet moves from the next tick
+                self.charge = None;
+                let speed = stats.hyper_speed.unwrap_or(0.0).max(SPEED_OF_LIGHT);
+                self.velocity = direction * speed;
+                *layer = SpaceLayer::Hyper;
+            } else {
+                self.charge = Some(left - 1);
+            }
+            return false;
+        }
+
+        // the fastest speed the fleet can still brake from before the goal
+        let acceleration = stats.acceleration.max(0.0);
+        let speed = stats.max_speed.min((2.0 * acceleration * remaining).sqrt());
+        self.velocity += (direction * speed - self.velocity).clamp_length_max(acceleration);
+        if self.velocity.dot(direction) >= remaining {
+            *position += direction * remaining;
+            self.arrive(layer);
+            return true;
+        }
+        *position += self.velocity;
+        false
+    }
+
+    fn brake(&mut self, position: &mut Vec3, acceleration: f32) {
+        self.velocity -= self.velocity.clamp_length_max(acceleration.max(0.0));
+        *position += self.velocity;
+    }
+
+    fn drop_out(&mut self, layer: &mut SpaceLayer) {
+        self.velocity = Vec3::ZERO;
+        *layer = SpaceLayer::Normal;
+    }
+
+    fn arrive(&mut self, layer: &mut SpaceLayer) {
+        self.target = None;
+        self.charge = None;
+        self.drop_out(layer);
+    }
+}
+
+//////////////////////// Event ////////////////////////
+
+/// sent when a fleet arrives at its target, the fleet is at rest in normal
+/// space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FleetArrived {
+    pub fleet: Entity,
+    /// the solar system whose hill sphere is entered, if the target is a
+    /// solar system
+    pub system: Option<Entity>,
+}
+
+//////////////////////// Travel Time ////////////////////////
+
+/// the ticks to travel `distance` from rest to rest in normal space, the
+/// fleet accelerates, cruises at `max_speed` if it can reach it, and brakes.
+pub fn normal_travel_time(distance: f32, acceleration: f32, max_speed: f32) -> f32 {
+    if distance <= 0.0 {
+        return 0.0;
+    }
+    if acceleration <= 0.0 || max_speed <= 0.0 {
+        return f32::INFINITY;
+    }
+    // the distance to reach the maximum speed and brake again
+    let ramp = max_speed * max_speed / acceleration;
+    if distance < ramp {
+        2.0 * (distance / acceleration).sqrt()
+    } else {
+        distance / max_speed + max_speed / acceleration
+    }
+}
+
+/// the ticks to travel `distance` through hyper space from rest, including
+/// the charge of hyper drives.
+pub fn hyper_travel_time(distance: f32, hyper_speed: f32) -> f32 {
+    let speed = hyper_speed.max(SPEED_OF_LIGHT);
+    HYPER_CHARGE_TICKS as f32 + (distance.max(0.0) / speed).ceil()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::fleet::{spawn_fleet, FleetRadius};
+    use crate::fleet::stats::ShipStats;
+    use crate::map::rapier_collider::{system_collision_step, ProximityStarted, ProximityStopped};
+    use bevy::ecs::event::Events;
+
+    fn stats(acceleration: f32, max_speed: f32, hyper_speed: Option<f32>) -> FleetStats {
+        FleetStats {
+            ships: 1,
+            acceleration,
+            max_speed,
+            hyper_speed,
+            ..default()
+        }
+    }
+
+    /// run until arrival, return the number of ticks
+    fn travel(stats: &FleetStats, target: MoveTarget, goal: Goal) -> (u32, Vec3) {
+        let mut movement = FleetMovement::default();
+        movement.move_to(target);
+        let mut position = Vec3::ZERO;
+        let mut layer = SpaceLayer::Normal;
+        for tick in 1..10_000 {
+            if movement.step(&mut position, &mut layer, stats, Some(goal)) {
+                assert_eq!(layer, SpaceLayer::Normal);
+                assert_eq!(movement.velocity, Vec3::ZERO);
+                return (tick, position);
+            }
+            let speed = movement.velocity.length();
+            match layer {
+                SpaceLayer::Normal => assert!(speed <= stats.max_speed + 1e-6),
+                SpaceLayer::Hyper => assert!(speed >= SPEED_OF_LIGHT),
+            }
+        }
+        panic!("never arrive");
+    }
+
+    #[test]
+    fn normal_space_travel_time() {
+        let goal = Goal {
+            center: Vec3::X * 100.0,
+            radius: 0.0,
+        };
+        let target = MoveTarget::point(goal.center);
+
+        // the simulation moves in whole ticks and arrives a bit earlier than
+        // the continuous estimate, but the tick count is exact
+
+        // cruise at the maximum speed
+        let fleet = stats(0.01, 0.5, None);
+        let (ticks, position) = travel(&fleet, target, goal);
+        assert_eq!(position, goal.center);
+        let expected = normal_travel_time(100.0, 0.01, 0.5);
+        assert_eq!(expected, 250.0);
+        assert!(ticks as f32 > expected * 0.95, "{}", ticks);
+        assert_eq!(ticks, 243);
+
+        // never reach the maximum speed
+        let fleet = stats(0.001, 0.5, None);
+        let (ticks, _) = travel(&fleet, target, goal);
+        let expected = normal_travel_time(100.0, 0.001, 0.5);
+        assert!((expected - 632.46).abs() < 0.01);
+        assert!(ticks as f32 > expected * 0.95, "{}", ticks);
+        assert_eq!(ticks, 615);
+
+        // deterministic
+        assert_eq!(travel(&fleet, target, goal).0, ticks);
+        // the hyper space flag is ignored without hyper drive
+        assert_eq!(travel(&fleet, target.via_hyperspace(), goal).0, ticks);
+    }
+
+    #[test]
+    fn hyper_space_travel_time() {
+        let goal = Goal {
+            center: Vec3::X * 100.0,
+            radius: 10.0,
+        };
+        let target = MoveTarget::point(goal.center).via_hyperspace();
+
+        let fleet = stats(0.01, 0.5, Some(2.0));
+        let (ticks, position) = travel(&fleet, target, goal);
+        assert_eq!(ticks, HYPER_CHARGE_TICKS + 45);
+        assert_eq!(ticks as f32, hyper_travel_time(90.0, 2.0));
+        assert_eq!(position, Vec3::X * 90.0);
+
+        // at least the speed of light
+        let fleet = stats(0.01, 0.5, Some(0.5));
+        let (ticks, _) = travel(&fleet, target, goal);
+        assert_eq!(ticks, HYPER_CHARGE_TICKS + 90);
+    }
+
+    #[test]
+    fn arrive_at_hill_sphere() {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<OidTable>();
+        world.init_resource::<Events<FleetArrived>>();
+        world.init_resource::<Events<ProximityStarted>>();
+        world.init_resource::<Events<ProximityStopped>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_fleet_movement, system_collision_step).chain());
+
+        let oid = Oid::v4();
+        let center = Vec3::new(0.0, 0.0, 50.0);
+        let system = world
+            .spawn((
+                oid,
+                Transform::from_translation(center),
+                AstroRadius::new(5.0),
+                SolarSystemMarker,
+            ))
+            .id();
+        world.resource_mut::<OidTable>().insert(oid, system);
+
+        let ship = ShipStats {
+            acceleration: 0.01,
+            max_speed: 0.5,
+            hyper_speed: Some(3.0),
+            ..default()
+        };
+        let ship = world.spawn(ship).id();
+        let fleet = spawn_fleet(
+            &mut world,
+            Oid::v4(),
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            vec![ship],
+        );
+        world
+            .get_mut::<FleetMovement>(fleet)
+            .unwrap()
+            .move_to(MoveTarget::system(oid, Vec3::ZERO).via_hyperspace());
+
+        let mut reader = world.resource::<Events<FleetArrived>>().get_reader();
+        let mut ticks = 0;
+        while reader.iter(world.resource()).next().is_none() {
+            schedule.run(&mut world);
+            ticks += 1;
+            assert!(ticks < 1000);
+            if ticks == HYPER_CHARGE_TICKS + 5 {
+                // the collider follows the fleet into hyper space
+                assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Hyper));
+                let engine = world.resource::<RapierCollisionEngine>();
+                let hits = engine.intersect_sphere(Vec3::Z * 15.0, 0.1, Some(ColliderKind::Fleet));
+                assert_eq!(hits, vec![fleet]);
+            }
+        }
+
+        assert_eq!(ticks, HYPER_CHARGE_TICKS + 15);
+        let events = world.resource::<Events<FleetArrived>>();
+        let arrival = events.iter_current_update_events().next().copied();
+        assert_eq!(
+            arrival,
+            Some(FleetArrived {
+                fleet,
+                system: Some(system)
+            })
+        );
+        let position = world.get::<Transform>(fleet).unwrap().translation;
+        assert!((position.distance(center) - 5.0).abs() < 1e-4);
+        assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Normal));
+        assert!(world.get::<FleetMovement>(fleet).unwrap().target.is_none());
+    }
+
+    #[test]
+    fn intercepted_by_interdiction_sphere() {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<OidTable>();
+        world.init_resource::<Events<FleetArrived>>();
+        world.init_resource::<Events<ProximityStarted>>();
+        world.init_resource::<Events<ProximityStopped>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_fleet_movement, system_collision_step).chain());
+
+        // the sphere is much thinner than one tick of travel in hyper space
+        let center = Vec3::new(0.0, 0.0, 30.0);
+        let fortification = world.spawn_empty().id();
+        let collider = world
+            .resource_mut::<RapierCollisionEngine>()
+            .spawn_fortification(fortification, center, 1.0);
+        world.entity_mut(fortification).insert(collider);
+
+        let ship = ShipStats {
+            acceleration: 0.01,
+            max_speed: 0.5,
+            hyper_speed: Some(20.0),
+            ..default()
+        };
+        let ship = world.spawn(ship).id();
+        let fleet = spawn_fleet(
+            &mut world,
+            Oid::v4(),
+            Vec3::ZERO,
+            SpaceLayer::Hyper,
+            vec![ship],
+        );
+        world
+            .get_mut::<FleetMovement>(fleet)
+            .unwrap()
+            .move_to(MoveTarget::point(Vec3::Z * 100.0).via_hyperspace());
+
+        schedule.run(&mut world);
+        assert_eq!(
+            world.get::<Transform>(fleet).unwrap().translation,
+            Vec3::Z * 20.0
+        );
+        assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Hyper));
+
+        // the fleet would jump over the sphere to z = 40 in this tick
+        schedule.run(&mut world);
+        let radius = f32::from(*world.get::<FleetRadius>(fleet).unwrap());
+        let position = world.get::<Transform>(fleet).unwrap().translation;
+        assert!(
+            (position.distance(center) - 1.0 - radius).abs() < 1e-4,
+            "{}",
+            position
+        );
+        assert!(position.z < center.z);
+        assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Normal));
+        let movement = world.get::<FleetMovement>(fleet).unwrap();
+        assert!(movement.is_idle(SpaceLayer::Normal));
+        assert!(world.resource::<Events<FleetArrived>>().is_empty());
+
+        // the collider stops with the fleet
+        let engine = world.resource::<RapierCollisionEngine>();
+        let hits = engine.intersect_sphere(position, 0.1, Some(ColliderKind::Fleet));
+        assert_eq!(hits, vec![fleet]);
+
+        // and stays there
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Transform>(fleet).unwrap().translation, position);
+
+        // the fleet touches the sphere, it is intercepted again when it jumps
+        world
+            .get_mut::<FleetMovement>(fleet)
+            .unwrap()
+            .move_to(MoveTarget::point(Vec3::Z * 100.0).via_hyperspace());
+        for _ in 0..HYPER_CHARGE_TICKS + 10 {
+            schedule.run(&mut world);
+            assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Normal));
+            assert!(world.get::<Transform>(fleet).unwrap().translation.z < center.z);
+        }
+        assert!(world
+            .get::<FleetMovement>(fleet)
+            .unwrap()
+            .is_idle(SpaceLayer::Normal));
+    }
+}
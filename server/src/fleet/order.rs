@@ -0,0 +1,597 @@
+//! the orders of fleets. a fleet executes the first order of its
+//! `OrderQueue`, the order is removed once completed and the next one starts
+//! in the same tick.
+//!
+//! orders refer to solar systems and fleets by `Oid`, so the queue is saved as
+//! is. an order whose solar system or fleet no longer exists is dropped.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use fxhash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::fleet::{FleetMarker, FleetRadius, FleetStats};
+use super::movement::{FleetMovement, MoveTarget};
+use crate::map::astronomy::AstroRadius;
+use crate::map::solar_system::SolarSystemMarker;
+use crate::map::SpaceLayer;
+use crate::utils::oid::{Oid, OidTable};
+
+/// the distance within which a fleet counts as arrived at a point
+pub const ARRIVAL_TOLERANCE: f32 = 1e-3;
+
+/// a system to execute the first order of every fleet, it sets the target of
+/// `FleetMovement` and advances the `OrderQueue` on completion.
+///
+/// schedule requirement:
+/// - must run before `system_fleet_movement`
+#[allow(clippy::type_complexity)]
+pub fn system_fleet_orders(
+    table: Res<OidTable>,
+    systems: Query<(&Transform, &AstroRadius), (With<SolarSystemMarker>, Without<FleetMarker>)>,
+    mut fleets: Query<
+        (
+            Entity,
+            &Transform,
+            &SpaceLayer,
+            &FleetRadius,
+            &FleetStats,
+            &mut FleetMovement,
+            &mut OrderQueue,
+        ),
+        With<FleetMarker>,
+    >,
+) {
+    // the state of all fleets at the beginning of the tick, for following or
+    // intercepting another fleet
+    let snapshot: FxHashMap<Entity, FleetState> = fleets
+        .iter()
+        .map(|(entity, transform, layer, radius, _, movement, _)| {
+            let state = FleetState {
+                position: transform.translation,
+                velocity: movement.velocity,
+                radius: (*radius).into(),
+                layer: *layer,
+            };
+            (entity, state)
+        })
+        .collect();
+
+    let system = |id: &Oid| {
+        let (transform, radius) = systems.get(table.query(id)?).ok()?;
+        Some((transform.translation, f32::from(*radius)))
+    };
+    let fleet = |id: &Oid| snapshot.get(&table.query(id)?).copied();
+
+    for (entity, _, _, _, stats, mut movement, mut queue) in fleets.iter_mut() {
+        if queue.is_empty() {
+            // the orders are cleared by the player
+            if queue.is_changed() && !queue.is_added() {
+                movement.stop();
+            }
+            continue;
+        }
+
+        let state = snapshot[&entity];
+        // each order either completes or sets the movement, bound the loop in
+        // case every order completes at once
+        for _ in 0..=queue.len() {
+            let Some(order) = queue.0.front_mut() else {
+                break;
+            };
+            let step = order.execute(&state, stats, system, fleet);
+            match step {
+                OrderStep::Move(target) => {
+                    if movement.target != Some(target) {
+                        movement.move_to(target);
+                    }
+                    break;
+                }
+                OrderStep::Stop => {
+                    movement.stop();
+                    break;
+                }
+                OrderStep::Done => {
+                    queue.0.pop_front();
+                    if queue.is_empty() {
+                        movement.stop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+//////////////////////// Component ////////////////////////
+
+/// an order of a fleet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FleetOrder {
+    /// move into the hill sphere of a solar system
+    MoveToSystem { system: Oid, hyperspace: bool },
+    /// move to a point
+    MoveToPoint { position: Vec3, hyperspace: bool },
+    /// visit the solar systems in turn forever, `next` is the index of the
+    /// system to visit next
+    Patrol {
+        systems: Vec<Oid>,
+        next: usize,
+        hyperspace: bool,
+    },
+    /// stay at the current position, for `ticks` ticks or forever if `None`
+    Hold { ticks: Option<u32> },
+    /// stay within `distance` of another fleet, e.g. to escort it. never
+    /// completes unless the fleet is gone.
+    Follow { fleet: Oid, distance: f32 },
+    /// catch a moving fleet in normal space, aiming at where it will be.
+    /// completes when the colliders touch.
+    Intercept { fleet: Oid },
+    /// travel to a solar system along the fastest route, expanded into
+    /// `MoveToSystem` orders by `system_fleet_routes`. the fleet stops while
+    /// it waits for the route.
+    Travel { system: Oid },
+}
+
+/// the orders of a fleet, the first one is executed.
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OrderQueue(VecDeque<FleetOrder>);
+
+impl OrderQueue {
+    pub fn new(orders: impl IntoIterator<Item = FleetOrder>) -> Self {
+        Self(orders.into_iter().collect())
+    }
+
+    /// append an order after the existing ones
+    pub fn push(&mut self, order: FleetOrder) {
+        self.0.push_back(order);
+    }
+
+    /// drop all orders and execute `order` at once
+    pub fn replace(&mut self, order: FleetOrder) {
+        self.0.clear();
+        self.0.push_back(order);
+    }
+
+    /// replace the order being executed with `orders`, executed in turn
+    pub fn expand_front(&mut self, orders: impl IntoIterator<Item = FleetOrder>) {
+        self.0.pop_front();
+        let orders: Vec<FleetOrder> = orders.into_iter().collect();
+        for order in orders.into_iter().rev() {
+            self.0.push_front(order);
+        }
+    }
+
+    /// drop all orders, the fleet stops at the next tick
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// drop the order being executed, e.g. when the fleet is intercepted. the
+    /// next order starts at the next tick.
+    pub fn fail_current(&mut self) -> Option<FleetOrder> {
+        self.0.pop_front()
+    }
+
+    /// the order being executed
+    pub fn current(&self) -> Option<&FleetOrder> {
+        self.0.front()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FleetOrder> + '_ {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+//////////////////////// Execution ////////////////////////
+
+/// the state of a fleet read by orders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FleetState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+    pub layer: SpaceLayer,
+}
+
+/// the result of executing an order for one tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderStep {
+    /// move to the target
+    Move(MoveTarget),
+    /// stop where the fleet is
+    Stop,
+    /// the order is completed or can't be executed any more
+    Done,
+}
+
+impl FleetOrder {
+    /// execute the order for the fleet in `state` for one tick. `system` and
+    /// `fleet` look up the position (and the hill sphere radius) of a solar
+    /// system and the state of a fleet.
+    pub fn execute(
+        &mut self,
+        state: &FleetState,
+        stats: &FleetStats,
+        system: impl Fn(&Oid) -> Option<(Vec3, f32)>,
+        fleet: impl Fn(&Oid) -> Option<FleetState>,
+    ) -> OrderStep {
+        let arrived = |center: Vec3, radius: f32| {
+            state.layer == SpaceLayer::Normal
+                && state.position.distance(center) <= radius + ARRIVAL_TOLERANCE
+        };
+
+        match self {
+            Self::MoveToSystem {
+                system: id,
+                hyperspace,
+            } => match system(id) {
+                Some((center, radius)) if !arrived(center, radius) => OrderStep::Move(
+                    with_hyperspace(MoveTarget::system(*id, center), *hyperspace),
+                ),
+                _ => OrderStep::Done,
+            },
+            // not expanded into a route yet
+            Self::Travel { system: id } => match system(id) {
+                Some((center, radius)) if !arrived(center, radius) => OrderStep::Stop,
+                _ => OrderStep::Done,
+            },
+            Self::MoveToPoint {
+                position,
+                hyperspace,
+            } => {
+                if arrived(*position, 0.0) {
+                    OrderStep::Done
+                } else {
+                    OrderStep::Move(with_hyperspace(MoveTarget::point(*position), *hyperspace))
+                }
+            }
+            Self::Patrol {
+                systems,
+                next,
+                hyperspace,
+            } => {
+                // skip the systems visited or gone, at most one round
+                for _ in 0..systems.len() {
+                    let id = systems[*next % systems.len()];
+                    match system(&id) {
+                        Some((center, radius)) if !arrived(center, radius) => {
+                            let target = MoveTarget::system(id, center);
+                            return OrderStep::Move(with_hyperspace(target, *hyperspace));
+                        }
+                        _ => *next = (*next + 1) % systems.len(),
+                    }
+                }
+                if systems.iter().any(|id| system(id).is_some()) {
+                    OrderStep::Stop
+                } else {
+                    OrderStep::Done
+                }
+            }
+            Self::Hold { ticks } => match ticks {
+                Some(0) => OrderStep::Done,
+                Some(ticks) => {
+                    *ticks -= 1;
+                    OrderStep::Stop
+                }
+                None => OrderStep::Stop,
+            },
+            Self::Follow {
+                fleet: id,
+                distance,
+            } => {
+                let Some(leader) = fleet(id) else {
+                    return OrderStep::Done;
+                };
+                let offset = state.position - leader.position;
+                if leader.layer != state.layer || offset.length() <= *distance {
+                    return OrderStep::Stop;
+                }
+                // keep half of the distance to the leader
+                let position = leader.position + offset.normalize() * *distance * 0.5;
+                OrderStep::Move(MoveTarget::point(position))
+            }
+            Self::Intercept { fleet: id } => {
+                let Some(target) = fleet(id) else {
+                    return OrderStep::Done;
+                };
+                let touching =
+                    state.position.distance(target.position) <= state.radius + target.radius;
+                if touching && target.layer == state.layer {
+                    return OrderStep::Done;
+                }
+                let position = lead_position(
+                    state.position,
+                    stats.max_speed,
+                    target.position,
+                    target.velocity,
+                );
+                OrderStep::Move(MoveTarget::point(position))
+            }
+        }
+    }
+}
+
+fn with_hyperspace(target: MoveTarget, hyperspace: bool) -> MoveTarget {
+    if hyperspace {
+        target.via_hyperspace()
+    } else {
+        target
+    }
+}
+
+/// the earliest point where a pursuer at `from` moving at `speed` can meet a
+/// target at `position` moving at `velocity`, or the current position of the
+/// target if it can't be caught.
+pub fn lead_position(from: Vec3, speed: f32, position: Vec3, velocity: Vec3) -> Vec3 {
+    // solve |offset + velocity * t| = speed * t for the smallest t > 0
+    let offset = position - from;
+    let a = velocity.length_squared() - speed * speed;
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.length_squared();
+
+    let time = if a.abs() < f32::EPSILON {
+        (b < 0.0).then(|| -c / b)
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+                .into_iter()
+                .filter(|t| *t > 0.0)
+                .reduce(f32::min)
+        }
+    };
+    match time {
+        Some(time) => position + velocity * time,
+        None => position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::fleet::spawn_fleet;
+    use crate::fleet::movement::{system_fleet_movement, FleetArrived, HYPER_CHARGE_TICKS};
+    use crate::fleet::stats::ShipStats;
+    use crate::map::rapier_collider::{
+        system_collision_step, ProximityStarted, ProximityStopped, RapierCollisionEngine,
+    };
+    use bevy::ecs::event::Events;
+
+    fn world() -> (World, Schedule) {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<OidTable>();
+        world.init_resource::<Events<FleetArrived>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_fleet_orders, system_fleet_movement).chain());
+        (world, schedule)
+    }
+
+    fn spawn_system(world: &mut World, position: Vec3) -> Oid {
+        let oid = Oid::v4();
+        let entity = world
+            .spawn((
+                oid,
+                Transform::from_translation(position),
+                AstroRadius::new(1.0),
+                SolarSystemMarker,
+            ))
+            .id();
+        world.resource_mut::<OidTable>().insert(oid, entity);
+        oid
+    }
+
+    fn spawn(world: &mut World, position: Vec3, max_speed: f32) -> Entity {
+        let ship = ShipStats {
+            acceleration: 0.05,
+            max_speed,
+            ..default()
+        };
+        let ship = world.spawn(ship).id();
+        spawn_fleet(world, Oid::v4(), position, SpaceLayer::Normal, vec![ship])
+    }
+
+    fn position(world: &World, fleet: Entity) -> Vec3 {
+        world.get::<Transform>(fleet).unwrap().translation
+    }
+
+    #[test]
+    fn move_then_hold_then_patrol() {
+        let (mut world, mut schedule) = world();
+        let a = spawn_system(&mut world, Vec3::X * 10.0);
+        let b = spawn_system(&mut world, Vec3::Z * 10.0);
+        let fleet = spawn(&mut world, Vec3::ZERO, 0.5);
+
+        let point = Vec3::new(5.0, 0.0, 5.0);
+        world.entity_mut(fleet).insert(OrderQueue::new([
+            FleetOrder::MoveToSystem {
+                system: a,
+                hyperspace: false,
+            },
+            FleetOrder::Hold { ticks: Some(3) },
+            FleetOrder::MoveToPoint {
+                position: point,
+                hyperspace: false,
+            },
+            FleetOrder::Patrol {
+                systems: vec![a, b],
+                next: 0,
+                hyperspace: false,
+            },
+        ]));
+
+        let mut ticks = 0;
+        while world.get::<OrderQueue>(fleet).unwrap().len() == 4 {
+            schedule.run(&mut world);
+            ticks += 1;
+            assert!(ticks < 1000);
+        }
+        assert!((position(&world, fleet).distance(Vec3::X * 10.0) - 1.0).abs() < 1e-3);
+
+        // the hold order counts down, then the fleet moves on
+        let held = position(&world, fleet);
+        for _ in 0..2 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world, fleet), held);
+        assert_eq!(world.get::<OrderQueue>(fleet).unwrap().len(), 3);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<OrderQueue>(fleet).unwrap().len(), 2);
+
+        while world.get::<OrderQueue>(fleet).unwrap().len() == 2 {
+            schedule.run(&mut world);
+            ticks += 1;
+            assert!(ticks < 2000);
+        }
+        // the patrol starts in the tick the point is reached
+        assert!(position(&world, fleet).distance(point) <= 0.05 + ARRIVAL_TOLERANCE);
+
+        // patrol between a and b forever
+        let mut visited = Vec::new();
+        for _ in 0..2000 {
+            schedule.run(&mut world);
+            let Some(FleetOrder::Patrol { next, .. }) =
+                world.get::<OrderQueue>(fleet).unwrap().current()
+            else {
+                panic!("patrol is never completed");
+            };
+            if visited.last() != Some(next) {
+                visited.push(*next);
+            }
+        }
+        assert!(visited.len() >= 3, "{:?}", visited);
+    }
+
+    #[test]
+    fn follow_and_intercept() {
+        let (mut world, mut schedule) = world();
+        let leader = spawn(&mut world, Vec3::ZERO, 0.2);
+        let escort = spawn(&mut world, Vec3::X * -5.0, 0.5);
+        let interceptor = spawn(&mut world, Vec3::new(30.0, 0.0, -30.0), 0.5);
+
+        let leader_oid = *world.get::<Oid>(leader).unwrap();
+        world
+            .get_mut::<FleetMovement>(leader)
+            .unwrap()
+            .move_to(MoveTarget::point(Vec3::Z * 1000.0));
+        world
+            .entity_mut(escort)
+            .insert(OrderQueue::new([FleetOrder::Follow {
+                fleet: leader_oid,
+                distance: 5.0,
+            }]));
+        world
+            .entity_mut(interceptor)
+            .insert(OrderQueue::new([FleetOrder::Intercept {
+                fleet: leader_oid,
+            }]));
+
+        let mut ticks = 0;
+        while !world.get::<OrderQueue>(interceptor).unwrap().is_empty() {
+            schedule.run(&mut world);
+            ticks += 1;
+            assert!(ticks < 1000);
+            let distance = position(&world, escort).distance(position(&world, leader));
+            assert!(distance < 10.0, "{}", distance);
+        }
+
+        let distance = position(&world, interceptor).distance(position(&world, leader));
+        assert!(distance <= 2.0 + 0.5, "{}", distance);
+        // the interceptor aims ahead of the leader instead of chasing it
+        assert!(position(&world, interceptor).z > 0.0);
+        assert_eq!(world.get::<OrderQueue>(escort).unwrap().len(), 1);
+
+        // the escort drops the order once the leader is gone
+        crate::fleet::fleet::disband_fleet(&mut world, leader);
+        schedule.run(&mut world);
+        assert!(world.get::<OrderQueue>(escort).unwrap().is_empty());
+    }
+
+    #[test]
+    fn intercepted_fleet_fails_its_order() {
+        let (mut world, _) = world();
+        world.init_resource::<Events<ProximityStarted>>();
+        world.init_resource::<Events<ProximityStopped>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems(
+            (
+                system_fleet_orders,
+                system_fleet_movement,
+                system_collision_step,
+            )
+                .chain(),
+        );
+
+        // an interdiction sphere between the fleet and its destination
+        let center = Vec3::Z * 30.0;
+        let fortification = world.spawn_empty().id();
+        let collider = world
+            .resource_mut::<RapierCollisionEngine>()
+            .spawn_fortification(fortification, center, 1.0);
+        world.entity_mut(fortification).insert(collider);
+
+        let ship = ShipStats {
+            acceleration: 0.05,
+            max_speed: 0.5,
+            hyper_speed: Some(20.0),
+            ..default()
+        };
+        let ship = world.spawn(ship).id();
+        let fleet = spawn_fleet(
+            &mut world,
+            Oid::v4(),
+            Vec3::ZERO,
+            SpaceLayer::Normal,
+            vec![ship],
+        );
+        let order = FleetOrder::MoveToPoint {
+            position: Vec3::Z * 100.0,
+            hyperspace: true,
+        };
+        world
+            .entity_mut(fleet)
+            .insert(OrderQueue::new([order.clone(), order]));
+
+        // the first order is intercepted at the sphere, the second one at once
+        // when the fleet jumps again
+        let mut lengths = Vec::new();
+        for _ in 0..3 * HYPER_CHARGE_TICKS {
+            schedule.run(&mut world);
+            assert!(position(&world, fleet).z < center.z);
+            let len = world.get::<OrderQueue>(fleet).unwrap().len();
+            if lengths.last() != Some(&len) {
+                lengths.push(len);
+            }
+        }
+        assert_eq!(lengths, vec![2, 1, 0]);
+        assert_eq!(world.get::<SpaceLayer>(fleet), Some(&SpaceLayer::Normal));
+        let movement = world.get::<FleetMovement>(fleet).unwrap();
+        assert!(movement.is_idle(SpaceLayer::Normal));
+    }
+
+    #[test]
+    fn lead_prediction() {
+        // a target crossing in front of the pursuer at half its speed
+        let position = lead_position(Vec3::ZERO, 1.0, Vec3::X * 10.0, Vec3::Z * 0.5);
+        let time = position.length();
+        assert!((position - (Vec3::X * 10.0 + Vec3::Z * 0.5 * time)).length() < 1e-3);
+
+        // a faster target running away can't be caught
+        let position = lead_position(Vec3::ZERO, 0.5, Vec3::X * 10.0, Vec3::X);
+        assert_eq!(position, Vec3::X * 10.0);
+    }
+}
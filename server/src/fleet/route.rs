@@ -0,0 +1,926 @@
+//! route planning between solar systems.
+//!
+//! the route graph has a node per solar system, two systems are connected if
+//! they are within the jump range of the fleet, i.e. the distance it can go
+//! without refueling. each leg is travelled in normal space or through hyper
+//! space, whichever is faster, see `normal_travel_time` and
+//! `hyper_travel_time`:
+//! - a hyper space leg pays the charge of the hyper drives on entry, and drops
+//!   out at rest on exit, so a route of many short jumps is slow.
+//! - a hyper space leg passing an interdiction sphere of a fortification is
+//!   not allowed, the fleet would be intercepted.
+//!
+//! the jump range is a stat of the fleet, the shortest one of its ships, see
+//! `ShipStats::jump_range`. a `FleetOrder::Travel` is expanded into the legs
+//! of its route by `system_fleet_routes`.
+//!
+//! routes are found by A* and cached until the map changes. the cache is keyed
+//! by the exact `RouteParams`, which change whenever a module of the fleet is
+//! disabled or repaired, so it's dropped as a whole once it holds
+//! `ROUTE_CACHE_CAPACITY` routes. at most `ROUTE_SEARCHES_PER_TICK` searches
+//! run in a tick, the other fleets wait for their routes.
+
+use core::fmt;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+use fxhash::FxHashMap;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+
+use super::fleet::{FleetMarker, FleetStats};
+use super::movement::{hyper_travel_time, normal_travel_time, HYPER_CHARGE_TICKS};
+use super::order::{FleetOrder, OrderQueue};
+use crate::map::fortification::{FortificationMarker, InterdictionRadius};
+use crate::map::solar_system::SolarSystemMarker;
+use crate::utils::oid::Oid;
+
+/// the cell size of the grids of `RoutePlanner`, close to the distance
+/// between neighbor solar systems
+pub const ROUTE_CELL_SIZE: f32 = 16.0;
+
+/// the maximum number of solar systems expanded by one search, it bounds the
+/// time of a search in a frame
+pub const ROUTE_EXPANSION_BUDGET: usize = 50_000;
+
+/// the maximum number of cached routes
+pub const ROUTE_CACHE_CAPACITY: usize = 4096;
+
+/// the maximum number of routes searched in a tick, the cached ones are free
+pub const ROUTE_SEARCHES_PER_TICK: usize = 8;
+
+/// a system to rebuild the route graph and drop the cached routes when any
+/// solar system or fortification is added, moved or removed, or an
+/// interdiction sphere is resized.
+#[allow(clippy::type_complexity)]
+pub fn system_route_planner(
+    mut planner: ResMut<RoutePlanner>,
+    systems: Query<(&Oid, &Transform), With<SolarSystemMarker>>,
+    fortifications: Query<(&Transform, &InterdictionRadius), With<FortificationMarker>>,
+    changed_systems: Query<(), (With<SolarSystemMarker>, Changed<Transform>)>,
+    changed_fortifications: Query<
+        (),
+        (
+            With<FortificationMarker>,
+            Or<(Changed<Transform>, Changed<InterdictionRadius>)>,
+        ),
+    >,
+    mut removed_systems: RemovedComponents<SolarSystemMarker>,
+    mut removed_fortifications: RemovedComponents<FortificationMarker>,
+) {
+    // drain both readers
+    let removed = removed_systems.iter().count() + removed_fortifications.iter().count();
+    if removed == 0 && changed_systems.is_empty() && changed_fortifications.is_empty() {
+        return;
+    }
+
+    planner.rebuild(
+        systems
+            .iter()
+            .map(|(id, transform)| (*id, transform.translation)),
+        fortifications
+            .iter()
+            .map(|(transform, radius)| (transform.translation, (*radius).into())),
+    );
+    info!(
+        "[route] rebuild route graph, {} solar systems",
+        planner.nodes.len()
+    );
+}
+
+/// a system to replace a `FleetOrder::Travel` at the front of the queue with
+/// the `MoveToSystem` orders of its route. the route starts at the solar
+/// system nearest to the fleet. without a route, e.g. out of the jump range,
+/// the fleet goes straight through hyper space.
+///
+/// the fleets are served in the order of `Oid`, a fleet whose route isn't
+/// cached waits once `ROUTE_SEARCHES_PER_TICK` searches ran in the tick.
+///
+/// schedule requirement:
+/// - must run after `system_route_planner`
+/// - must run before `system_fleet_orders`
+pub fn system_fleet_routes(
+    mut planner: ResMut<RoutePlanner>,
+    mut fleets: Query<(&Oid, &Transform, &FleetStats, &mut OrderQueue), With<FleetMarker>>,
+) {
+    let mut travelling: Vec<_> = fleets
+        .iter_mut()
+        .filter(|(.., queue)| matches!(queue.current(), Some(FleetOrder::Travel { .. })))
+        .collect();
+    travelling.sort_by_key(|(id, ..)| **id);
+
+    let mut searches = 0;
+    for (id, transform, stats, mut queue) in travelling {
+        let Some(FleetOrder::Travel { system }) = queue.current() else {
+            continue;
+        };
+        let system = *system;
+        let direct = FleetOrder::MoveToSystem {
+            system,
+            hyperspace: true,
+        };
+
+        let params = RouteParams::new(stats);
+        let start = planner.nearest(transform.translation);
+        if let Some(start) = start {
+            if !planner.is_cached(start, system, &params) {
+                if searches == ROUTE_SEARCHES_PER_TICK {
+                    continue;
+                }
+                searches += 1;
+            }
+        }
+        let route = match start {
+            Some(start) => planner.find_route(start, system, &params),
+            None => Err(RouteError::UnknownSystem(system)),
+        };
+        let orders: Vec<FleetOrder> = match route {
+            Ok(route) if !route.waypoints.is_empty() => route.orders().collect(),
+            Ok(_) => vec![direct],
+            Err(err) => {
+                warn!(
+                    "[route] fleet {:?} goes straight to {:?}: {}",
+                    id, system, err
+                );
+                vec![direct]
+            }
+        };
+        queue.expand_front(orders);
+    }
+}
+
+//////////////////////// Route ////////////////////////
+
+/// the capability of a fleet relevant to routes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteParams {
+    pub acceleration: f32,
+    pub max_speed: f32,
+    /// `None` if the fleet can't enter hyper space
+    pub hyper_speed: Option<f32>,
+    /// the maximum length of a leg
+    pub jump_range: f32,
+}
+
+impl RouteParams {
+    /// the parameters of a fleet
+    pub fn new(stats: &FleetStats) -> Self {
+        Self {
+            acceleration: stats.acceleration,
+            max_speed: stats.max_speed,
+            hyper_speed: stats.hyper_speed,
+            jump_range: stats.jump_range,
+        }
+    }
+
+    fn key(&self) -> [u32; 4] {
+        [
+            self.acceleration.to_bits(),
+            self.max_speed.to_bits(),
+            self.hyper_speed.map_or(u32::MAX, f32::to_bits),
+            self.jump_range.to_bits(),
+        ]
+    }
+
+    /// the fastest way to travel a leg of `distance`, and its ticks
+    fn leg(&self, distance: f32, hyperspace: bool) -> (bool, f32) {
+        let normal = normal_travel_time(distance, self.acceleration, self.max_speed);
+        match self.hyper_speed {
+            Some(speed) if hyperspace => {
+                let hyper = hyper_travel_time(distance, speed);
+                if hyper < normal {
+                    (true, hyper)
+                } else {
+                    (false, normal)
+                }
+            }
+            _ => (false, normal),
+        }
+    }
+
+    /// the least ticks per map unit of any leg, so the distance times it never
+    /// overestimates the ticks of a route
+    fn min_rate(&self) -> f32 {
+        let normal = 1.0 / self.max_speed;
+        match self.hyper_speed {
+            Some(speed) => {
+                let hyper = 1.0 / speed + HYPER_CHARGE_TICKS as f32 / self.jump_range;
+                normal.min(hyper)
+            }
+            None => normal,
+        }
+    }
+}
+
+/// a solar system on a route
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub system: Oid,
+    pub position: Vec3,
+    /// whether the leg to this system is through hyper space
+    pub hyperspace: bool,
+    /// the ticks from the start of the route to the arrival
+    pub eta: f32,
+}
+
+/// a route between two solar systems
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    /// the solar systems to visit in order, excluding the start
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl Route {
+    /// the ticks from the start to the destination
+    pub fn eta(&self) -> f32 {
+        self.waypoints.last().map_or(0.0, |waypoint| waypoint.eta)
+    }
+
+    /// the orders to follow the route
+    pub fn orders(&self) -> impl Iterator<Item = FleetOrder> + '_ {
+        self.waypoints
+            .iter()
+            .map(|waypoint| FleetOrder::MoveToSystem {
+                system: waypoint.system,
+                hyperspace: waypoint.hyperspace,
+            })
+    }
+}
+
+/// the reason no route is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteError {
+    /// the solar system is not in the route graph
+    UnknownSystem(Oid),
+    /// no route within the jump range
+    Unreachable,
+    /// the search expanded `ROUTE_EXPANSION_BUDGET` solar systems
+    BudgetExceeded,
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSystem(id) => write!(f, "unknown solar system {:?}", id),
+            Self::Unreachable => write!(f, "no route within the jump range"),
+            Self::BudgetExceeded => write!(f, "the route search is over budget"),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+//////////////////////// Planner ////////////////////////
+
+#[derive(Debug, Clone, Copy)]
+struct RouteNode {
+    id: Oid,
+    position: Vec3,
+}
+
+/// the route graph and the cached routes.
+///
+/// CON data, reconstructed from the solar systems and fortifications
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RoutePlanner {
+    /// sorted by `Oid`
+    nodes: Vec<RouteNode>,
+    index: FxHashMap<Oid, u32>,
+    cells: FxHashMap<IVec3, Vec<u32>>,
+    /// the range of non-empty cells
+    bounds: Option<(IVec3, IVec3)>,
+    fortifications: Vec<(Vec3, f32)>,
+    fortification_cells: FxHashMap<IVec3, Vec<u32>>,
+    cache: FxHashMap<(Oid, Oid, [u32; 4]), Result<Route, RouteError>>,
+}
+
+fn cell_of(position: Vec3) -> IVec3 {
+    (position / ROUTE_CELL_SIZE).floor().as_ivec3()
+}
+
+/// the cells overlapped by the box between `min` and `max`
+fn cells_between(min: Vec3, max: Vec3) -> impl Iterator<Item = IVec3> {
+    let (min, max) = (cell_of(min), cell_of(max));
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+    })
+}
+
+impl RoutePlanner {
+    /// replace the route graph, all cached routes are dropped
+    pub fn rebuild(
+        &mut self,
+        systems: impl IntoIterator<Item = (Oid, Vec3)>,
+        fortifications: impl IntoIterator<Item = (Vec3, f32)>,
+    ) {
+        self.nodes = systems
+            .into_iter()
+            .map(|(id, position)| RouteNode { id, position })
+            .collect();
+        self.nodes.sort_by_key(|node| node.id);
+
+        self.index.clear();
+        self.cells.clear();
+        self.bounds = None;
+        for (i, node) in self.nodes.iter().enumerate() {
+            self.index.insert(node.id, i as u32);
+            let cell = cell_of(node.position);
+            self.cells.entry(cell).or_default().push(i as u32);
+            self.bounds = Some(match self.bounds {
+                Some((min, max)) => (min.min(cell), max.max(cell)),
+                None => (cell, cell),
+            });
+        }
+
+        self.fortifications = fortifications.into_iter().collect();
+        self.fortification_cells.clear();
+        for (i, (center, radius)) in self.fortifications.iter().enumerate() {
+            for cell in cells_between(*center - *radius, *center + *radius) {
+                self.fortification_cells
+                    .entry(cell)
+                    .or_default()
+                    .push(i as u32);
+            }
+        }
+
+        self.cache.clear();
+    }
+
+    /// the number of solar systems in the route graph
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// test if the route from `from` to `to` is cached, so `find_route` runs
+    /// no search
+    pub fn is_cached(&self, from: Oid, to: Oid, params: &RouteParams) -> bool {
+        self.cache.contains_key(&(from, to, params.key()))
+    }
+
+    /// find the fastest route from the solar system `from` to `to`, the
+    /// result is cached until the map changes or the cache is full.
+    pub fn find_route(
+        &mut self,
+        from: Oid,
+        to: Oid,
+        params: &RouteParams,
+    ) -> Result<Route, RouteError> {
+        let key = (from, to, params.key());
+        if let Some(route) = self.cache.get(&key) {
+            return route.clone();
+        }
+        let route = self.search(from, to, params);
+        if self.cache.len() >= ROUTE_CACHE_CAPACITY {
+            self.cache.clear();
+        }
+        self.cache.insert(key, route.clone());
+        route
+    }
+
+    /// the solar system nearest to `position`, ties are broken by `Oid`. the
+    /// cells are searched in rings around the cell of `position` until no
+    /// closer system can be found.
+    pub fn nearest(&self, position: Vec3) -> Option<Oid> {
+        let (min, max) = self.bounds?;
+        let center = cell_of(position).clamp(min, max);
+        // the distance from `position` to the center cell, if it's clamped
+        let corner = center.as_vec3() * ROUTE_CELL_SIZE;
+        let offset = position
+            .clamp(corner, corner + ROUTE_CELL_SIZE)
+            .distance(position);
+        let extent = (max - center).max(center - min).max_element();
+
+        let mut best: Option<(f32, u32)> = None;
+        for ring in 0..=extent {
+            if let Some((distance, _)) = best {
+                // every cell of the ring is at least `ring - 1` cells away
+                if (ring - 1) as f32 * ROUTE_CELL_SIZE - offset > distance {
+                    break;
+                }
+            }
+            for cell in ring_cells(center, ring, min, max) {
+                for node in self.cells.get(&cell).into_iter().flatten() {
+                    let distance = self.nodes[*node as usize].position.distance(position);
+                    let better = match best {
+                        Some(known) => (distance, *node) < known,
+                        None => true,
+                    };
+                    if better {
+                        best = Some((distance, *node));
+                    }
+                }
+            }
+        }
+        best.map(|(_, node)| self.nodes[node as usize].id)
+    }
+
+    /// the number of cached routes
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// drop all cached routes
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// the solar systems within `range` of the node, excluding itself
+    fn neighbors(&self, node: u32, range: f32) -> impl Iterator<Item = (u32, f32)> + '_ {
+        let center = self.nodes[node as usize].position;
+        let (min, max) = self.bounds.unwrap_or_default();
+        let lower = cell_of(center - range).max(min);
+        let upper = cell_of(center + range).min(max);
+
+        (lower.x..=upper.x)
+            .flat_map(move |x| {
+                (lower.y..=upper.y)
+                    .flat_map(move |y| (lower.z..=upper.z).map(move |z| IVec3::new(x, y, z)))
+            })
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter_map(move |other| {
+                let distance = self.nodes[*other as usize].position.distance(center);
+                (*other != node && distance <= range).then_some((*other, distance))
+            })
+    }
+
+    /// test if the segment passes any interdiction sphere
+    fn interdicted(&self, from: Vec3, to: Vec3) -> bool {
+        if self.fortifications.is_empty() {
+            return false;
+        }
+        cells_between(from.min(to), from.max(to))
+            .filter_map(|cell| self.fortification_cells.get(&cell))
+            .flatten()
+            .any(|i| {
+                let (center, radius) = self.fortifications[*i as usize];
+                segment_distance(from, to, center) <= radius
+            })
+    }
+
+    fn search(&self, from: Oid, to: Oid, params: &RouteParams) -> Result<Route, RouteError> {
+        let start = *self
+            .index
+            .get(&from)
+            .ok_or(RouteError::UnknownSystem(from))?;
+        let goal = *self.index.get(&to).ok_or(RouteError::UnknownSystem(to))?;
+        let target = self.nodes[goal as usize].position;
+        let rate = params.min_rate();
+        let heuristic = |node: u32| self.nodes[node as usize].position.distance(target) * rate;
+
+        // the best ticks to each node, and the previous node & the mode
+        let mut best: FxHashMap<u32, (f32, u32, bool)> = FxHashMap::default();
+        let mut open = BinaryHeap::new();
+        best.insert(start, (0.0, start, false));
+        open.push(Open {
+            estimate: heuristic(start),
+            ticks: 0.0,
+            node: start,
+        });
+
+        let mut expanded = 0;
+        while let Some(Open { ticks, node, .. }) = open.pop() {
+            if node == goal {
+                return Ok(self.trace(&best, start, goal));
+            }
+            if ticks > best[&node].0 {
+                continue;
+            }
+            expanded += 1;
+            if expanded > ROUTE_EXPANSION_BUDGET {
+                return Err(RouteError::BudgetExceeded);
+            }
+
+            let position = self.nodes[node as usize].position;
+            for (next, distance) in self.neighbors(node, params.jump_range) {
+                let next_position = self.nodes[next as usize].position;
+                let hyperspace =
+                    params.hyper_speed.is_some() && !self.interdicted(position, next_position);
+                let (hyperspace, leg) = params.leg(distance, hyperspace);
+                let ticks = ticks + leg;
+                let better = match best.get(&next) {
+                    Some((known, ..)) => ticks < *known,
+                    None => true,
+                };
+                if better {
+                    best.insert(next, (ticks, node, hyperspace));
+                    open.push(Open {
+                        estimate: ticks + heuristic(next),
+                        ticks,
+                        node: next,
+                    });
+                }
+            }
+        }
+        Err(RouteError::Unreachable)
+    }
+
+    fn trace(&self, best: &FxHashMap<u32, (f32, u32, bool)>, start: u32, goal: u32) -> Route {
+        let mut waypoints = Vec::new();
+        let mut node = goal;
+        while node != start {
+            let (eta, previous, hyperspace) = best[&node];
+            let RouteNode { id, position } = self.nodes[node as usize];
+            waypoints.push(Waypoint {
+                system: id,
+                position,
+                hyperspace,
+                eta,
+            });
+            node = previous;
+        }
+        waypoints.reverse();
+        Route { waypoints }
+    }
+}
+
+/// an entry of the open set, the lowest estimate is popped first, ties are
+/// broken by the node index so the search is deterministic
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    estimate: f32,
+    ticks: f32,
+    node: u32,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+/// the cells at the chebyshev distance `ring` from `center`, within the cells
+/// between `min` and `max`
+fn ring_cells(center: IVec3, ring: i32, min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+    let lower = (center - ring).max(min);
+    let upper = (center + ring).min(max);
+    (lower.x..=upper.x).flat_map(move |x| {
+        (lower.y..=upper.y).flat_map(move |y| {
+            // the whole column on the sides of the ring, only its ends inside
+            let side = (x - center.x).abs() == ring || (y - center.y).abs() == ring;
+            let column = side.then_some(lower.z..=upper.z).into_iter().flatten();
+            let ends = (!side)
+                .then_some([center.z - ring, center.z + ring])
+                .into_iter()
+                .flatten()
+                .filter(move |z| (lower.z..=upper.z).contains(z));
+            column.chain(ends).map(move |z| IVec3::new(x, y, z))
+        })
+    })
+}
+
+/// the solar systems and the fortifications of a map, see `RoutePlanner::rebuild`
+#[doc(hidden)]
+pub type RouteMap = (Vec<(Oid, Vec3)>, Vec<(Vec3, f32)>);
+
+/// a random map of `n` solar systems in a flat square and `n / 100`
+/// fortifications, shared by the tests and the benchmark.
+#[doc(hidden)]
+pub fn generate_route_map(seed: u64, n: u32) -> RouteMap {
+    let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
+    let size = route_map_size(n);
+    let systems = (0..n)
+        .map(|i| {
+            let position = Vec3::new(rng.gen(), rng.gen::<f32>() * 0.05, rng.gen()) * size;
+            (Oid::v5(&i.to_le_bytes()), position)
+        })
+        .collect();
+    let fortifications = (0..n / 100)
+        .map(|_| (Vec3::new(rng.gen(), 0.0, rng.gen()) * size, 10.0))
+        .collect();
+    (systems, fortifications)
+}
+
+/// the width of a map of `generate_route_map`
+#[doc(hidden)]
+pub fn route_map_size(n: u32) -> f32 {
+    (n as f32).sqrt() * 8.0
+}
+
+/// the distance from `point` to the segment between `a` and `b`
+fn segment_distance(a: Vec3, b: Vec3, point: Vec3) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(hyper_speed: Option<f32>, jump_range: f32) -> RouteParams {
+        RouteParams {
+            acceleration: 0.01,
+            max_speed: 0.5,
+            hyper_speed,
+            jump_range,
+        }
+    }
+
+    /// solar systems on the x axis every 10 map units
+    fn line(n: u32) -> (RoutePlanner, Vec<Oid>) {
+        let ids: Vec<Oid> = (0..n).map(|i| Oid::v5(&i.to_le_bytes())).collect();
+        let mut planner = RoutePlanner::default();
+        planner.rebuild(
+            ids.iter()
+                .enumerate()
+                .map(|(i, id)| (*id, Vec3::X * 10.0 * i as f32)),
+            [],
+        );
+        (planner, ids)
+    }
+
+    #[test]
+    fn jump_range_and_eta() {
+        let (mut planner, ids) = line(5);
+
+        // normal space only, every leg is 10 map units
+        let route = planner.find_route(ids[0], ids[4], &params(None, 10.0));
+        let route = route.unwrap();
+        let systems: Vec<Oid> = route.waypoints.iter().map(|w| w.system).collect();
+        assert_eq!(systems, ids[1..]);
+        assert!(route.waypoints.iter().all(|w| !w.hyperspace));
+        let leg = normal_travel_time(10.0, 0.01, 0.5);
+        assert!((route.eta() - 4.0 * leg).abs() < 1e-3);
+        assert_eq!(route.waypoints[0].eta, leg);
+
+        // a longer jump range skips the systems in between
+        let route = planner.find_route(ids[0], ids[4], &params(None, 40.0));
+        assert_eq!(route.unwrap().waypoints.len(), 1);
+
+        // out of range
+        let route = planner.find_route(ids[0], ids[4], &params(None, 5.0));
+        assert_eq!(route, Err(RouteError::Unreachable));
+        let unknown = Oid::v5(b"unknown");
+        let route = planner.find_route(ids[0], unknown, &params(None, 5.0));
+        assert_eq!(route, Err(RouteError::UnknownSystem(unknown)));
+    }
+
+    #[test]
+    fn hyperspace_and_interdiction() {
+        let (mut planner, ids) = line(5);
+        let fast = params(Some(4.0), 40.0);
+
+        // one jump through hyper space, the charge is paid once
+        let route = planner.find_route(ids[0], ids[4], &fast).unwrap();
+        assert_eq!(route.waypoints.len(), 1);
+        assert!(route.waypoints[0].hyperspace);
+        assert_eq!(route.eta(), hyper_travel_time(40.0, 4.0));
+
+        // a fortification between the 2nd and the 3rd system blocks every
+        // hyper space leg across it
+        let systems: Vec<(Oid, Vec3)> = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, Vec3::X * 10.0 * i as f32))
+            .collect();
+        planner.rebuild(systems, [(Vec3::new(15.0, 0.0, 3.0), 4.0)]);
+        assert_eq!(planner.cached(), 0);
+
+        let route = planner.find_route(ids[0], ids[4], &fast).unwrap();
+        let crossing = route
+            .waypoints
+            .iter()
+            .find(|w| w.position.x >= 20.0)
+            .unwrap();
+        assert!(!crossing.hyperspace);
+        assert!(route.eta() > hyper_travel_time(40.0, 4.0));
+        assert!(route.orders().count() >= 2);
+
+        // cached
+        assert_eq!(planner.cached(), 1);
+        assert_eq!(planner.find_route(ids[0], ids[4], &fast), Ok(route));
+    }
+
+    #[test]
+    fn cache_is_bounded() {
+        let (mut planner, ids) = line(5);
+
+        // a damaged fleet has slightly different params on every change
+        for i in 0..=ROUTE_CACHE_CAPACITY {
+            let mut params = params(None, 10.0);
+            params.acceleration -= i as f32 * 1e-6;
+            assert!(planner.find_route(ids[0], ids[4], &params).is_ok());
+            assert!(planner.cached() <= ROUTE_CACHE_CAPACITY);
+        }
+        assert_eq!(planner.cached(), 1);
+    }
+
+    #[test]
+    fn travel_order_follows_route() {
+        let mut world = World::new();
+        world.init_resource::<RoutePlanner>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_route_planner, system_fleet_routes).chain());
+
+        // the last system is beyond the jump range of the fleet
+        let ids: Vec<Oid> = (0..5_u32).map(|i| Oid::v5(&i.to_le_bytes())).collect();
+        for (i, id) in ids.iter().enumerate() {
+            let position = Vec3::X * 30.0 * i as f32;
+            world.spawn((
+                *id,
+                Transform::from_translation(position),
+                SolarSystemMarker,
+            ));
+        }
+        let stats = FleetStats {
+            ships: 1,
+            acceleration: 0.01,
+            max_speed: 0.5,
+            hyper_speed: Some(4.0),
+            jump_range: 48.0,
+            ..default()
+        };
+        let travel = |system| FleetOrder::Travel { system };
+        let fleet = world
+            .spawn((
+                Oid::v5(b"fleet"),
+                Transform::from_translation(Vec3::new(2.0, 1.0, 0.0)),
+                stats,
+                OrderQueue::new([travel(ids[4]), FleetOrder::Hold { ticks: None }]),
+                FleetMarker,
+            ))
+            .id();
+        schedule.run(&mut world);
+
+        let route = world
+            .resource_mut::<RoutePlanner>()
+            .find_route(ids[0], ids[4], &RouteParams::new(&stats))
+            .unwrap();
+        assert_eq!(route.waypoints.last().unwrap().system, ids[4]);
+        let orders: Vec<FleetOrder> = world
+            .get::<OrderQueue>(fleet)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        let expected: Vec<FleetOrder> = route
+            .orders()
+            .chain([FleetOrder::Hold { ticks: None }])
+            .collect();
+        assert_eq!(orders, expected);
+
+        // an unknown system, straight there
+        let unknown = Oid::v5(b"unknown");
+        world
+            .get_mut::<OrderQueue>(fleet)
+            .unwrap()
+            .replace(travel(unknown));
+        schedule.run(&mut world);
+        let orders: Vec<FleetOrder> = world
+            .get::<OrderQueue>(fleet)
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(
+            orders,
+            vec![FleetOrder::MoveToSystem {
+                system: unknown,
+                hyperspace: true
+            }]
+        );
+    }
+
+    #[test]
+    fn nearest_on_grid() {
+        let (systems, _) = generate_route_map(7, 2000);
+        let size = route_map_size(2000);
+        let mut planner = RoutePlanner::default();
+        planner.rebuild(systems.clone(), []);
+
+        let linear = |position: Vec3| {
+            systems
+                .iter()
+                .map(|(id, p)| (p.distance(position), *id))
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+                .map(|(_, id)| id)
+        };
+        let mut rng = Xoshiro256StarStar::seed_from_u64(7);
+        for _ in 0..500 {
+            // some points are far out of the map
+            let position = (Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 3.0 - 1.0) * size;
+            assert_eq!(planner.nearest(position), linear(position), "{}", position);
+        }
+
+        // a tie is broken by `Oid`
+        let (planner, ids) = line(2);
+        let first = ids[0].min(ids[1]);
+        assert_eq!(planner.nearest(Vec3::X * 5.0), Some(first));
+        assert_eq!(RoutePlanner::default().nearest(Vec3::ZERO), None);
+    }
+
+    #[test]
+    fn searches_per_tick_are_capped() {
+        let mut world = World::new();
+        world.init_resource::<RoutePlanner>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_route_planner, system_fleet_routes).chain());
+
+        let ids: Vec<Oid> = (0..5_u32).map(|i| Oid::v5(&i.to_le_bytes())).collect();
+        for (i, id) in ids.iter().enumerate() {
+            let position = Vec3::X * 30.0 * i as f32;
+            world.spawn((
+                *id,
+                Transform::from_translation(position),
+                SolarSystemMarker,
+            ));
+        }
+        // a different jump range for every fleet, no route is shared
+        let fleets: Vec<Entity> = (0..ROUTE_SEARCHES_PER_TICK + 2)
+            .map(|i| {
+                let stats = FleetStats {
+                    ships: 1,
+                    acceleration: 0.01,
+                    max_speed: 0.5,
+                    jump_range: 40.0 + i as f32,
+                    ..default()
+                };
+                let queue = OrderQueue::new([FleetOrder::Travel { system: ids[4] }]);
+                let id = Oid::v5(&(i as u32).to_le_bytes());
+                world
+                    .spawn((id, Transform::default(), stats, queue, FleetMarker))
+                    .id()
+            })
+            .collect();
+        let waiting = |world: &World| {
+            fleets
+                .iter()
+                .filter(|fleet| {
+                    let queue = world.get::<OrderQueue>(**fleet).unwrap();
+                    matches!(queue.current(), Some(FleetOrder::Travel { .. }))
+                })
+                .count()
+        };
+
+        schedule.run(&mut world);
+        assert_eq!(
+            world.resource::<RoutePlanner>().cached(),
+            ROUTE_SEARCHES_PER_TICK
+        );
+        assert_eq!(waiting(&world), 2);
+        schedule.run(&mut world);
+        assert_eq!(waiting(&world), 0);
+    }
+
+    #[test]
+    fn ten_thousand_systems() {
+        let n = 10_000;
+        let (systems, fortifications) = generate_route_map(48, n);
+        let size = route_map_size(n);
+
+        // between the nearest systems to two opposite corners
+        let corner = |target: Vec3| {
+            systems
+                .iter()
+                .min_by(|a, b| a.1.distance(target).total_cmp(&b.1.distance(target)))
+                .unwrap()
+                .0
+        };
+        let from = corner(Vec3::ZERO);
+        let to = corner(Vec3::new(size, 0.0, size));
+
+        let mut planner = RoutePlanner::default();
+        planner.rebuild(systems.clone(), fortifications.clone());
+        let route = planner.find_route(from, to, &params(Some(2.0), 30.0));
+        let route = route.unwrap();
+
+        assert_eq!(route.waypoints.last().unwrap().system, to);
+        let direct = size * std::f32::consts::SQRT_2;
+        assert!(route.eta() >= direct * params(Some(2.0), 30.0).min_rate());
+
+        // the same map in another order gives the same route
+        let mut again = RoutePlanner::default();
+        again.rebuild(systems.into_iter().rev(), fortifications);
+        assert_eq!(
+            again.find_route(from, to, &params(Some(2.0), 30.0)),
+            Ok(route)
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// a ship class describe the size or technology involved in the ship
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ShipClass {
     Frigate,      // 军用船只，护卫舰
     Destroyer,    // 军用船只，驱逐舰
@@ -9,18 +12,127 @@ pub enum ShipClass {
     Special,      // 特殊船只
 }
 
-/// Basic: Power, Engine, Command, Armor, Weapon, Science, Engineering, Storage.
+impl ShipClass {
+    pub const ALL: [ShipClass; 7] = [
+        Self::Frigate,
+        Self::Destroyer,
+        Self::Cruiser,
+        Self::Battleship,
+        Self::LargeCarrier,
+        Self::MultiPurpose,
+        Self::Special,
+    ];
+}
 
-/// Slot: Engine, Power, General, Armor[outer], Armor[inner], Weapon [], Weapon
-/// [M]
+/// the kind of slot in a ship template, each slot holds one module, see
+/// `ModuleKind::fits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SlotKind {
+    Engine,
+    Power,
+    General,
+    ArmorOuter,
+    ArmorInner,
+    Weapon,
+}
 
-/// a ship template describe the skeleton of the ship
+impl SlotKind {
+    pub const ALL: [SlotKind; 6] = [
+        Self::Engine,
+        Self::Power,
+        Self::General,
+        Self::ArmorOuter,
+        Self::ArmorInner,
+        Self::Weapon,
+    ];
+}
+
+/// a ship template describe the skeleton of the ship, templates are defined in
+/// ship data files, see `ShipCatalog`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ShipTemplate {
+    name: String,
+    class: ShipClass,
     base_mass: f32,
     integrity: f32,
-    slot_e: i32,
-    slot_p: i32,
-    slot_g: i32,
-    slot_a: i32,
-    slot_w: i32,
+    #[serde(default, rename = "engine_slots")]
+    slot_e: u32,
+    #[serde(default, rename = "power_slots")]
+    slot_p: u32,
+    #[serde(default, rename = "general_slots")]
+    slot_g: u32,
+    #[serde(default, rename = "armor_outer_slots")]
+    slot_ao: u32,
+    #[serde(default, rename = "armor_inner_slots")]
+    slot_ai: u32,
+    #[serde(default, rename = "weapon_slots")]
+    slot_w: u32,
+}
+
+impl ShipTemplate {
+    /// a template of `class` without any slot
+    pub fn new(name: impl Into<String>, class: ShipClass, base_mass: f32, integrity: f32) -> Self {
+        Self {
+            name: name.into(),
+            class,
+            base_mass,
+            integrity,
+            slot_e: 0,
+            slot_p: 0,
+            slot_g: 0,
+            slot_ao: 0,
+            slot_ai: 0,
+            slot_w: 0,
+        }
+    }
+
+    /// set the number of slots of `kind`
+    pub fn with_slots(mut self, kind: SlotKind, count: u32) -> Self {
+        *self.slot_mut(kind) = count;
+        self
+    }
+
+    /// the unique name of the template
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn class(&self) -> ShipClass {
+        self.class
+    }
+
+    /// the mass of the skeleton without any module, before the hull size of
+    /// the class is applied
+    pub fn base_mass(&self) -> f32 {
+        self.base_mass
+    }
+
+    /// the structure points of the skeleton, before the hull size of the
+    /// class is applied
+    pub fn integrity(&self) -> f32 {
+        self.integrity
+    }
+
+    /// the number of slots of `kind`
+    pub fn slots(&self, kind: SlotKind) -> u32 {
+        match kind {
+            SlotKind::Engine => self.slot_e,
+            SlotKind::Power => self.slot_p,
+            SlotKind::General => self.slot_g,
+            SlotKind::ArmorOuter => self.slot_ao,
+            SlotKind::ArmorInner => self.slot_ai,
+            SlotKind::Weapon => self.slot_w,
+        }
+    }
+
+    fn slot_mut(&mut self, kind: SlotKind) -> &mut u32 {
+        match kind {
+            SlotKind::Engine => &mut self.slot_e,
+            SlotKind::Power => &mut self.slot_p,
+            SlotKind::General => &mut self.slot_g,
+            SlotKind::ArmorOuter => &mut self.slot_ao,
+            SlotKind::ArmorInner => &mut self.slot_ai,
+            SlotKind::Weapon => &mut self.slot_w,
+        }
+    }
 }
@@ -0,0 +1,264 @@
+//! the statistics of a ship derived from its design, read by the designer UI,
+//! the AI and the combat.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::catalog::ShipCatalog;
+use super::class::ClassRules;
+use super::damage::ShipHealth;
+use super::design::ShipDesign;
+use super::module::ModuleEffect;
+use super::ship::SlotKind;
+use crate::map::SPEED_OF_LIGHT;
+
+/// the acceleration at which the maximum normal space speed is half of the
+/// speed of light, in map unit per tick².
+pub const HALF_LIGHT_SPEED_ACCELERATION: f32 = 0.01;
+
+/// the maximum speed in normal space of any ship
+pub const MAX_NORMAL_SPEED: f32 = 0.99 * SPEED_OF_LIGHT;
+
+/// the jump range of a ship without fuel tank, in map unit
+pub const BASE_JUMP_RANGE: f32 = 24.0;
+
+/// a system to recompute the `ShipStats` of ships whose design or damage is
+/// added, changed or removed, or of all ships if the `ShipCatalog` changed.
+#[allow(clippy::type_complexity)]
+pub fn system_ship_stats(
+    mut commands: Commands,
+    catalog: Res<ShipCatalog>,
+    mut query: Query<(
+        Entity,
+        Ref<ShipDesign>,
+        Option<Ref<ShipHealth>>,
+        Option<&mut ShipStats>,
+    )>,
+    mut repaired: RemovedComponents<ShipHealth>,
+) {
+    let repaired: HashSet<Entity> = repaired.iter().collect();
+    for (entity, design, health, stats) in query.iter_mut() {
+        let damaged = match &health {
+            Some(health) => health.is_changed(),
+            None => repaired.contains(&entity),
+        };
+        if design.is_changed() || damaged || catalog.is_changed() {
+            let rules = catalog.rules(design.template.class());
+            let disabled = health.as_ref().map_or(&[][..], |h| &h.disabled[..]);
+            let new = ShipStats::from_damaged_design(&design, rules, disabled);
+            match stats {
+                Some(mut stats) => stats.set_if_neq(new),
+                None => {
+                    commands.entity(entity).insert(new);
+                }
+            }
+        }
+    }
+}
+
+/// the armor points of each layer, the outer layer is hit first
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ArmorLayers {
+    pub outer: f32,
+    pub inner: f32,
+}
+
+/// the statistics of a ship, see `ShipStats::from_design`.
+///
+/// CON data, reconstructed from the `ShipDesign`
+#[derive(Component, Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct ShipStats {
+    /// the mass of the template and all modules
+    pub mass: f32,
+    /// in map unit per tick²
+    pub acceleration: f32,
+    /// the maximum speed in normal space, always below the speed of light
+    pub max_speed: f32,
+    /// the speed in hyper space, at least the speed of light. `None` if the
+    /// ship has no hyper drive, or its class can't enter hyper space.
+    pub hyper_speed: Option<f32>,
+    /// the power output minus the power draw of all working modules
+    pub power_surplus: f32,
+    /// the structure points of the template scaled by the hull size
+    pub integrity: f32,
+    pub armor: ArmorLayers,
+    pub weapon_dps: f32,
+    pub sensor_range: f32,
+    pub cargo: f32,
+    /// the longest leg of a route without refueling, `BASE_JUMP_RANGE` plus
+    /// the fuel of all modules
+    pub jump_range: f32,
+}
+
+impl ShipStats {
+    /// compute the statistics of a design under `rules`, the rules of the
+    /// template class. the design is not validated.
+    pub fn from_design(design: &ShipDesign, rules: &ClassRules) -> Self {
+        Self::from_damaged_design(design, rules, &[])
+    }
+
+    /// compute the statistics of a damaged design, the effects of the
+    /// `disabled` modules are ignored except the armor. see `ShipHealth`.
+    pub fn from_damaged_design(
+        design: &ShipDesign,
+        rules: &ClassRules,
+        disabled: &[usize],
+    ) -> Self {
+        let mass = design.mass(rules);
+        let mut stats = Self {
+            mass,
+            integrity: design.template.integrity() * rules.hull_size,
+            jump_range: BASE_JUMP_RANGE,
+            ..default()
+        };
+
+        let mut thrust = 0.0;
+        let mut hyper_drive = 0.0;
+        for (i, (slot, module)) in design.modules.iter().enumerate() {
+            let working = !disabled.contains(&i);
+            if working {
+                stats.power_surplus += module.power;
+            }
+            for effect in module.effects.iter() {
+                if !working && !matches!(effect, ModuleEffect::Armor(_)) {
+                    continue;
+                }
+                match *effect {
+                    ModuleEffect::Thrust(value) => thrust += value,
+                    ModuleEffect::HyperDrive(value) => hyper_drive += value,
+                    ModuleEffect::Armor(value) => match slot {
+                        SlotKind::ArmorInner => stats.armor.inner += value,
+                        _ => stats.armor.outer += value,
+                    },
+                    ModuleEffect::Firepower(value) => stats.weapon_dps += value,
+                    ModuleEffect::Sensor(value) => {
+                        stats.sensor_range = stats.sensor_range.max(value)
+                    }
+                    ModuleEffect::Cargo(value) => stats.cargo += value,
+                    ModuleEffect::Fuel(value) => stats.jump_range += value,
+                    ModuleEffect::Repair(_) | ModuleEffect::Command(_) => {}
+                }
+            }
+        }
+
+        if mass > 0.0 {
+            stats.acceleration = thrust / mass;
+            stats.max_speed = max_normal_speed(stats.acceleration);
+            if hyper_drive > 0.0 && rules.hyperspace {
+                stats.hyper_speed = Some(SPEED_OF_LIGHT * (1.0 + hyper_drive / mass));
+            }
+        }
+        stats
+    }
+}
+
+/// the maximum normal space speed of a ship with `acceleration`, approaches
+/// the speed of light but never exceeds `MAX_NORMAL_SPEED`.
+pub fn max_normal_speed(acceleration: f32) -> f32 {
+    let acceleration = acceleration.max(0.0);
+    let speed = SPEED_OF_LIGHT * acceleration / (acceleration + HALF_LIGHT_SPEED_ACCELERATION);
+    speed.min(MAX_NORMAL_SPEED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fleet::module::{ModuleKind, ShipModule};
+    use crate::fleet::ship::{ShipClass, ShipTemplate};
+
+    fn module(kind: ModuleKind, effects: Vec<ModuleEffect>) -> ShipModule {
+        ShipModule {
+            name: format!("{:?}", kind),
+            kind,
+            mass: 25.0,
+            power: 0.0,
+            cost: 0,
+            effects,
+        }
+    }
+
+    fn design(thrust: f32) -> ShipDesign {
+        let template = ShipTemplate::new("scout", ShipClass::Special, 100.0, 80.0);
+        let engine = module(
+            ModuleKind::Engine,
+            vec![
+                ModuleEffect::Thrust(thrust),
+                ModuleEffect::HyperDrive(150.0),
+            ],
+        );
+        ShipDesign::new("scout", template)
+            .with_module(SlotKind::Engine, engine)
+            .with_module(
+                SlotKind::ArmorOuter,
+                module(ModuleKind::Armor, vec![ModuleEffect::Armor(10.0)]),
+            )
+            .with_module(
+                SlotKind::ArmorInner,
+                module(ModuleKind::Armor, vec![ModuleEffect::Armor(5.0)]),
+            )
+            .with_module(
+                SlotKind::General,
+                module(
+                    ModuleKind::Science,
+                    vec![ModuleEffect::Sensor(3.0), ModuleEffect::Sensor(7.0)],
+                ),
+            )
+    }
+
+    #[test]
+    fn stats_from_design() {
+        let stats = ShipStats::from_design(&design(2.0), ShipClass::Special.rules());
+        assert_eq!(stats.mass, 200.0);
+        assert_eq!(stats.acceleration, 0.01);
+        assert!((stats.max_speed - SPEED_OF_LIGHT / 2.0).abs() < 1e-6);
+        assert_eq!(stats.hyper_speed, Some(SPEED_OF_LIGHT * 1.75));
+        assert_eq!(stats.integrity, 80.0);
+        assert_eq!(
+            stats.armor,
+            ArmorLayers {
+                outer: 10.0,
+                inner: 5.0
+            }
+        );
+        assert_eq!(stats.sensor_range, 7.0);
+        assert_eq!(stats.weapon_dps, 0.0);
+        assert_eq!(stats.jump_range, BASE_JUMP_RANGE);
+
+        // fuel tanks extend the jump range
+        let tank = module(ModuleKind::Storage, vec![ModuleEffect::Fuel(20.0)]);
+        let fueled = design(2.0)
+            .with_module(SlotKind::General, tank.clone())
+            .with_module(SlotKind::General, tank);
+        let stats = ShipStats::from_design(&fueled, ShipClass::Special.rules());
+        assert_eq!(stats.jump_range, BASE_JUMP_RANGE + 40.0);
+
+        // capped below the speed of light, however powerful the engine is
+        let stats = ShipStats::from_design(&design(1e9), ShipClass::Special.rules());
+        assert_eq!(stats.max_speed, MAX_NORMAL_SPEED);
+        assert!(max_normal_speed(-1.0) == 0.0);
+    }
+
+    #[test]
+    fn recompute_on_change() {
+        let mut world = World::new();
+        world.init_resource::<ShipCatalog>();
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_ship_stats);
+
+        let ship = world.spawn(design(2.0)).id();
+        schedule.run(&mut world);
+        assert_eq!(world.get::<ShipStats>(ship).unwrap().acceleration, 0.01);
+
+        world
+            .get_mut::<ShipDesign>(ship)
+            .unwrap()
+            .modules
+            .truncate(1);
+        schedule.run(&mut world);
+        let stats = world.get::<ShipStats>(ship).unwrap();
+        assert_eq!(stats.mass, 125.0);
+        assert_eq!(stats.armor, ArmorLayers::default());
+    }
+}
@@ -1,16 +1,75 @@
 #![allow(mixed_script_confusables)]
 
+pub mod faction;
 pub mod fleet;
 pub mod gen;
 pub mod map;
+pub mod save;
 pub mod utils;
 
-use bevy::prelude::{App, Plugin};
+use bevy::prelude::{App, CoreSchedule, IntoSystemAppConfig, IntoSystemConfig, Plugin};
+use fleet::catalog::ShipDataPlugin;
+use fleet::combat::{system_combat, CombatLog};
+use fleet::damage::{system_ship_destruction, system_ship_repair};
+use fleet::fleet::system_fleet_stats;
+use fleet::movement::{system_fleet_movement, FleetArrived};
+use fleet::order::system_fleet_orders;
+use fleet::route::{system_fleet_routes, system_route_planner, RoutePlanner};
+use fleet::stats::system_ship_stats;
+use map::rapier_collider::{system_collision_step, RapierCollisionPlugin};
+use save::autosave::AutosavePlugin;
+use utils::time::{system_game_time_advance, GameTime};
 
-struct BevyInterstellarServerPlugin;
+pub struct BevyInterstellarServerPlugin;
 
 impl Plugin for BevyInterstellarServerPlugin {
     fn build(&self, app: &mut App) {
-        todo!()
+        app.add_plugin(RapierCollisionPlugin)
+            .add_plugin(AutosavePlugin)
+            .add_plugin(ShipDataPlugin)
+            .init_resource::<GameTime>()
+            .add_system(system_game_time_advance.in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(system_ship_stats.in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(
+                system_fleet_stats
+                    .after(system_ship_stats)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .init_resource::<RoutePlanner>()
+            .add_system(system_route_planner.in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(
+                system_fleet_routes
+                    .after(system_route_planner)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_event::<FleetArrived>()
+            .add_system(
+                system_fleet_orders
+                    .after(system_fleet_routes)
+                    .before(system_fleet_movement)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                system_fleet_movement
+                    .after(system_fleet_stats)
+                    .before(system_collision_step)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_event::<CombatLog>()
+            .add_system(
+                system_combat
+                    .after(system_collision_step)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                system_ship_destruction
+                    .after(system_combat)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                system_ship_repair
+                    .after(system_ship_destruction)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
     }
 }
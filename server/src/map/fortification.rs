@@ -0,0 +1,59 @@
+//! a `Fortification` is a station in the hyper space, it intercepts fleets
+//! entering its interdiction sphere.
+//! # components
+//! - Oid
+//! - Transform (and GlobalTransform): the translation in the L3 map
+//! - InterdictionRadius: the radius of the interdiction sphere
+//! - RapierCollider: the handle for rapier physics engine
+//! - FortificationMarker: a marker component to indicate this is a
+//!   fortification
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::oid::Oid;
+
+/// a marker for hyper space fortification
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FortificationMarker;
+
+/// the radius of the interdiction sphere of a fortification in L3 map
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd)]
+pub struct InterdictionRadius(f32);
+
+impl InterdictionRadius {
+    pub fn new(radius: f32) -> Self {
+        Self(radius)
+    }
+}
+
+impl From<InterdictionRadius> for f32 {
+    fn from(radius: InterdictionRadius) -> f32 {
+        radius.0
+    }
+}
+
+/// the object-oriented representation of a fortification, used for
+/// serialization.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FortificationSerde {
+    pub id: Oid,
+    pub transform: Transform,
+    pub radius: InterdictionRadius,
+}
+
+impl FortificationSerde {
+    /// spawn the fortification entity in the L3 map, the collider is assigned
+    /// by `rebuild_collision_world`.
+    pub fn spawn(&self, commands: &mut Commands) -> Entity {
+        commands
+            .spawn((
+                self.id,
+                self.transform,
+                self.radius,
+                GlobalTransform::default(),
+                FortificationMarker,
+            ))
+            .id()
+    }
+}
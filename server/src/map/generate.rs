@@ -1,3 +1,6 @@
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+
 pub enum GalaxyShape {
     PARTIAL,
     DISC,
@@ -9,3 +12,21 @@ pub struct MapGenArgs {
     galaxy_shape: GalaxyShape,
     galaxy_size: u32,
 }
+
+/// the seed used to generate the current galaxy.
+///
+/// S/L data
+#[derive(Resource, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct GalaxySeed(u64);
+
+impl GalaxySeed {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+}
+
+impl From<GalaxySeed> for u64 {
+    fn from(seed: GalaxySeed) -> u64 {
+        seed.0
+    }
+}
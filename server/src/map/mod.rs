@@ -13,12 +13,30 @@
 //!   interaction is ignored.
 
 pub mod astronomy;
+pub mod fortification;
 pub mod generate;
 pub mod rapier_collider;
 pub mod solar_system;
+pub mod spatial_index;
 pub mod star;
 
-use bevy::prelude::{Commands, ResMut};
+use bevy::prelude::{Commands, Component, ResMut};
+use serde::{Deserialize, Serialize};
+
+/// the speed of light in L3 map, in map unit per tick. it's the maximum speed
+/// in normal space and the minimum speed in hyper space.
+pub const SPEED_OF_LIGHT: f32 = 1.0;
+
+/// the layer of the map a game object is in.
+///
+/// S/L data
+#[derive(Component, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum SpaceLayer {
+    /// the normal space, the maximum speed limit is light
+    Normal,
+    /// the hyper space, the minimum speed limit is light
+    Hyper,
+}
 
 // use self::solar_system::SolarSystemGenerator;
 
@@ -1,71 +1,353 @@
 use bevy::prelude::*;
-use once_cell::unsync::Lazy;
-use rapier3d::prelude::*;
+use physics::logic::collision::{
+    self, ColliderRef, CollisionEngine, CollisionHandle, CollisionLayers, CollisionShape,
+    ContactEvent,
+};
+
+use super::astronomy::AstroRadius;
+use super::fortification::{FortificationMarker, InterdictionRadius};
+use super::solar_system::SolarSystemMarker;
+use super::SpaceLayer;
+use crate::fleet::fleet::{FleetMarker, FleetRadius};
+
+//////////////////////// Plugin ////////////////////////
+
+/// a plugin for the collision detection at L3 map, the engine is stepped once
+/// per simulation tick.
+pub struct RapierCollisionPlugin;
+
+impl Plugin for RapierCollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RapierCollisionEngine>()
+            .add_event::<ProximityStarted>()
+            .add_event::<ProximityStopped>()
+            .add_event::<RebuildCollisionWorld>()
+            .add_systems(
+                (system_rebuild_collision_world, system_collision_step)
+                    .chain()
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+/// an exclusive system to rebuild the collision world if requested by
+/// `RebuildCollisionWorld`.
+///
+/// schedule requirement:
+/// - must run before `system_collision_step`
+pub fn system_rebuild_collision_world(world: &mut World) {
+    let requested = world
+        .resource_mut::<Events<RebuildCollisionWorld>>()
+        .drain()
+        .count();
+    if requested > 0 {
+        rebuild_collision_world(world);
+    }
+}
+
+/// replace the `RapierCollisionEngine` by a new one populated from all solar
+/// systems, fleets and fortifications in the world, and reassign their
+/// `RapierCollider`. return the number of colliders.
+///
+/// the intersections are forgotten, so `ProximityStarted` is sent again for
+/// the intersecting objects in the next step.
+pub fn rebuild_collision_world(world: &mut World) -> usize {
+    let mut engine = RapierCollisionEngine::new();
+    let mut colliders = Vec::new();
+
+    let mut solar_systems =
+        world.query_filtered::<(Entity, &Transform, &AstroRadius), With<SolarSystemMarker>>();
+    for (entity, transform, radius) in solar_systems.iter(world) {
+        let collider = engine.spawn_solar_system(entity, transform.translation, (*radius).into());
+        colliders.push((entity, collider));
+    }
+
+    let mut fleets = world
+        .query_filtered::<(Entity, &Transform, &FleetRadius, &SpaceLayer), With<FleetMarker>>();
+    for (entity, transform, radius, layer) in fleets.iter(world) {
+        let collider = engine.spawn_fleet(entity, transform.translation, (*radius).into(), *layer);
+        colliders.push((entity, collider));
+    }
+
+    let mut fortifications = world
+        .query_filtered::<(Entity, &Transform, &InterdictionRadius), With<FortificationMarker>>();
+    for (entity, transform, radius) in fortifications.iter(world) {
+        let collider = engine.spawn_fortification(entity, transform.translation, (*radius).into());
+        colliders.push((entity, collider));
+    }
+
+    // the colliders of the old engine are invalid now
+    let stale: Vec<Entity> = world
+        .query_filtered::<Entity, With<RapierCollider>>()
+        .iter(world)
+        .collect();
+    for entity in stale {
+        world.entity_mut(entity).remove::<RapierCollider>();
+    }
+    for (entity, collider) in colliders {
+        world.entity_mut(entity).insert(collider);
+    }
+
+    let assigned = world.query::<&RapierCollider>().iter(world).count();
+    if assigned != engine.len() {
+        error!(
+            "[physics] rebuild with {} colliders but {} assigned",
+            engine.len(),
+            assigned
+        );
+    }
+
+    info!(
+        "[physics] rebuild collision world, {} colliders",
+        engine.len()
+    );
+    let len = engine.len();
+    world.insert_resource(engine);
+    len
+}
+
+/// a system to run one step of collision simulation, and send the
+/// intersections started or stopped in this step as events.
+///
+/// schedule requirement:
+/// - must run after any `update_transform` of the tick
+/// - must run before any query of intersections of the tick
+pub fn system_collision_step(
+    mut engine: ResMut<RapierCollisionEngine>,
+    mut started: EventWriter<ProximityStarted>,
+    mut stopped: EventWriter<ProximityStopped>,
+) {
+    engine.step();
+    started.send_batch(engine.started.drain(..));
+    stopped.send_batch(engine.stopped.drain(..));
+}
+
+//////////////////////// Event ////////////////////////
+
+/// send to rebuild the collision world from the ECS state at the next tick,
+/// e.g. to recover from a desync.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RebuildCollisionWorld;
+
+/// the kind of game object owning a collider, it is derived from the layers
+/// of the collider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ColliderKind {
+    Fleet = 1,
+    SolarSystem = 2,
+    /// a hyper space fortification, its collider is the interdiction sphere
+    Fortification = 3,
+}
+
+impl ColliderKind {
+    fn from_layers(layers: CollisionLayers) -> Option<Self> {
+        match layers.memberships {
+            LAYER_NORMAL_FLEET | LAYER_HYPER_FLEET => Some(Self::Fleet),
+            LAYER_SOLAR_SYSTEM => Some(Self::SolarSystem),
+            LAYER_FORTIFICATION => Some(Self::Fortification),
+            _ => None,
+        }
+    }
+}
+
+/// a game object owning a collider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CollisionObject {
+    pub entity: Entity,
+    pub kind: ColliderKind,
+}
+
+impl CollisionObject {
+    fn from_collider(collider: ColliderRef) -> Option<Self> {
+        let object = ColliderKind::from_layers(collider.layers).map(|kind| Self {
+            entity: collider.entity,
+            kind,
+        });
+        if object.is_none() {
+            error!("[physics] unknown collider {:?}", collider);
+        }
+        object
+    }
+}
+
+/// the result of a swept query, see `RapierCollisionEngine::sweep`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepHit {
+    /// the game object hit
+    pub object: CollisionObject,
+    /// the time of impact, as a fraction of the motion in [0, 1]
+    pub toi: f32,
+    /// the position of the moving collider at the time of impact
+    pub position: Vec3,
+}
+
+impl SweepHit {
+    fn from_hit(hit: collision::SweepHit) -> Option<Self> {
+        Some(Self {
+            object: CollisionObject::from_collider(hit.other)?,
+            toi: hit.toi,
+            position: hit.position,
+        })
+    }
+}
+
+/// sent once when two game objects start to intersect, e.g. a fleet enters a
+/// solar system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProximityStarted(pub CollisionObject, pub CollisionObject);
+
+/// sent once when two game objects stop intersecting, e.g. a fleet leaves a
+/// solar system, or one of them is removed from the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProximityStopped(pub CollisionObject, pub CollisionObject);
+
+//////////////////////// Collision Layer ////////////////////////
+
+// the interaction rules between layers, see the `map` module.
+// - normal space: fleet-fleet, fleet-solar system
+// - hyper space: fleet-fortification
+
+const LAYER_NORMAL_FLEET: u32 = 1 << 0;
+const LAYER_SOLAR_SYSTEM: u32 = 1 << 1;
+const LAYER_HYPER_FLEET: u32 = 1 << 2;
+const LAYER_FORTIFICATION: u32 = 1 << 3;
+
+const NORMAL_FLEET_LAYERS: CollisionLayers =
+    CollisionLayers::new(LAYER_NORMAL_FLEET, LAYER_NORMAL_FLEET | LAYER_SOLAR_SYSTEM);
+const HYPER_FLEET_LAYERS: CollisionLayers =
+    CollisionLayers::new(LAYER_HYPER_FLEET, LAYER_FORTIFICATION);
+const SOLAR_SYSTEM_LAYERS: CollisionLayers =
+    CollisionLayers::new(LAYER_SOLAR_SYSTEM, LAYER_NORMAL_FLEET);
+const FORTIFICATION_LAYERS: CollisionLayers =
+    CollisionLayers::new(LAYER_FORTIFICATION, LAYER_HYPER_FLEET);
+
+fn fleet_layers(layer: SpaceLayer) -> CollisionLayers {
+    match layer {
+        SpaceLayer::Normal => NORMAL_FLEET_LAYERS,
+        SpaceLayer::Hyper => HYPER_FLEET_LAYERS,
+    }
+}
+
+/// the collision layers of a game object of `kind` in `layer`, solar systems
+/// are always in normal space and fortifications in hyper space.
+pub(super) fn collision_layers(kind: ColliderKind, layer: SpaceLayer) -> CollisionLayers {
+    match kind {
+        ColliderKind::Fleet => fleet_layers(layer),
+        ColliderKind::SolarSystem => SOLAR_SYSTEM_LAYERS,
+        ColliderKind::Fortification => FORTIFICATION_LAYERS,
+    }
+}
+
+/// a filter of spatial queries, only colliders of `kind` are included, or all
+/// colliders if `None`.
+fn kind_filter(kind: Option<ColliderKind>) -> CollisionLayers {
+    match kind {
+        None => CollisionLayers::ALL,
+        Some(ColliderKind::Fleet) => {
+            CollisionLayers::selecting(LAYER_NORMAL_FLEET | LAYER_HYPER_FLEET)
+        }
+        Some(ColliderKind::SolarSystem) => CollisionLayers::selecting(LAYER_SOLAR_SYSTEM),
+        Some(ColliderKind::Fortification) => CollisionLayers::selecting(LAYER_FORTIFICATION),
+    }
+}
 
 //////////////////////// Component ////////////////////////
 
 /// a handle for collision detection, used at L3 map.
 ///
-/// should always reconstruct on reload
+/// should always reconstruct on reload, see `rebuild_collision_world`
 #[derive(Component, Debug, Copy, Clone)]
-pub struct RapierCollider(ColliderHandle);
+pub struct RapierCollider(CollisionHandle);
 
 //////////////////////// Resource ////////////////////////
 
-/// the engine for collision detection, used at L3 map.
+/// the engine for collision detection at L3 map, it applies the interaction
+/// rules of game objects on the `CollisionEngine` of the `physics` crate.
 ///
-/// should always reconstruct on reload
-#[derive(Default)]
+/// should always reconstruct on reload, see `rebuild_collision_world`
+#[derive(Resource, Default)]
 pub struct RapierCollisionEngine {
-    pipeline: CollisionPipeline,
-
-    colliders: ColliderSet,
-    narrow_phase: NarrowPhase,
-    broad_phase: BroadPhase,
+    engine: CollisionEngine,
+    started: Vec<ProximityStarted>,
+    stopped: Vec<ProximityStopped>,
 }
 
-// TODO: use collision group & user data
 impl RapierCollisionEngine {
     /// create a empty collision engine.
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// pre-condition: collider.user_date() the lower 64 bits must be
-    /// entity bits.
-    fn spawn(&mut self, collider: Collider) -> RapierCollider {
-        let user_data = collider.user_data;
-        let handle = RapierCollider(self.colliders.insert(collider));
-        info!("[physics] spawn handle: {:?}, {:?}", user_data, handle);
-        handle
+    /// the number of colliders in the engine
+    pub fn len(&self) -> usize {
+        self.engine.len()
+    }
+
+    /// test if the engine contains no collider
+    pub fn is_empty(&self) -> bool {
+        self.engine.is_empty()
+    }
+
+    /// spawn a sensor ball for a game object in `layer`
+    pub(super) fn spawn_ball(
+        &mut self,
+        object: CollisionObject,
+        trans: Vec3,
+        r: f32,
+        layer: SpaceLayer,
+    ) -> RapierCollider {
+        let shape = CollisionShape::Ball { radius: r };
+        let layers = collision_layers(object.kind, layer);
+        RapierCollider(self.engine.insert(object.entity, shape, trans, layers))
     }
 
     /// spawn a RapierCollider component for solar system
     pub fn spawn_solar_system(&mut self, e: Entity, trans: Vec3, r: f32) -> RapierCollider {
-        let user_data = e.to_bits() as u128;
+        let object = CollisionObject {
+            entity: e,
+            kind: ColliderKind::SolarSystem,
+        };
+        self.spawn_ball(object, trans, r, SpaceLayer::Normal)
+    }
 
-        let collider = ColliderBuilder::ball(r)
-            .sensor(true)
-            .active_collision_types(ActiveCollisionTypes::FIXED_FIXED)
-            .user_data(user_data)
-            .translation(trans.into())
-            .build();
-        self.spawn(collider)
+    /// spawn a RapierCollider component for fleet in `layer`
+    pub fn spawn_fleet(
+        &mut self,
+        e: Entity,
+        trans: Vec3,
+        r: f32,
+        layer: SpaceLayer,
+    ) -> RapierCollider {
+        let object = CollisionObject {
+            entity: e,
+            kind: ColliderKind::Fleet,
+        };
+        self.spawn_ball(object, trans, r, layer)
     }
 
-    /// remove an object from the engine
-    pub fn remove(&mut self, handle: RapierCollider) {
-        static mut ISLAND_MANAGER: Lazy<IslandManager> = Lazy::new(IslandManager::default);
-        static mut RIGID_BODIES: Lazy<RigidBodySet> = Lazy::new(RigidBodySet::default);
+    /// spawn a RapierCollider component for hyper space fortification, `r` is
+    /// the radius of its interdiction sphere.
+    pub fn spawn_fortification(&mut self, e: Entity, trans: Vec3, r: f32) -> RapierCollider {
+        let object = CollisionObject {
+            entity: e,
+            kind: ColliderKind::Fortification,
+        };
+        self.spawn_ball(object, trans, r, SpaceLayer::Hyper)
+    }
 
-        info!("[physics] remove handle: {:?}", handle);
+    /// move a fleet collider between layers when the fleet enters or leaves
+    /// hyper space. the intersections in the old layer are stopped in the
+    /// next step.
+    ///
+    /// no effect if the handle is invalid
+    pub fn update_fleet_layer(&mut self, handle: RapierCollider, layer: SpaceLayer) {
+        self.engine.set_layers(handle.0, fleet_layers(layer));
+    }
 
-        // this is safe because no operation is done for `ISLAND_MANAGER` and
-        // `RIGID_BODIES`
-        unsafe {
-            self.colliders
-                .remove(handle.0, &mut ISLAND_MANAGER, &mut RIGID_BODIES, false);
-        }
+    /// remove an object from the engine
+    pub fn remove(&mut self, handle: RapierCollider) {
+        self.engine.remove(handle.0);
     }
 
     /// update the next position for a entity with collision handle. please only
@@ -75,54 +357,415 @@ impl RapierCollisionEngine {
     ///
     /// no effect if the handle is invalid
     pub fn update_transform(&mut self, handle: RapierCollider, dest: Vec3) {
-        if let Some(collider) = self.colliders.get_mut(handle.0) {
-            collider.set_translation(dest.into());
-        } else {
-            error!("[physics] invalid handle {:?}", handle);
-        }
+        self.engine.update_transform(handle.0, dest);
     }
 
-    /// run one step of collision simulation
+    /// run one step of collision simulation, the intersections started or
+    /// stopped in this step are collected for `system_collision_step`.
     pub fn step(&mut self) {
-        static mut RIGID_BODIES: Lazy<RigidBodySet> = Lazy::new(RigidBodySet::default);
-
-        // this is safe because no operation is done for `RIGID_BODIES`
-        unsafe {
-            self.pipeline.step(
-                1000.0,
-                &mut self.broad_phase,
-                &mut self.narrow_phase,
-                &mut RIGID_BODIES,
-                &mut self.colliders,
-                None,
-                &(),
-                &(),
-            )
+        self.engine.step();
+        for event in self.engine.drain_events() {
+            let (ContactEvent::Started(a, b) | ContactEvent::Stopped(a, b)) = event;
+            let (Some(a), Some(b)) = (
+                CollisionObject::from_collider(a),
+                CollisionObject::from_collider(b),
+            ) else {
+                continue;
+            };
+
+            match event {
+                ContactEvent::Started(..) => self.started.push(ProximityStarted(a, b)),
+                ContactEvent::Stopped(..) => self.stopped.push(ProximityStopped(a, b)),
+            }
         }
     }
 
-    /// get all intersections between game objects
-    pub fn intersections(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
-        fn to_entity(colliders: &ColliderSet, handle: ColliderHandle) -> Entity {
-            if let Some(collider) = colliders.get(handle) {
-                let entity_bits = collider.user_data as u64;
-                Entity::from_bits(entity_bits)
+    /// all entities of `kind` whose collider intersects the sphere, e.g. the
+    /// solar systems within `r` of a point.
+    ///
+    /// like other spatial queries, colliders are at their positions of the
+    /// last step.
+    pub fn intersect_sphere(
+        &self,
+        center: Vec3,
+        r: f32,
+        kind: Option<ColliderKind>,
+    ) -> Vec<Entity> {
+        self.engine.intersect_sphere(center, r, kind_filter(kind))
+    }
+
+    /// all entities of `kind` whose collider intersects the axis aligned box
+    /// between `min` and `max`, e.g. a box selection.
+    pub fn intersect_box(&self, min: Vec3, max: Vec3, kind: Option<ColliderKind>) -> Vec<Entity> {
+        self.engine.intersect_box(min, max, kind_filter(kind))
+    }
+
+    /// the nearest `k` entities of `kind` to `point`, sorted by the distance
+    /// to their collider.
+    pub fn nearest(&self, point: Vec3, k: usize, kind: Option<ColliderKind>) -> Vec<Entity> {
+        self.engine.nearest(point, k, kind_filter(kind))
+    }
+
+    /// the first entity of `kind` hit by the ray and the hit point.
+    pub fn cast_ray(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max_toi: f32,
+        kind: Option<ColliderKind>,
+    ) -> Option<(Entity, Vec3)> {
+        self.engine
+            .cast_ray(origin, dir, max_toi, kind_filter(kind))
+    }
+
+    /// continuous detection for the collider of `handle` moving linearly from
+    /// its current position to `dest`, which a single `update_transform`
+    /// may tunnel through. return the earliest object touched.
+    ///
+    /// objects of `kind` are tested regardless of the layer, e.g. the
+    /// destination solar system of a fleet in hyper space. if `kind` is
+    /// `None`, objects interacting with the collider are tested.
+    pub fn sweep(
+        &self,
+        handle: RapierCollider,
+        dest: Vec3,
+        kind: Option<ColliderKind>,
+    ) -> Option<SweepHit> {
+        let filter = kind.map(|kind| kind_filter(Some(kind)));
+        SweepHit::from_hit(self.engine.sweep(handle.0, dest, filter)?)
+    }
+
+    /// like `update_transform`, but the collider stops at the earliest object
+    /// touched during the motion, see `sweep`. the hit is returned, so the
+    /// caller can apply the rule, e.g. `system_fleet_movement` intercepts a
+    /// fleet in hyper space at the interdiction sphere of a fortification.
+    ///
+    /// the hill sphere of the destination system needs no sweep, the fleet
+    /// movement stops at it exactly, see `FleetMovement::step`.
+    pub fn update_transform_swept(
+        &mut self,
+        handle: RapierCollider,
+        dest: Vec3,
+        kind: Option<ColliderKind>,
+    ) -> Option<SweepHit> {
+        let filter = kind.map(|kind| kind_filter(Some(kind)));
+        let hit = self.engine.update_transform_swept(handle.0, dest, filter)?;
+        SweepHit::from_hit(hit)
+    }
+
+    /// get all intersections between game objects, invalid handles are
+    /// skipped.
+    pub fn intersections(&self) -> impl Iterator<Item = (CollisionObject, CollisionObject)> + '_ {
+        self.engine.intersections().filter_map(|(a, b)| {
+            Some((
+                CollisionObject::from_collider(a)?,
+                CollisionObject::from_collider(b)?,
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    fn intersections(world: &World) -> Vec<(Entity, Entity)> {
+        let mut pairs: Vec<(Entity, Entity)> = world
+            .resource::<RapierCollisionEngine>()
+            .intersections()
+            .map(|(a, b)| (a.entity.min(b.entity), a.entity.max(b.entity)))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    fn world_with_engine() -> (World, Schedule) {
+        let mut world = World::new();
+        world.init_resource::<RapierCollisionEngine>();
+        world.init_resource::<Events<ProximityStarted>>();
+        world.init_resource::<Events<ProximityStopped>>();
+        let mut schedule = Schedule::new();
+        schedule.add_system(system_collision_step);
+        (world, schedule)
+    }
+
+    fn drain<E: bevy::ecs::event::Event>(world: &mut World) -> Vec<E> {
+        world.resource_mut::<Events<E>>().drain().collect()
+    }
+
+    #[test]
+    fn spawn_update_step_remove() {
+        let (mut world, mut schedule) = world_with_engine();
+
+        let a = world.spawn_empty().id();
+        let b = world.spawn_empty().id();
+        let (handle_a, handle_b) = {
+            let mut engine = world.resource_mut::<RapierCollisionEngine>();
+            (
+                engine.spawn_solar_system(a, Vec3::ZERO, 1.0),
+                engine.spawn_fleet(b, Vec3::new(10.0, 0.0, 0.0), 1.0, SpaceLayer::Normal),
+            )
+        };
+
+        schedule.run(&mut world);
+        assert!(intersections(&world).is_empty());
+
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_transform(handle_b, Vec3::new(1.5, 0.0, 0.0));
+        schedule.run(&mut world);
+        assert_eq!(intersections(&world), [(a, b)]);
+
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .remove(handle_a);
+        schedule.run(&mut world);
+        assert!(intersections(&world).is_empty());
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 1);
+
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .remove(handle_b);
+        assert!(world.resource::<RapierCollisionEngine>().is_empty());
+    }
+
+    #[test]
+    fn proximity_events_once() {
+        let (mut world, mut schedule) = world_with_engine();
+
+        let a = world.spawn_empty().id();
+        let b = world.spawn_empty().id();
+        let (handle_a, handle_b) = {
+            let mut engine = world.resource_mut::<RapierCollisionEngine>();
+            (
+                engine.spawn_solar_system(a, Vec3::ZERO, 1.0),
+                engine.spawn_fleet(b, Vec3::new(10.0, 0.0, 0.0), 1.0, SpaceLayer::Normal),
+            )
+        };
+        let entities = |a: CollisionObject, b: CollisionObject| {
+            let (a, b) = if a.kind == ColliderKind::SolarSystem {
+                (a, b)
             } else {
-                // this is invalid entity id.
-                error!("[physics] invalid handle {:?}", handle);
-                Entity::from_raw(0)
-            }
+                (b, a)
+            };
+            assert_eq!(a.kind, ColliderKind::SolarSystem);
+            assert_eq!(b.kind, ColliderKind::Fleet);
+            (a.entity, b.entity)
+        };
+
+        // enter
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_transform(handle_b, Vec3::new(1.5, 0.0, 0.0));
+        schedule.run(&mut world);
+        let started = drain::<ProximityStarted>(&mut world);
+        assert_eq!(started.len(), 1);
+        assert_eq!(entities(started[0].0, started[0].1), (a, b));
+
+        // still inside
+        schedule.run(&mut world);
+        assert!(drain::<ProximityStarted>(&mut world).is_empty());
+        assert!(drain::<ProximityStopped>(&mut world).is_empty());
+
+        // leave
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_transform(handle_b, Vec3::new(10.0, 0.0, 0.0));
+        schedule.run(&mut world);
+        let stopped = drain::<ProximityStopped>(&mut world);
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(entities(stopped[0].0, stopped[0].1), (a, b));
+
+        // enter again, then removed
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_transform(handle_b, Vec3::new(1.5, 0.0, 0.0));
+        schedule.run(&mut world);
+        assert_eq!(drain::<ProximityStarted>(&mut world).len(), 1);
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .remove(handle_a);
+        schedule.run(&mut world);
+        let stopped = drain::<ProximityStopped>(&mut world);
+        assert_eq!(stopped.len(), 1);
+        assert_eq!(entities(stopped[0].0, stopped[0].1), (a, b));
+    }
+
+    #[test]
+    fn hyper_space_fleet_only_meets_fortification() {
+        let (mut world, mut schedule) = world_with_engine();
+
+        let [system, fleet_a, fleet_b, fortification] = [(); 4].map(|_| world.spawn_empty().id());
+        let (handle_a, handle_b) = {
+            let mut engine = world.resource_mut::<RapierCollisionEngine>();
+            engine.spawn_solar_system(system, Vec3::ZERO, 5.0);
+            engine.spawn_fortification(fortification, Vec3::ZERO, 5.0);
+            (
+                engine.spawn_fleet(fleet_a, Vec3::X, 0.5, SpaceLayer::Normal),
+                engine.spawn_fleet(fleet_b, Vec3::X * 1.5, 0.5, SpaceLayer::Normal),
+            )
+        };
+        let pair = |a: Entity, b: Entity| (a.min(b), a.max(b));
+
+        // all in normal space
+        schedule.run(&mut world);
+        let mut expected = vec![
+            pair(system, fleet_a),
+            pair(system, fleet_b),
+            pair(fleet_a, fleet_b),
+        ];
+        expected.sort();
+        assert_eq!(intersections(&world), expected);
+
+        // fleet a enters hyper space
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_fleet_layer(handle_a, SpaceLayer::Hyper);
+        schedule.run(&mut world);
+        let mut expected = vec![pair(system, fleet_b), pair(fleet_a, fortification)];
+        expected.sort();
+        assert_eq!(intersections(&world), expected);
+        assert_eq!(drain::<ProximityStopped>(&mut world).len(), 2);
+
+        // both in hyper space, no fleet-fleet interaction
+        world
+            .resource_mut::<RapierCollisionEngine>()
+            .update_fleet_layer(handle_b, SpaceLayer::Hyper);
+        schedule.run(&mut world);
+        let mut expected = vec![pair(fleet_a, fortification), pair(fleet_b, fortification)];
+        expected.sort();
+        assert_eq!(intersections(&world), expected);
+    }
+
+    #[test]
+    fn spatial_queries_by_kind() {
+        let (mut world, mut schedule) = world_with_engine();
+
+        let [system_a, system_b, fleet, fortification] = [(); 4].map(|_| world.spawn_empty().id());
+        {
+            let mut engine = world.resource_mut::<RapierCollisionEngine>();
+            engine.spawn_solar_system(system_a, Vec3::ZERO, 1.0);
+            engine.spawn_solar_system(system_b, Vec3::X * 10.0, 1.0);
+            engine.spawn_fleet(fleet, Vec3::X * 3.0, 0.5, SpaceLayer::Hyper);
+            engine.spawn_fortification(fortification, Vec3::X * 20.0, 2.0);
+        }
+        schedule.run(&mut world);
+        let engine = world.resource::<RapierCollisionEngine>();
+
+        let systems = Some(ColliderKind::SolarSystem);
+        assert_eq!(engine.intersect_sphere(Vec3::X * 3.0, 1.0, systems), []);
+        assert_eq!(engine.intersect_sphere(Vec3::X * 3.0, 1.0, None), [fleet]);
+        assert_eq!(engine.nearest(Vec3::X * 3.0, 1, systems), [system_a]);
+        assert_eq!(
+            engine.nearest(Vec3::X * 3.0, 4, None),
+            [fleet, system_a, system_b, fortification]
+        );
+
+        let mut selected = engine.intersect_box(Vec3::splat(-1.0), Vec3::splat(11.0), None);
+        selected.sort();
+        assert_eq!(selected, [system_a, system_b, fleet]);
+
+        let hit = engine.cast_ray(
+            Vec3::X * -5.0,
+            Vec3::X,
+            100.0,
+            Some(ColliderKind::Fortification),
+        );
+        assert_eq!(hit, Some((fortification, Vec3::X * 18.0)));
+    }
+
+    #[test]
+    fn hyper_space_fleet_cannot_tunnel() {
+        let (mut world, mut schedule) = world_with_engine();
+
+        let [system, fleet, fortification] = [(); 3].map(|_| world.spawn_empty().id());
+        let handle = {
+            let mut engine = world.resource_mut::<RapierCollisionEngine>();
+            engine.spawn_solar_system(system, Vec3::X * 50.0, 5.0);
+            engine.spawn_fortification(fortification, Vec3::ZERO, 5.0);
+            engine.spawn_fleet(fleet, Vec3::X * -100.0, 0.5, SpaceLayer::Hyper)
+        };
+        schedule.run(&mut world);
+        let engine = world.resource::<RapierCollisionEngine>();
+
+        // the solar system is in normal space, only the fortification is hit
+        let hit = engine.sweep(handle, Vec3::X * 100.0, None).unwrap();
+        assert_eq!(hit.object.entity, fortification);
+        assert!((hit.toi - 94.5 / 200.0).abs() < 1e-4);
+        assert!((hit.position - Vec3::X * -5.5).length() < 1e-3);
+
+        // the destination solar system, regardless of layer
+        let hit = engine.sweep(handle, Vec3::X * 100.0, Some(ColliderKind::SolarSystem));
+        assert_eq!(hit.unwrap().object.entity, system);
+
+        // intercepted, then leaving the fortification is not a hit
+        let mut engine = world.resource_mut::<RapierCollisionEngine>();
+        let hit = engine.update_transform_swept(handle, Vec3::X * 100.0, None);
+        assert_eq!(hit.unwrap().object.entity, fortification);
+        assert!(engine.sweep(handle, Vec3::X * -100.0, None).is_none());
+
+        schedule.run(&mut world);
+        let started = drain::<ProximityStarted>(&mut world);
+        assert!(started
+            .iter()
+            .any(|event| event.0.entity == fortification || event.1.entity == fortification));
+    }
+
+    #[test]
+    fn rebuild_from_ecs() {
+        let (mut world, _) = world_with_engine();
+        world.init_resource::<Events<RebuildCollisionWorld>>();
+        let mut schedule = Schedule::new();
+        schedule.add_systems((system_rebuild_collision_world, system_collision_step).chain());
+
+        let system = world
+            .spawn((
+                Transform::default(),
+                AstroRadius::new(5.0),
+                SolarSystemMarker,
+            ))
+            .id();
+        let fleet = world
+            .spawn((
+                Transform::from_xyz(1.0, 0.0, 0.0),
+                FleetRadius::new(0.5),
+                SpaceLayer::Normal,
+                FleetMarker,
+            ))
+            .id();
+        let fortification = world
+            .spawn((
+                Transform::from_xyz(100.0, 0.0, 0.0),
+                InterdictionRadius::new(5.0),
+                FortificationMarker,
+            ))
+            .id();
+        // not a game object with collider anymore
+        let stale = world.spawn_empty().id();
+        let handle =
+            RapierCollisionEngine::new().spawn_fleet(stale, Vec3::ZERO, 1.0, SpaceLayer::Normal);
+        world.entity_mut(stale).insert(handle);
+
+        assert_eq!(rebuild_collision_world(&mut world), 3);
+        assert!(world.get::<RapierCollider>(stale).is_none());
+        for entity in [system, fleet, fortification] {
+            assert!(world.get::<RapierCollider>(entity).is_some());
         }
+        schedule.run(&mut world);
+        assert_eq!(intersections(&world), [(system, fleet)]);
+
+        // desync, then recover at runtime
+        let handle = *world.get::<RapierCollider>(fleet).unwrap();
+        world.resource_mut::<RapierCollisionEngine>().remove(handle);
+        schedule.run(&mut world);
+        assert!(intersections(&world).is_empty());
+        drain::<ProximityStarted>(&mut world);
 
-        self.narrow_phase
-            .intersection_graph()
-            .interactions_with_endpoints()
-            .filter(|pair| pair.2.intersecting)
-            .map(|pair| {
-                (
-                    to_entity(&self.colliders, pair.0),
-                    to_entity(&self.colliders, pair.1),
-                )
-            })
+        world.send_event(RebuildCollisionWorld);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RapierCollisionEngine>().len(), 3);
+        assert_eq!(intersections(&world), [(system, fleet)]);
+        assert_eq!(drain::<ProximityStarted>(&mut world).len(), 1);
     }
 }
@@ -22,6 +22,7 @@
 //!   entity
 
 use super::astronomy::{AstroMass, AstroRadius};
+use crate::utils::oid::{Oid, OidTable};
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -35,15 +36,93 @@ pub fn solar_system_radius(mass: f32) -> f32 {
 #[derive(Component, Debug, Clone, Copy)]
 pub struct SolarSystemMarker;
 
+/// the stars inside the solar system.
+///
+/// CON data, reconstructed from the `Oid` of stars
+#[derive(Component, Debug, Clone, Default)]
+pub struct ContainsStars(Vec<Entity>);
+
+impl ContainsStars {
+    pub fn new(stars: Vec<Entity>) -> Self {
+        Self(stars)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// the object-oriented representation of the solar system, used for
+/// generation & serialization.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolarSystemSerde {
-    // id: ObjectId,
+    id: Oid,
     transform: Transform,
     mass: AstroMass,
     radius: AstroRadius,
+    stars: Vec<Oid>,
 }
 
 impl SolarSystemSerde {
+    pub fn new(
+        id: Oid,
+        transform: Transform,
+        mass: AstroMass,
+        radius: AstroRadius,
+        stars: Vec<Oid>,
+    ) -> Self {
+        Self {
+            id,
+            transform,
+            mass,
+            radius,
+            stars,
+        }
+    }
+
+    pub fn id(&self) -> Oid {
+        self.id
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// the `Oid` of stars inside the solar system
+    pub fn stars(&self) -> &[Oid] {
+        &self.stars
+    }
+
+    /// spawn the solar system entity in the L3 map, the collider is assigned
+    /// by `rebuild_collision_world`.
+    ///
+    /// pre-condition: all stars of the solar system are already in `table`.
+    pub fn spawn(&self, commands: &mut Commands, table: &OidTable) -> Entity {
+        let stars = self
+            .stars
+            .iter()
+            .filter_map(|id| {
+                let star = table.query(id);
+                if star.is_none() {
+                    warn!("[galaxy_map] missing star {:?} in {:?}", id, self.id);
+                }
+                star
+            })
+            .collect();
+
+        commands
+            .spawn((
+                self.id,
+                self.transform,
+                self.mass,
+                self.radius,
+                ContainsStars::new(stars),
+                GlobalTransform::default(),
+                SolarSystemMarker,
+            ))
+            .id()
+    }
+
     // transform
     // compute translate
     // let r_rng = r * self.rng_uniform().sqrt();
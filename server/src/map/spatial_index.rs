@@ -0,0 +1,417 @@
+//! spatial indices for the collision detection at L3 map.
+//!
+//! `RapierCollisionEngine` runs the full rapier pipeline, which may be
+//! overkill for huge galaxies where every object is a ball. `GridIndex` is a
+//! lightweight alternative with the same interaction rules, both implement
+//! `SpatialIndex`.
+
+use bevy::prelude::*;
+use fxhash::{FxHashMap, FxHashSet};
+use physics::logic::collision::CollisionLayers;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256StarStar;
+
+use super::rapier_collider::{
+    collision_layers, ColliderKind, CollisionObject, RapierCollider, RapierCollisionEngine,
+};
+use super::SpaceLayer;
+
+/// a spatial index of game objects, each object is a ball.
+///
+/// the overlap pairs and the range queries reflect the objects at the last
+/// `update`.
+pub trait SpatialIndex {
+    /// the handle of an object in the index
+    type Handle: Copy;
+
+    /// insert a game object in `layer`, solar systems are always in normal
+    /// space and fortifications in hyper space.
+    fn insert(
+        &mut self,
+        object: CollisionObject,
+        center: Vec3,
+        radius: f32,
+        layer: SpaceLayer,
+    ) -> Self::Handle;
+
+    /// move an object, no effect if the handle is invalid
+    fn update_transform(&mut self, handle: Self::Handle, center: Vec3);
+
+    /// remove an object, no effect if the handle is invalid
+    fn remove(&mut self, handle: Self::Handle);
+
+    /// the number of objects in the index
+    fn len(&self) -> usize;
+
+    /// test if the index contains no object
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// bring the overlap pairs and the range queries up to date.
+    fn update(&mut self);
+
+    /// all pairs of interacting objects which overlap, in no particular order.
+    fn overlap_pairs(&self) -> Vec<(CollisionObject, CollisionObject)>;
+
+    /// all entities of `kind` overlapping the sphere, or of all kinds if
+    /// `None`.
+    fn intersect_sphere(&self, center: Vec3, r: f32, kind: Option<ColliderKind>) -> Vec<Entity>;
+}
+
+impl SpatialIndex for RapierCollisionEngine {
+    type Handle = RapierCollider;
+
+    fn insert(
+        &mut self,
+        object: CollisionObject,
+        center: Vec3,
+        radius: f32,
+        layer: SpaceLayer,
+    ) -> RapierCollider {
+        self.spawn_ball(object, center, radius, layer)
+    }
+
+    fn update_transform(&mut self, handle: RapierCollider, center: Vec3) {
+        RapierCollisionEngine::update_transform(self, handle, center);
+    }
+
+    fn remove(&mut self, handle: RapierCollider) {
+        RapierCollisionEngine::remove(self, handle);
+    }
+
+    fn len(&self) -> usize {
+        RapierCollisionEngine::len(self)
+    }
+
+    fn update(&mut self) {
+        self.step();
+    }
+
+    fn overlap_pairs(&self) -> Vec<(CollisionObject, CollisionObject)> {
+        self.intersections().collect()
+    }
+
+    fn intersect_sphere(&self, center: Vec3, r: f32, kind: Option<ColliderKind>) -> Vec<Entity> {
+        RapierCollisionEngine::intersect_sphere(self, center, r, kind)
+    }
+}
+
+//////////////////////// Grid ////////////////////////
+
+/// a handle of `GridIndex`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GridHandle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct GridObject {
+    object: CollisionObject,
+    layers: CollisionLayers,
+    radius: f32,
+    center: Vec3,
+    /// the center at the last `update`
+    indexed: Vec3,
+}
+
+#[derive(Debug, Clone, Default)]
+struct GridSlot {
+    generation: u32,
+    object: Option<GridObject>,
+}
+
+/// a uniform grid, each object is put into every cell overlapped by its
+/// bounding box. the cell size should be close to the diameter of most
+/// objects, objects much larger than a cell are slow.
+#[derive(Debug, Clone)]
+pub struct GridIndex {
+    cell_size: f32,
+    slots: Vec<GridSlot>,
+    free: Vec<u32>,
+    len: usize,
+    /// the handles in each non-empty cell at the last `update`
+    cells: FxHashMap<IVec3, Vec<GridHandle>>,
+    pairs: Vec<(CollisionObject, CollisionObject)>,
+}
+
+impl GridIndex {
+    /// create an empty grid, `cell_size` must be positive.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        Self {
+            cell_size,
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            cells: FxHashMap::default(),
+            pairs: Vec::new(),
+        }
+    }
+
+    fn get(&self, handle: GridHandle) -> Option<&GridObject> {
+        let slot = self.slots.get(handle.index as usize)?;
+        (slot.generation == handle.generation)
+            .then_some(slot.object.as_ref())
+            .flatten()
+    }
+
+    fn get_mut(&mut self, handle: GridHandle) -> Option<&mut GridObject> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        (slot.generation == handle.generation)
+            .then_some(slot.object.as_mut())
+            .flatten()
+    }
+}
+
+/// the cells overlapped by the bounding box of the ball
+fn cells_of(cell_size: f32, center: Vec3, r: f32) -> impl Iterator<Item = IVec3> {
+    let min = ((center - r) / cell_size).floor().as_ivec3();
+    let max = ((center + r) / cell_size).floor().as_ivec3();
+    (min.x..=max.x).flat_map(move |x| {
+        (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| IVec3::new(x, y, z)))
+    })
+}
+
+fn overlap(a: &GridObject, b: &GridObject) -> bool {
+    let r = a.radius + b.radius;
+    a.indexed.distance_squared(b.indexed) <= r * r
+}
+
+impl SpatialIndex for GridIndex {
+    type Handle = GridHandle;
+
+    fn insert(
+        &mut self,
+        object: CollisionObject,
+        center: Vec3,
+        radius: f32,
+        layer: SpaceLayer,
+    ) -> GridHandle {
+        let entry = GridObject {
+            object,
+            layers: collision_layers(object.kind, layer),
+            radius,
+            center,
+            indexed: center,
+        };
+
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(GridSlot::default());
+            (self.slots.len() - 1) as u32
+        });
+        let slot = &mut self.slots[index as usize];
+        slot.object = Some(entry);
+        self.len += 1;
+        GridHandle {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    fn update_transform(&mut self, handle: GridHandle, center: Vec3) {
+        if let Some(object) = self.get_mut(handle) {
+            object.center = center;
+        } else {
+            error!("[physics] invalid handle {:?}", handle);
+        }
+    }
+
+    fn remove(&mut self, handle: GridHandle) {
+        if self.get(handle).is_none() {
+            return;
+        }
+        let slot = &mut self.slots[handle.index as usize];
+        slot.object = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.len -= 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn update(&mut self) {
+        let mut cells: FxHashMap<IVec3, Vec<GridHandle>> = FxHashMap::default();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let Some(object) = slot.object.as_mut() else {
+                continue;
+            };
+            object.indexed = object.center;
+
+            let handle = GridHandle {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            for cell in cells_of(self.cell_size, object.indexed, object.radius) {
+                cells.entry(cell).or_default().push(handle);
+            }
+        }
+
+        // a pair sharing several cells is found several times
+        let mut pairs = FxHashSet::default();
+        for handles in cells.values() {
+            for (i, a) in handles.iter().enumerate() {
+                let object_a = self.get(*a).unwrap();
+                for b in handles[i + 1..].iter() {
+                    let object_b = self.get(*b).unwrap();
+                    if object_a.layers.interacts(object_b.layers) && overlap(object_a, object_b) {
+                        pairs.insert((a.index.min(b.index), a.index.max(b.index)));
+                    }
+                }
+            }
+        }
+
+        self.pairs = pairs
+            .into_iter()
+            .map(|(a, b)| {
+                let object =
+                    |index: u32| self.slots[index as usize].object.as_ref().unwrap().object;
+                (object(a), object(b))
+            })
+            .collect();
+        self.cells = cells;
+    }
+
+    fn overlap_pairs(&self) -> Vec<(CollisionObject, CollisionObject)> {
+        self.pairs.clone()
+    }
+
+    fn intersect_sphere(&self, center: Vec3, r: f32, kind: Option<ColliderKind>) -> Vec<Entity> {
+        let mut handles: Vec<GridHandle> = cells_of(self.cell_size, center, r)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        handles.sort_by_key(|handle| handle.index);
+        handles.dedup();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| self.get(handle))
+            .filter(|object| kind.is_none() || kind == Some(object.object.kind))
+            .filter(|object| {
+                let sum = object.radius + r;
+                object.indexed.distance_squared(center) <= sum * sum
+            })
+            .map(|object| object.object.entity)
+            .collect()
+    }
+}
+
+/// a disc galaxy of `n` objects, 40% solar systems, 50% fleets in both layers
+/// and 10% fortifications. shared by the tests and the benchmark.
+#[doc(hidden)]
+pub fn generate_galaxy(seed: u64, n: u32) -> Vec<(CollisionObject, Vec3, f32, SpaceLayer)> {
+    let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
+    let size = (n as f32).sqrt() * 8.0;
+    (0..n)
+        .map(|i| {
+            let (kind, radius, layer) = match rng.gen_range(0..10) {
+                0..=3 => (ColliderKind::SolarSystem, 2.0, SpaceLayer::Normal),
+                4..=6 => (ColliderKind::Fleet, 0.5, SpaceLayer::Normal),
+                7..=8 => (ColliderKind::Fleet, 0.5, SpaceLayer::Hyper),
+                _ => (ColliderKind::Fortification, 4.0, SpaceLayer::Hyper),
+            };
+            let object = CollisionObject {
+                entity: Entity::from_raw(i),
+                kind,
+            };
+            let center = Vec3::new(rng.gen(), rng.gen::<f32>() * 0.05, rng.gen()) * size;
+            (object, center, radius, layer)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity_pairs(index: &impl SpatialIndex) -> Vec<(Entity, Entity)> {
+        let mut pairs: Vec<(Entity, Entity)> = index
+            .overlap_pairs()
+            .into_iter()
+            .map(|(a, b)| (a.entity.min(b.entity), a.entity.max(b.entity)))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    fn sphere(index: &impl SpatialIndex, center: Vec3, kind: Option<ColliderKind>) -> Vec<Entity> {
+        let mut entities = index.intersect_sphere(center, 10.0, kind);
+        entities.sort();
+        entities
+    }
+
+    #[test]
+    fn grid_is_equivalent_to_rapier() {
+        for seed in [1, 2, 3] {
+            let galaxy = generate_galaxy(seed, 2000);
+            let mut rapier = RapierCollisionEngine::new();
+            let mut grid = GridIndex::new(4.0);
+            let handles: Vec<_> = galaxy
+                .iter()
+                .map(|&(object, center, radius, layer)| {
+                    (
+                        rapier.insert(object, center, radius, layer),
+                        grid.insert(object, center, radius, layer),
+                    )
+                })
+                .collect();
+            rapier.update();
+            grid.update();
+
+            let pairs = entity_pairs(&rapier);
+            assert!(!pairs.is_empty());
+            assert_eq!(entity_pairs(&grid), pairs);
+
+            // move all fleets, remove some objects
+            let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
+            for (i, (object, center, ..)) in galaxy.iter().enumerate() {
+                let (rapier_handle, grid_handle) = handles[i];
+                if i % 7 == 0 {
+                    rapier.remove(rapier_handle);
+                    grid.remove(grid_handle);
+                } else if object.kind == ColliderKind::Fleet {
+                    let dest = *center + Vec3::new(rng.gen(), 0.0, rng.gen()) * 5.0;
+                    SpatialIndex::update_transform(&mut rapier, rapier_handle, dest);
+                    grid.update_transform(grid_handle, dest);
+                }
+            }
+            rapier.update();
+            grid.update();
+            assert_eq!(SpatialIndex::len(&rapier), grid.len());
+            assert_eq!(entity_pairs(&grid), entity_pairs(&rapier));
+
+            for (_, center, ..) in galaxy.iter().step_by(97) {
+                for kind in [None, Some(ColliderKind::SolarSystem)] {
+                    assert_eq!(sphere(&grid, *center, kind), sphere(&rapier, *center, kind));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_handle_reuse() {
+        let mut grid = GridIndex::new(1.0);
+        let object = |i| CollisionObject {
+            entity: Entity::from_raw(i),
+            kind: ColliderKind::Fleet,
+        };
+        let a = grid.insert(object(0), Vec3::ZERO, 0.5, SpaceLayer::Normal);
+        grid.remove(a);
+        let b = grid.insert(object(1), Vec3::ZERO, 0.5, SpaceLayer::Normal);
+        let c = grid.insert(object(2), Vec3::X * 0.5, 0.5, SpaceLayer::Normal);
+        assert_eq!(a.index, b.index);
+
+        // the stale handle does not affect the new object
+        grid.remove(a);
+        grid.update_transform(a, Vec3::X * 100.0);
+        grid.update();
+        assert_eq!(grid.len(), 2);
+        assert_eq!(entity_pairs(&grid), [(object(1).entity, object(2).entity)]);
+        assert!(grid.get(c).is_some());
+    }
+}
@@ -0,0 +1,285 @@
+//! autosave the game periodically or on key events.
+//!
+//! the world is snapshotted on the main thread, which only clones the S/L
+//! data. encoding, compressing & writing the save happen on the
+//! `IoTaskPool`, so saving never stalls the simulation.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy::tasks::{IoTaskPool, Task};
+use futures_lite::future;
+
+use super::index::{SaveIndex, SaveSummary};
+use super::{snapshot_world, write_save, SaveError, SaveGame};
+use crate::utils::time::GameTime;
+
+/// the configuration of autosave
+#[derive(Resource, Debug, Clone)]
+pub struct AutosaveConfig {
+    /// the directory for autosaves & the index
+    pub dir: PathBuf,
+    /// autosave every `interval_days` in-game days, 0 to only autosave on
+    /// `AutosaveRequest`
+    pub interval_days: u64,
+    /// the number of rotating autosave slots
+    pub slots: usize,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("saves"),
+            interval_days: 30,
+            slots: 3,
+        }
+    }
+}
+
+/// an event to request an autosave, send it on key events (e.g. a war is
+/// declared).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutosaveRequest;
+
+/// the progress of autosave
+#[derive(Resource, Default)]
+pub struct AutosaveState {
+    /// the in-game day of the last autosave
+    last_day: Option<u64>,
+    requested: bool,
+    task: Option<Task<Result<SaveSummary, SaveError>>>,
+}
+
+impl AutosaveState {
+    /// test if an autosave is being written in background
+    pub fn writing(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// forget the last autosave when another game is loaded, the interval
+    /// restarts from the day of the loaded game.
+    pub fn reset(&mut self) {
+        self.last_day = None;
+        self.requested = false;
+    }
+}
+
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutosaveConfig>()
+            .init_resource::<AutosaveState>()
+            .add_event::<AutosaveRequest>()
+            .add_systems(
+                (
+                    system_autosave_schedule,
+                    system_autosave_snapshot,
+                    system_autosave_poll,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// a system to decide whether an autosave is due.
+pub fn system_autosave_schedule(
+    config: Res<AutosaveConfig>,
+    time: Res<GameTime>,
+    mut state: ResMut<AutosaveState>,
+    mut requests: EventReader<AutosaveRequest>,
+) {
+    let day = time.days();
+    let last_day = *state.last_day.get_or_insert(day);
+
+    if !requests.is_empty() {
+        requests.clear();
+        state.requested = true;
+    }
+    if config.interval_days > 0 && day >= last_day + config.interval_days {
+        state.requested = true;
+    }
+}
+
+/// a system to snapshot the world & start writing it in background. a due
+/// autosave waits if the previous one is still being written.
+pub fn system_autosave_snapshot(world: &mut World) {
+    let state = world.resource::<AutosaveState>();
+    if !state.requested || state.task.is_some() {
+        return;
+    }
+
+    let snapshot = snapshot_world(world);
+    let config = world.resource::<AutosaveConfig>().clone();
+    let mut state = world.resource_mut::<AutosaveState>();
+    state.requested = false;
+
+    match snapshot {
+        Ok(save) => {
+            state.last_day = Some(save.time.days());
+            let task = IoTaskPool::get()
+                .spawn(async move { write_autosave(&config.dir, config.slots, &save) });
+            state.task = Some(task);
+        }
+        Err(e) => error!("[save] fail to snapshot for autosave: {}", e),
+    }
+}
+
+/// a system to collect the result of the background autosave.
+pub fn system_autosave_poll(mut state: ResMut<AutosaveState>) {
+    let Some(task) = state.task.as_mut() else {
+        return;
+    };
+    let Some(result) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+
+    state.task = None;
+    match result {
+        Ok(summary) => info!("[save] autosave to {}", summary.file),
+        Err(e) => error!("[save] fail to autosave: {}", e),
+    }
+}
+
+/// write `save` into the oldest autosave slot in `dir` and record it in the
+/// index of `dir`.
+pub fn write_autosave(dir: &Path, slots: usize, save: &SaveGame) -> Result<SaveSummary, SaveError> {
+    fs::create_dir_all(dir)?;
+    let mut index = match SaveIndex::read(dir) {
+        Ok(index) => index,
+        Err(e) => {
+            warn!("[save] rebuild the broken save index: {}", e);
+            SaveIndex::rebuild(dir)?
+        }
+    };
+
+    let file = next_autosave_file(&index, slots);
+    write_save(dir.join(&file), save)?;
+
+    let summary = SaveSummary::new(file, save);
+    index.record(summary.clone());
+    index.write(dir)?;
+    Ok(summary)
+}
+
+/// the first autosave slot never written, or the least recently written one.
+fn next_autosave_file(index: &SaveIndex, slots: usize) -> String {
+    (0..slots.max(1))
+        .map(|slot| format!("autosave_{}.sav", slot))
+        .min_by_key(|file| Reverse(index.position(file).unwrap_or(usize::MAX)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::index::INDEX_FILE;
+    use crate::save::world::tests::generate_save;
+    use crate::save::{load_world, read_save};
+    use crate::utils::time::TICKS_PER_DAY;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bvi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotate_slots() {
+        let dir = temp_dir("rotate");
+        let mut save = generate_save(5);
+
+        for day in 1..=3 {
+            save.time = GameTime::new(day * TICKS_PER_DAY);
+            write_autosave(&dir, 2, &save).unwrap();
+        }
+
+        let index = SaveIndex::read(&dir).unwrap();
+        let files: Vec<&str> = index.entries().iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, ["autosave_0.sav", "autosave_1.sav"]);
+        assert_eq!(index.entries()[0].time.days(), 3);
+        assert_eq!(index.entries()[0].faction.as_deref(), Some("Terran Union"));
+        assert_eq!(
+            read_save(dir.join("autosave_0.sav")).unwrap().time.days(),
+            3
+        );
+        assert_eq!(
+            read_save(dir.join("autosave_1.sav")).unwrap().time.days(),
+            2
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_broken_index() {
+        let dir = temp_dir("broken");
+        let mut save = generate_save(5);
+        for day in 1..=2 {
+            save.time = GameTime::new(day * TICKS_PER_DAY);
+            write_autosave(&dir, 3, &save).unwrap();
+        }
+
+        // the entries are recovered from the saves in the directory
+        fs::write(dir.join(INDEX_FILE), b"garbage").unwrap();
+        save.time = GameTime::new(3 * TICKS_PER_DAY);
+        write_autosave(&dir, 3, &save).unwrap();
+
+        let index = SaveIndex::read(&dir).unwrap();
+        let mut files: Vec<&str> = index.entries().iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files.remove(0), "autosave_2.sav");
+        files.sort();
+        assert_eq!(files, ["autosave_0.sav", "autosave_1.sav"]);
+        assert_eq!(index.entries()[0].time.days(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn autosave_in_background() {
+        let dir = temp_dir("background");
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AutosavePlugin)
+            .insert_resource(AutosaveConfig {
+                dir: dir.clone(),
+                interval_days: 10,
+                slots: 2,
+            });
+        load_world(&mut app.world, &generate_save(5));
+
+        let update_until_written = |app: &mut App| {
+            app.update();
+            while app.world.resource::<AutosaveState>().writing() {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                app.update();
+            }
+        };
+
+        // not due yet
+        update_until_written(&mut app);
+        assert!(SaveIndex::read(&dir).unwrap().entries().is_empty());
+
+        // due after 10 days
+        let ticks = app.world.resource::<GameTime>().ticks();
+        app.insert_resource(GameTime::new(ticks + 10 * TICKS_PER_DAY));
+        update_until_written(&mut app);
+        assert_eq!(SaveIndex::read(&dir).unwrap().entries().len(), 1);
+
+        // on key events
+        app.world.send_event(AutosaveRequest);
+        update_until_written(&mut app);
+        assert_eq!(SaveIndex::read(&dir).unwrap().entries().len(), 2);
+
+        // the interval restarts from the day of a loaded game
+        let mut save = generate_save(6);
+        save.time = GameTime::new(ticks + 100 * TICKS_PER_DAY);
+        load_world(&mut app.world, &save);
+        update_until_written(&mut app);
+        assert_eq!(SaveIndex::read(&dir).unwrap().entries().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use core::fmt;
+
+use bincode::error::{DecodeError, EncodeError};
+
+use super::section::SectionTag;
+
+/// the error when saving or loading the game
+#[derive(Debug)]
+pub enum SaveError {
+    /// fail to read or write the save file
+    Io(std::io::Error),
+    /// the file does not start with `SAVE_MAGIC`, it's not a save file
+    BadMagic,
+    /// the save is written by a newer or unknown version of the game
+    UnsupportedVersion(u32),
+    /// the file ends before the header is complete
+    Truncated,
+    /// the section is missing or out of order
+    MissingSection(SectionTag),
+    /// the file ends before the section is complete
+    TruncatedSection(SectionTag),
+    /// the checksum of the section does not match its data
+    ChecksumMismatch(SectionTag),
+    /// the section passes the checksum but can't be decompressed
+    CorruptedSection(SectionTag),
+    /// some bytes left after the payload
+    TrailingBytes(usize),
+    /// a resource required by the save is missing in the world
+    MissingResource(&'static str),
+    /// the extension of a text save is neither `.ron` nor `.json`
+    UnknownTextFormat,
+    Encode(EncodeError),
+    Decode(DecodeError),
+    RonEncode(ron::Error),
+    RonDecode(ron::error::SpannedError),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::BadMagic => write!(f, "not a save file"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported save version {}", v),
+            Self::Truncated => write!(f, "save file is truncated"),
+            Self::MissingSection(t) => write!(f, "missing section `{}`", tag_str(t)),
+            Self::TruncatedSection(t) => write!(f, "section `{}` is truncated", tag_str(t)),
+            Self::ChecksumMismatch(t) => {
+                write!(f, "checksum mismatch in section `{}`", tag_str(t))
+            }
+            Self::CorruptedSection(t) => write!(f, "section `{}` is corrupted", tag_str(t)),
+            Self::TrailingBytes(n) => write!(f, "{} unexpected bytes after the payload", n),
+            Self::MissingResource(r) => write!(f, "missing resource `{}`", r),
+            Self::UnknownTextFormat => write!(f, "unknown text save format"),
+            Self::Encode(e) => write!(f, "fail to encode: {}", e),
+            Self::Decode(e) => write!(f, "fail to decode: {}", e),
+            Self::RonEncode(e) => write!(f, "fail to encode ron: {}", e),
+            Self::RonDecode(e) => write!(f, "fail to decode ron at {}", e),
+            Self::Json(e) => write!(f, "json error: {}", e),
+        }
+    }
+}
+
+fn tag_str(tag: &SectionTag) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(tag)
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Encode(e) => Some(e),
+            Self::Decode(e) => Some(e),
+            Self::RonEncode(e) => Some(e),
+            Self::RonDecode(e) => Some(e),
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<EncodeError> for SaveError {
+    fn from(e: EncodeError) -> Self {
+        Self::Encode(e)
+    }
+}
+
+impl From<DecodeError> for SaveError {
+    fn from(e: DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(e: ron::Error) -> Self {
+        Self::RonEncode(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        Self::RonDecode(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use bincode::serde::{decode_from_slice, encode_to_vec};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::migration::migrate;
+use super::section::{write_section, SectionReader, SectionTag};
+use super::SaveError;
+use crate::faction::PlayerFaction;
+use crate::fleet::fleet::FleetSerde;
+use crate::map::fortification::FortificationSerde;
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::utils::time::GameTime;
+use crate::utils::SERDE_CONFIG;
+
+/// the first 8 bytes of every save file
+pub const SAVE_MAGIC: [u8; 8] = *b"BVISAVE\0";
+
+/// the version of the save format written by this build
+pub const SAVE_VERSION: u32 = 8;
+
+const HEADER_LEN: usize = SAVE_MAGIC.len() + std::mem::size_of::<u32>();
+
+/// section for `GalaxySeed`, `GameTime` & `PlayerFaction`
+pub(super) const SECTION_META: SectionTag = *b"META";
+/// section for all stars
+pub(super) const SECTION_STARS: SectionTag = *b"STAR";
+/// section for all solar systems
+pub(super) const SECTION_SOLAR_SYSTEMS: SectionTag = *b"SSYS";
+/// section for all fleets and their ships
+pub(super) const SECTION_FLEETS: SectionTag = *b"FLET";
+/// section for all fortifications
+pub(super) const SECTION_FORTIFICATIONS: SectionTag = *b"FORT";
+
+/// all S/L data of a game session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub player: Option<PlayerFaction>,
+    /// all stars, sorted by `Oid`
+    pub stars: Vec<StarObject>,
+    /// all solar systems, sorted by `Oid`
+    pub solar_systems: Vec<SolarSystemSerde>,
+    /// all fleets, sorted by `Oid`
+    pub fleets: Vec<FleetSerde>,
+    /// all fortifications, sorted by `Oid`
+    pub fortifications: Vec<FortificationSerde>,
+}
+
+impl SaveGame {
+    /// encode the save into bytes, including the header.
+    pub fn encode(&self) -> Result<Vec<u8>, SaveError> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&SAVE_MAGIC);
+        bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+
+        let meta = encode_to_vec((self.seed, self.time, &self.player), SERDE_CONFIG)?;
+        write_section(&mut bytes, SECTION_META, &meta)?;
+        let stars = encode_to_vec(&self.stars, SERDE_CONFIG)?;
+        write_section(&mut bytes, SECTION_STARS, &stars)?;
+        let solar_systems = encode_to_vec(&self.solar_systems, SERDE_CONFIG)?;
+        write_section(&mut bytes, SECTION_SOLAR_SYSTEMS, &solar_systems)?;
+        let fleets = encode_to_vec(&self.fleets, SERDE_CONFIG)?;
+        write_section(&mut bytes, SECTION_FLEETS, &fleets)?;
+        let fortifications = encode_to_vec(&self.fortifications, SERDE_CONFIG)?;
+        write_section(&mut bytes, SECTION_FORTIFICATIONS, &fortifications)?;
+        Ok(bytes)
+    }
+
+    /// decode the save from bytes produced by `SaveGame::encode`, saves of
+    /// older versions are migrated to `SAVE_VERSION`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, SaveError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(SaveError::Truncated);
+        }
+
+        let (magic, rest) = bytes.split_at(SAVE_MAGIC.len());
+        if magic != SAVE_MAGIC {
+            return Err(SaveError::BadMagic);
+        }
+
+        let (version, payload) = rest.split_at(std::mem::size_of::<u32>());
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        migrate(version, payload)
+    }
+
+    /// decode the sections written by `SaveGame::encode`.
+    pub(super) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time, player) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        let fleets = decode_payload(&reader.read(SECTION_FLEETS)?)?;
+        let fortifications = decode_payload(&reader.read(SECTION_FORTIFICATIONS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            player,
+            stars,
+            solar_systems,
+            fleets,
+            fortifications,
+        })
+    }
+}
+
+/// decode the whole `payload` as `T`.
+pub(super) fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, SaveError> {
+    let (value, len) = decode_from_slice(payload, SERDE_CONFIG)?;
+    if len != payload.len() {
+        return Err(SaveError::TrailingBytes(payload.len() - len));
+    }
+    Ok(value)
+}
+
+/// write `bytes` to a temporary file next to `path`, then rename it to `path`.
+/// the file at `path` is either the old one or the new one, never a partially
+/// written one.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), SaveError> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(result?)
+}
+
+/// write the save to `path` atomically, overwrite the file if exists.
+pub fn write_save(path: impl AsRef<Path>, save: &SaveGame) -> Result<(), SaveError> {
+    write_atomic(path.as_ref(), &save.encode()?)
+}
+
+/// read the save from `path`.
+pub fn read_save(path: impl AsRef<Path>) -> Result<SaveGame, SaveError> {
+    SaveGame::decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::world::tests::generate_save;
+
+    #[test]
+    fn compressed() {
+        let save = generate_save(3);
+        let uncompressed = encode_to_vec(&save, SERDE_CONFIG).unwrap();
+        assert!(save.encode().unwrap().len() < uncompressed.len());
+    }
+
+    #[test]
+    fn truncated_save_is_error() {
+        let bytes = generate_save(3).encode().unwrap();
+        for len in 0..bytes.len() {
+            assert!(SaveGame::decode(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupted_save_is_error() {
+        let bytes = generate_save(3).encode().unwrap();
+        for i in HEADER_LEN..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 0x5a;
+            assert!(SaveGame::decode(&corrupted).is_err());
+        }
+
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x5a;
+        assert!(matches!(
+            SaveGame::decode(&corrupted),
+            Err(SaveError::ChecksumMismatch(SECTION_FORTIFICATIONS))
+        ));
+    }
+
+    #[test]
+    fn write_then_read() {
+        let dir = std::env::temp_dir().join(format!("bvi-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("quick.sav");
+
+        let save = generate_save(3);
+        write_save(&path, &save).unwrap();
+        write_save(&path, &save).unwrap();
+        let read = read_save(&path).unwrap();
+
+        assert_eq!(read.encode().unwrap(), save.encode().unwrap());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+//! an index of the saves in a directory, so the load menu can list the saves
+//! without decoding the whole world.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::log::*;
+use bincode::serde::encode_to_vec;
+use serde::{Deserialize, Serialize};
+
+use super::format::{decode_payload, write_atomic};
+use super::{read_save, SaveError, SaveGame};
+use crate::map::generate::GalaxySeed;
+use crate::utils::time::GameTime;
+use crate::utils::SERDE_CONFIG;
+
+/// the file name of the index inside the save directory
+pub const INDEX_FILE: &str = "index.bin";
+
+/// the extension of save files
+pub const SAVE_EXTENSION: &str = "sav";
+
+/// the first 8 bytes of the index file
+const INDEX_MAGIC: [u8; 8] = *b"BVIINDEX";
+
+/// the metadata of a save, shown in the load menu.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SaveSummary {
+    /// the file name of the save, relative to the save directory
+    pub file: String,
+    /// when the save is written, in seconds since unix epoch
+    pub timestamp: u64,
+    pub time: GameTime,
+    pub seed: GalaxySeed,
+    /// the name of the player faction
+    pub faction: Option<String>,
+    pub stars: u64,
+    pub solar_systems: u64,
+}
+
+impl SaveSummary {
+    /// summarize `save` written into `file` just now.
+    pub fn new(file: impl Into<String>, save: &SaveGame) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            file: file.into(),
+            timestamp,
+            time: save.time,
+            seed: save.seed,
+            faction: save.player.as_ref().map(|p| p.name().to_string()),
+            stars: save.stars.len() as u64,
+            solar_systems: save.solar_systems.len() as u64,
+        }
+    }
+}
+
+/// the summaries of all saves in a directory, the most recently written first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SaveIndex {
+    entries: Vec<SaveSummary>,
+}
+
+impl SaveIndex {
+    /// read the index in `dir`, an empty index is returned if there is none.
+    pub fn read(dir: &Path) -> Result<Self, SaveError> {
+        let bytes = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        match bytes.strip_prefix(&INDEX_MAGIC) {
+            Some(payload) => decode_payload(payload),
+            None => Err(SaveError::BadMagic),
+        }
+    }
+
+    /// rebuild the index from the saves in `dir`, e.g. the index is broken.
+    /// the saves are ordered by the time they are written, the files which
+    /// can't be read are skipped.
+    pub fn rebuild(dir: &Path) -> Result<Self, SaveError> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SAVE_EXTENSION) {
+                continue;
+            }
+            let Some(file) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let save = match read_save(&path) {
+                Ok(save) => save,
+                Err(e) => {
+                    warn!("[save] skip {} in the index: {}", file, e);
+                    continue;
+                }
+            };
+            let mut summary = SaveSummary::new(file, &save);
+            if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                summary.timestamp = modified
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+            }
+            entries.push(summary);
+        }
+
+        entries.sort_by(|a, b| {
+            b.timestamp
+                .cmp(&a.timestamp)
+                .then_with(|| a.file.cmp(&b.file))
+        });
+        Ok(Self { entries })
+    }
+
+    /// write the index into `dir` atomically.
+    pub fn write(&self, dir: &Path) -> Result<(), SaveError> {
+        let mut bytes = INDEX_MAGIC.to_vec();
+        bytes.extend(encode_to_vec(self, SERDE_CONFIG)?);
+        write_atomic(&dir.join(INDEX_FILE), &bytes)
+    }
+
+    pub fn entries(&self) -> &[SaveSummary] {
+        &self.entries
+    }
+
+    /// the position of `file` in the index, 0 being the most recent one
+    pub fn position(&self, file: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.file == file)
+    }
+
+    /// record a newly written save, replace the old summary of the same file.
+    pub fn record(&mut self, summary: SaveSummary) {
+        self.remove(&summary.file);
+        self.entries.insert(0, summary);
+    }
+
+    /// remove the summary of `file`
+    pub fn remove(&mut self, file: &str) {
+        self.entries.retain(|e| e.file != file);
+    }
+}
@@ -0,0 +1,217 @@
+//! upgrade the payload written by older versions of the game.
+//!
+//! a save is decoded with the frozen schema of its own version, then upgraded
+//! one version at a time (`v1 -> v2 -> ...`) until it reaches `SAVE_VERSION`.
+//!
+//! to change the payload:
+//! 1. copy the structs about to change into `schema/vN.rs`, N being the
+//!    current `SAVE_VERSION`, and implement `From` for the next version. if
+//!    only the container changes, the variant of the old version can hold the
+//!    live structs.
+//! 2. change the live structs & bump `SAVE_VERSION`.
+//! 3. add a variant to `VersionedSave`.
+//! 4. run `cargo test -p server write_save_fixture -- --ignored` and check in
+//!    the new fixture.
+
+use bevy::log::*;
+
+use super::format::decode_payload;
+use super::schema::{v1, v3, v4, v5, v6, v7};
+use super::{SaveError, SaveGame, SAVE_VERSION};
+
+/// the payload of a save, in the schema of the version it's written.
+enum VersionedSave {
+    V1(v1::SaveGame),
+    V2(v3::SaveGame),
+    V3(v3::SaveGame),
+    V4(v4::SaveGame),
+    V5(v5::SaveGame),
+    V6(v6::SaveGame),
+    V7(v7::SaveGame),
+    V8(SaveGame),
+}
+
+impl VersionedSave {
+    fn decode(version: u32, payload: &[u8]) -> Result<Self, SaveError> {
+        match version {
+            1 => decode_payload(payload).map(Self::V1),
+            2 => decode_payload(payload).map(Self::V2),
+            3 => v3::SaveGame::decode_sections(payload).map(Self::V3),
+            4 => v4::SaveGame::decode_sections(payload).map(Self::V4),
+            5 => v5::SaveGame::decode_sections(payload).map(Self::V5),
+            6 => v6::SaveGame::decode_sections(payload).map(Self::V6),
+            7 => v7::SaveGame::decode_sections(payload).map(Self::V7),
+            8 => SaveGame::decode_sections(payload).map(Self::V8),
+            _ => Err(SaveError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// decode the payload of `version` and upgrade it to `SAVE_VERSION`.
+pub fn migrate(version: u32, payload: &[u8]) -> Result<SaveGame, SaveError> {
+    if version != SAVE_VERSION {
+        info!("[save] migrate save from v{} to v{}", version, SAVE_VERSION);
+    }
+
+    let mut save = VersionedSave::decode(version, payload)?;
+    loop {
+        save = match save {
+            VersionedSave::V1(save) => VersionedSave::V2(save.into()),
+            VersionedSave::V2(save) => VersionedSave::V3(save),
+            VersionedSave::V3(save) => VersionedSave::V4(save.into()),
+            VersionedSave::V4(save) => VersionedSave::V5(save.into()),
+            VersionedSave::V5(save) => VersionedSave::V6(save.into()),
+            VersionedSave::V6(save) => VersionedSave::V7(save.into()),
+            VersionedSave::V7(save) => VersionedSave::V8(save.into()),
+            VersionedSave::V8(save) => return Ok(save),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::Generative;
+    use crate::map::astronomy::{AstroMass, AstroRadius};
+    use crate::map::generate::GalaxySeed;
+    use crate::map::star::StarObject;
+    use crate::save::world::tests::generate_save;
+    use crate::save::SAVE_MAGIC;
+    use crate::save::{load_world, snapshot_world};
+    use crate::utils::oid::Oid;
+    use crate::utils::time::GameTime;
+    use crate::utils::SERDE_CONFIG;
+    use bevy::prelude::{Transform, Vec3, World};
+    use bincode::serde::encode_to_vec;
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256StarStar;
+    use std::path::PathBuf;
+
+    fn fixture_path(version: u32) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "fixtures", "save"]
+            .iter()
+            .collect::<PathBuf>()
+            .join(format!("v{}.sav", version))
+    }
+
+    /// write the fixture for the current version, run it before bumping the
+    /// version.
+    #[test]
+    #[ignore]
+    fn write_save_fixture() {
+        let bytes = generate_save(20230401).encode().unwrap();
+        std::fs::write(fixture_path(SAVE_VERSION), bytes).unwrap();
+    }
+
+    /// write the fixture of version 1, the first version before any
+    /// migration. it's encoded from the frozen schema with 4 stars and 3
+    /// solar systems of the seed 20230401, at the tick 4096.
+    #[test]
+    #[ignore]
+    fn write_v1_fixture() {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(20230401);
+        let stars = (0..4).map(|_| StarObject::new_from_rng(&mut rng)).collect();
+        let solar_systems = (0..3_u32)
+            .map(|i| v1::SolarSystemSerde {
+                id: Oid::v5(&i.to_le_bytes()),
+                transform: Transform::from_translation(
+                    Vec3::new(rng.gen(), 0.0, rng.gen()) * 320.0,
+                ),
+                mass: AstroMass::new(rng.gen_range(0.1..10.0)),
+                radius: AstroRadius::new(rng.gen_range(0.5..2.0)),
+            })
+            .collect();
+        let save = v1::SaveGame {
+            seed: GalaxySeed::new(20230401),
+            time: GameTime::new(4096),
+            stars,
+            solar_systems,
+        };
+
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&1_u32.to_le_bytes());
+        bytes.extend(encode_to_vec(&save, SERDE_CONFIG).unwrap());
+        std::fs::write(fixture_path(1), bytes).unwrap();
+    }
+
+    #[test]
+    fn every_fixture_loads() {
+        for version in 1..=SAVE_VERSION {
+            let bytes = std::fs::read(fixture_path(version)).unwrap();
+            let save = SaveGame::decode(&bytes).unwrap();
+
+            // an upgraded save can be loaded & saved again in the latest version
+            let mut world = World::new();
+            load_world(&mut world, &save);
+            let resaved = snapshot_world(&mut world).unwrap().encode().unwrap();
+            assert_eq!(
+                SaveGame::decode(&resaved).unwrap().encode().unwrap(),
+                resaved
+            );
+        }
+    }
+
+    #[test]
+    fn migrate_v1() {
+        let bytes = std::fs::read(fixture_path(1)).unwrap();
+        let save = SaveGame::decode(&bytes).unwrap();
+
+        assert_eq!(u64::from(save.seed), 20230401);
+        assert_eq!(save.time.ticks(), 4096);
+        assert!(save.player.is_none());
+        assert_eq!(save.stars.len(), 4);
+        assert_eq!(save.solar_systems.len(), 3);
+        assert!(save.solar_systems.iter().all(|s| s.stars().is_empty()));
+    }
+
+    #[test]
+    fn migrate_v4() {
+        let bytes = std::fs::read(fixture_path(4)).unwrap();
+        let save = SaveGame::decode(&bytes).unwrap();
+
+        assert!(save.player.is_some());
+        assert!(!save.solar_systems.is_empty());
+        assert!(save.fleets.is_empty());
+    }
+
+    #[test]
+    fn migrate_v5() {
+        let bytes = std::fs::read(fixture_path(5)).unwrap();
+        let save = SaveGame::decode(&bytes).unwrap();
+
+        assert!(!save.fleets.is_empty());
+        assert!(save.fortifications.is_empty());
+    }
+
+    #[test]
+    fn migrate_v6() {
+        let bytes = std::fs::read(fixture_path(6)).unwrap();
+        let save = SaveGame::decode(&bytes).unwrap();
+
+        assert!(!save.fortifications.is_empty());
+        assert!(save.fleets.iter().all(|fleet| fleet.stance.is_none()));
+    }
+
+    #[test]
+    fn migrate_v7() {
+        let bytes = std::fs::read(fixture_path(7)).unwrap();
+        let save = SaveGame::decode(&bytes).unwrap();
+
+        assert!(save.fleets.iter().any(|fleet| fleet.stance.is_some()));
+        assert!(save
+            .fleets
+            .iter()
+            .flat_map(|fleet| fleet.ships.iter())
+            .all(|ship| ship.health.is_none()));
+    }
+
+    #[test]
+    fn reject_future_version() {
+        let mut bytes = generate_save(1).encode().unwrap();
+        bytes[8..12].copy_from_slice(&(SAVE_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            SaveGame::decode(&bytes),
+            Err(SaveError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,38 @@
+//! a module for saving & loading the game.
+//!
+//! only S/L data is written into the save file. CON data (e.g. `OidTable`,
+//! `RapierCollider`, `GlobalTransform`) is reconstructed when the save is
+//! loaded back into the world.
+//!
+//! the layout of a save file is
+//! - magic: 8 bytes, `SAVE_MAGIC`
+//! - version: 4 bytes, little endian, `SAVE_VERSION` at the time of writing
+//! - payload: `SaveGame` encoded by bincode, split into compressed &
+//!   checksummed sections (see `section`)
+//!
+//! the file is written atomically, a crash during saving never leaves a
+//! partially written save behind.
+//!
+//! saves written by older versions are upgraded by `migration` when decoded.
+//!
+//! `text` exports & imports the same data as RON or JSON.
+//!
+//! `index` keeps the summaries of the saves in a directory, and `autosave`
+//! writes rotating autosaves in background.
+//!
+//! all objects in the payload are sorted by their `Oid`, so saving the same
+//! world always produce the same bytes.
+
+pub mod autosave;
+pub mod error;
+pub mod format;
+pub mod index;
+pub mod migration;
+pub mod schema;
+pub mod section;
+pub mod text;
+pub mod world;
+
+pub use error::SaveError;
+pub use format::{read_save, write_save, SaveGame, SAVE_MAGIC, SAVE_VERSION};
+pub use world::{load_world, snapshot_world};
@@ -0,0 +1,11 @@
+//! frozen schemas of the payload written by older versions of the game.
+//!
+//! `vN` only copies the structs that changed between version N and N + 1, the
+//! rest are reused from the schema of the next version.
+
+pub mod v1;
+pub mod v3;
+pub mod v4;
+pub mod v5;
+pub mod v6;
+pub mod v7;
@@ -0,0 +1,53 @@
+//! the payload of save version 1.
+//!
+//! changed in version 2: `SolarSystemSerde` records the `Oid` of its stars.
+
+use bevy::prelude::Transform;
+use serde::{Deserialize, Serialize};
+
+use crate::map::astronomy::{AstroMass, AstroRadius};
+use crate::map::generate::GalaxySeed;
+use crate::map::star::StarObject;
+use crate::utils::oid::Oid;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolarSystemSerde {
+    pub id: Oid,
+    pub transform: Transform,
+    pub mass: AstroMass,
+    pub radius: AstroRadius,
+}
+
+impl From<SaveGame> for super::v3::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            stars: save.stars,
+            solar_systems: save.solar_systems.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// version 1 does not record which stars belong to a solar system, so the
+/// solar system is upgraded with no star.
+impl From<SolarSystemSerde> for crate::map::solar_system::SolarSystemSerde {
+    fn from(solar_system: SolarSystemSerde) -> Self {
+        Self::new(
+            solar_system.id,
+            solar_system.transform,
+            solar_system.mass,
+            solar_system.radius,
+            Vec::new(),
+        )
+    }
+}
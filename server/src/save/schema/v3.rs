@@ -0,0 +1,53 @@
+//! the payload of save version 3, version 2 shares the same structs but
+//! stores them as a single uncompressed blob.
+//!
+//! changed in version 4: the `META` section records the `PlayerFaction`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::save::format::{decode_payload, SECTION_META, SECTION_SOLAR_SYSTEMS, SECTION_STARS};
+use crate::save::section::SectionReader;
+use crate::save::SaveError;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+}
+
+impl SaveGame {
+    pub(in crate::save) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            stars,
+            solar_systems,
+        })
+    }
+}
+
+/// version 3 does not record the player, the upgraded save has no
+/// `PlayerFaction`.
+impl From<SaveGame> for super::v4::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            player: None,
+            stars: save.stars,
+            solar_systems: save.solar_systems,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! the payload of save version 4.
+//!
+//! changed in version 5: the `FLET` section records the fleets.
+
+use serde::{Deserialize, Serialize};
+
+use crate::faction::PlayerFaction;
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::save::format::{decode_payload, SECTION_META, SECTION_SOLAR_SYSTEMS, SECTION_STARS};
+use crate::save::section::SectionReader;
+use crate::save::SaveError;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub player: Option<PlayerFaction>,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+}
+
+impl SaveGame {
+    pub(in crate::save) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time, player) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            player,
+            stars,
+            solar_systems,
+        })
+    }
+}
+
+/// version 4 does not record fleets, the upgraded save has none.
+impl From<SaveGame> for super::v5::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            player: save.player,
+            stars: save.stars,
+            solar_systems: save.solar_systems,
+            fleets: Vec::new(),
+        }
+    }
+}
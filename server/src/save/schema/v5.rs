@@ -0,0 +1,62 @@
+//! the payload of save version 5.
+//!
+//! changed in version 6: the `FORT` section records the fortifications.
+
+use serde::{Deserialize, Serialize};
+
+use super::v6::FleetSerde;
+use crate::faction::PlayerFaction;
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::save::format::{
+    decode_payload, SECTION_FLEETS, SECTION_META, SECTION_SOLAR_SYSTEMS, SECTION_STARS,
+};
+use crate::save::section::SectionReader;
+use crate::save::SaveError;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub player: Option<PlayerFaction>,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+    pub fleets: Vec<FleetSerde>,
+}
+
+impl SaveGame {
+    pub(in crate::save) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time, player) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        let fleets = decode_payload(&reader.read(SECTION_FLEETS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            player,
+            stars,
+            solar_systems,
+            fleets,
+        })
+    }
+}
+
+/// version 5 does not record fortifications, the upgraded save has none.
+impl From<SaveGame> for super::v6::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            player: save.player,
+            stars: save.stars,
+            solar_systems: save.solar_systems,
+            fleets: save.fleets,
+            fortifications: Vec::new(),
+        }
+    }
+}
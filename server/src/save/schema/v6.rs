@@ -0,0 +1,101 @@
+//! the payload of save version 6.
+//!
+//! changed in version 7: a fleet records its `CombatStance`.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::v7::ShipSerde;
+use crate::faction::PlayerFaction;
+use crate::fleet::fleet::{FleetOwner, FleetRadius};
+use crate::fleet::movement::FleetMovement;
+use crate::fleet::order::OrderQueue;
+use crate::map::fortification::FortificationSerde;
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::map::SpaceLayer;
+use crate::save::format::{
+    decode_payload, SECTION_FLEETS, SECTION_FORTIFICATIONS, SECTION_META, SECTION_SOLAR_SYSTEMS,
+    SECTION_STARS,
+};
+use crate::save::section::SectionReader;
+use crate::save::SaveError;
+use crate::utils::oid::Oid;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetSerde {
+    pub id: Oid,
+    pub owner: FleetOwner,
+    pub transform: Transform,
+    pub layer: SpaceLayer,
+    pub radius: FleetRadius,
+    pub movement: FleetMovement,
+    pub orders: OrderQueue,
+    pub ships: Vec<ShipSerde>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub player: Option<PlayerFaction>,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+    pub fleets: Vec<FleetSerde>,
+    pub fortifications: Vec<FortificationSerde>,
+}
+
+impl SaveGame {
+    pub(in crate::save) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time, player) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        let fleets = decode_payload(&reader.read(SECTION_FLEETS)?)?;
+        let fortifications = decode_payload(&reader.read(SECTION_FORTIFICATIONS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            player,
+            stars,
+            solar_systems,
+            fleets,
+            fortifications,
+        })
+    }
+}
+
+/// version 6 does not record the stance, the upgraded fleet uses the default.
+impl From<FleetSerde> for super::v7::FleetSerde {
+    fn from(fleet: FleetSerde) -> Self {
+        Self {
+            id: fleet.id,
+            owner: fleet.owner,
+            transform: fleet.transform,
+            layer: fleet.layer,
+            radius: fleet.radius,
+            movement: fleet.movement,
+            orders: fleet.orders,
+            stance: None,
+            ships: fleet.ships,
+        }
+    }
+}
+
+impl From<SaveGame> for super::v7::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            player: save.player,
+            stars: save.stars,
+            solar_systems: save.solar_systems,
+            fleets: save.fleets.into_iter().map(Into::into).collect(),
+            fortifications: save.fortifications,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! the payload of save version 7.
+//!
+//! changed in version 8: the ships record their `ShipHealth`.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::faction::PlayerFaction;
+use crate::fleet::combat::CombatStance;
+use crate::fleet::design::ShipDesign;
+use crate::fleet::fleet::{FleetOwner, FleetRadius};
+use crate::fleet::movement::FleetMovement;
+use crate::fleet::order::OrderQueue;
+use crate::map::fortification::FortificationSerde;
+use crate::map::generate::GalaxySeed;
+use crate::map::solar_system::SolarSystemSerde;
+use crate::map::star::StarObject;
+use crate::map::SpaceLayer;
+use crate::save::format::{
+    decode_payload, SECTION_FLEETS, SECTION_FORTIFICATIONS, SECTION_META, SECTION_SOLAR_SYSTEMS,
+    SECTION_STARS,
+};
+use crate::save::section::SectionReader;
+use crate::save::SaveError;
+use crate::utils::oid::Oid;
+use crate::utils::time::GameTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShipSerde {
+    pub id: Oid,
+    pub design: ShipDesign,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetSerde {
+    pub id: Oid,
+    pub owner: FleetOwner,
+    pub transform: Transform,
+    pub layer: SpaceLayer,
+    pub radius: FleetRadius,
+    pub movement: FleetMovement,
+    pub orders: OrderQueue,
+    pub stance: Option<CombatStance>,
+    pub ships: Vec<ShipSerde>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: GalaxySeed,
+    pub time: GameTime,
+    pub player: Option<PlayerFaction>,
+    pub stars: Vec<StarObject>,
+    pub solar_systems: Vec<SolarSystemSerde>,
+    pub fleets: Vec<FleetSerde>,
+    pub fortifications: Vec<FortificationSerde>,
+}
+
+impl SaveGame {
+    pub(in crate::save) fn decode_sections(payload: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SectionReader::new(payload);
+        let (seed, time, player) = decode_payload(&reader.read(SECTION_META)?)?;
+        let stars = decode_payload(&reader.read(SECTION_STARS)?)?;
+        let solar_systems = decode_payload(&reader.read(SECTION_SOLAR_SYSTEMS)?)?;
+        let fleets = decode_payload(&reader.read(SECTION_FLEETS)?)?;
+        let fortifications = decode_payload(&reader.read(SECTION_FORTIFICATIONS)?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            time,
+            player,
+            stars,
+            solar_systems,
+            fleets,
+            fortifications,
+        })
+    }
+}
+
+/// version 7 does not record damage, the upgraded ships are undamaged.
+impl From<FleetSerde> for crate::fleet::fleet::FleetSerde {
+    fn from(fleet: FleetSerde) -> Self {
+        Self {
+            id: fleet.id,
+            owner: fleet.owner,
+            transform: fleet.transform,
+            layer: fleet.layer,
+            radius: fleet.radius,
+            movement: fleet.movement,
+            orders: fleet.orders,
+            stance: fleet.stance,
+            ships: fleet
+                .ships
+                .into_iter()
+                .map(|ship| crate::fleet::fleet::ShipSerde {
+                    id: ship.id,
+                    design: ship.design,
+                    health: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<SaveGame> for crate::save::SaveGame {
+    fn from(save: SaveGame) -> Self {
+        Self {
+            seed: save.seed,
+            time: save.time,
+            player: save.player,
+            stars: save.stars,
+            solar_systems: save.solar_systems,
+            fleets: save.fleets.into_iter().map(Into::into).collect(),
+            fortifications: save.fortifications,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! the container of the payload since save version 3.
+//!
+//! the payload is split into sections, each section is
+//! - tag: 4 bytes, the name of the section
+//! - length: 8 bytes, little endian, the length of the uncompressed data
+//! - stored length: 8 bytes, little endian, the length of the stored data
+//! - checksum: 20 bytes, sha1 of the stored data
+//! - stored data: the data compressed by deflate
+
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+
+use super::SaveError;
+
+/// the name of a section
+pub type SectionTag = [u8; 4];
+
+const CHECKSUM_LEN: usize = 20;
+const SECTION_HEADER_LEN: usize = 4 + 8 + 8 + CHECKSUM_LEN;
+
+/// compress `data` and append it to `out` as a section.
+pub(super) fn write_section(
+    out: &mut Vec<u8>,
+    tag: SectionTag,
+    data: &[u8],
+) -> Result<(), SaveError> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let stored = encoder.finish()?;
+    let checksum: [u8; CHECKSUM_LEN] = Sha1::digest(&stored).into();
+
+    out.reserve(SECTION_HEADER_LEN + stored.len());
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    out.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+    out.extend_from_slice(&checksum);
+    out.extend_from_slice(&stored);
+    Ok(())
+}
+
+/// read sections one by one from the bytes after the save header.
+pub(super) struct SectionReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SectionReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// read the next section, which must be `tag`, verify its checksum and
+    /// return the uncompressed data.
+    pub fn read(&mut self, tag: SectionTag) -> Result<Vec<u8>, SaveError> {
+        if self.bytes.len() < SECTION_HEADER_LEN {
+            return Err(SaveError::MissingSection(tag));
+        }
+
+        let (header, rest) = self.bytes.split_at(SECTION_HEADER_LEN);
+        let (found, header) = header.split_at(4);
+        if found != tag {
+            return Err(SaveError::MissingSection(tag));
+        }
+        let (len, header) = header.split_at(8);
+        let (stored_len, checksum) = header.split_at(8);
+        let len = u64::from_le_bytes(len.try_into().unwrap());
+        let stored_len = u64::from_le_bytes(stored_len.try_into().unwrap());
+
+        if (rest.len() as u64) < stored_len {
+            return Err(SaveError::TruncatedSection(tag));
+        }
+        let (stored, rest) = rest.split_at(stored_len as usize);
+        if Sha1::digest(stored).as_slice() != checksum {
+            return Err(SaveError::ChecksumMismatch(tag));
+        }
+
+        // read one more byte than expected, so a wrong length is detected
+        // without inflating an unbounded amount of data.
+        let mut data = Vec::new();
+        DeflateDecoder::new(stored)
+            .take(len.saturating_add(1))
+            .read_to_end(&mut data)
+            .map_err(|_| SaveError::CorruptedSection(tag))?;
+        if data.len() as u64 != len {
+            return Err(SaveError::CorruptedSection(tag));
+        }
+
+        self.bytes = rest;
+        Ok(data)
+    }
+
+    /// make sure all sections are read.
+    pub fn finish(self) -> Result<(), SaveError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            n => Err(SaveError::TrailingBytes(n)),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! export & import the world as human-readable text, for modding and
+//! debugging.
+//!
+//! the text contains the same `SaveGame` as the binary save, so importing
+//! reuses the entity reconstruction of `load_world`. unlike the binary save,
+//! text saves are not migrated, only text of `SAVE_VERSION` can be imported.
+
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::World;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use super::format::write_atomic;
+use super::{load_world, snapshot_world, SaveError, SaveGame, SAVE_VERSION};
+
+/// the format of a text save
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    Ron,
+    Json,
+}
+
+impl TextFormat {
+    /// guess the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(Self::Ron),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// the root of a text save.
+#[derive(Serialize, Deserialize)]
+struct TextSave {
+    version: u32,
+    save: SaveGame,
+}
+
+impl SaveGame {
+    /// encode the save as pretty printed text.
+    pub fn to_text(&self, format: TextFormat) -> Result<String, SaveError> {
+        let text = TextSave {
+            version: SAVE_VERSION,
+            save: self.clone(),
+        };
+
+        match format {
+            TextFormat::Ron => Ok(ron::ser::to_string_pretty(&text, PrettyConfig::new())?),
+            TextFormat::Json => Ok(serde_json::to_string_pretty(&text)?),
+        }
+    }
+
+    /// decode the save from text produced by `SaveGame::to_text`.
+    pub fn from_text(text: &str, format: TextFormat) -> Result<Self, SaveError> {
+        let text: TextSave = match format {
+            TextFormat::Ron => ron::from_str(text)?,
+            TextFormat::Json => serde_json::from_str(text)?,
+        };
+
+        if text.version != SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(text.version));
+        }
+        Ok(text.save)
+    }
+}
+
+/// dump all S/L data in the world as text.
+pub fn export_world(world: &mut World, format: TextFormat) -> Result<String, SaveError> {
+    snapshot_world(world)?.to_text(format)
+}
+
+/// replace the world with the one in `text`, see `load_world`.
+pub fn import_world(world: &mut World, text: &str, format: TextFormat) -> Result<(), SaveError> {
+    let save = SaveGame::from_text(text, format)?;
+    load_world(world, &save);
+    Ok(())
+}
+
+/// write the save to `path` as text, the format is decided by the extension.
+pub fn write_text_save(path: impl AsRef<Path>, save: &SaveGame) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    let format = TextFormat::from_path(path).ok_or(SaveError::UnknownTextFormat)?;
+    write_atomic(path, save.to_text(format)?.as_bytes())
+}
+
+/// read the save from a text file, the format is decided by the extension.
+pub fn read_text_save(path: impl AsRef<Path>) -> Result<SaveGame, SaveError> {
+    let path = path.as_ref();
+    let format = TextFormat::from_path(path).ok_or(SaveError::UnknownTextFormat)?;
+    SaveGame::from_text(&fs::read_to_string(path)?, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::world::tests::generate_save;
+
+    #[test]
+    fn export_import_is_identical() {
+        for format in [TextFormat::Ron, TextFormat::Json] {
+            let mut world = World::new();
+            load_world(&mut world, &generate_save(11));
+            let bytes = snapshot_world(&mut world).unwrap().encode().unwrap();
+            let text = export_world(&mut world, format).unwrap();
+
+            let mut imported = World::new();
+            import_world(&mut imported, &text, format).unwrap();
+            let imported_bytes = snapshot_world(&mut imported).unwrap().encode().unwrap();
+
+            assert_eq!(bytes, imported_bytes);
+            assert_eq!(export_world(&mut imported, format).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn report_position_of_syntax_error() {
+        let text = generate_save(11).to_text(TextFormat::Ron).unwrap();
+        let broken = text.replacen("seed", "sed", 1);
+
+        let error = SaveGame::from_text(&broken, TextFormat::Ron).unwrap_err();
+        assert!(matches!(error, SaveError::RonDecode(_)));
+        assert!(error.to_string().contains(':'));
+    }
+}
@@ -0,0 +1,362 @@
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+
+use super::autosave::AutosaveState;
+use super::{SaveError, SaveGame};
+use crate::faction::PlayerFaction;
+use crate::fleet::fleet::{rebuild_fleet_stats, FleetMarker, FleetSerde};
+use crate::map::fortification::{FortificationMarker, FortificationSerde, InterdictionRadius};
+use crate::map::generate::GalaxySeed;
+use crate::map::rapier_collider::rebuild_collision_world;
+use crate::map::solar_system::{ContainsStars, SolarSystemMarker, SolarSystemSerde};
+use crate::map::star::*;
+use crate::utils::oid::{Oid, OidTable};
+use crate::utils::time::GameTime;
+
+/// collect all S/L data in the world into a `SaveGame`.
+pub fn snapshot_world(world: &mut World) -> Result<SaveGame, SaveError> {
+    let seed = *world
+        .get_resource::<GalaxySeed>()
+        .ok_or(SaveError::MissingResource("GalaxySeed"))?;
+    let time = *world
+        .get_resource::<GameTime>()
+        .ok_or(SaveError::MissingResource("GameTime"))?;
+    let player = world.get_resource::<PlayerFaction>().cloned();
+
+    let mut stars: Vec<StarObject> = world
+        .query::<(
+            &Oid,
+            &AstroMass,
+            &AstroRadius,
+            &Luminosity,
+            &Temperature,
+            &StarCategory,
+            &Transform,
+        )>()
+        .iter(world)
+        .map(
+            |(id, mass, radius, luminosity, temperature, category, transform)| StarObject {
+                id: *id,
+                mass: *mass,
+                radius: *radius,
+                luminosity: *luminosity,
+                temperature: *temperature,
+                category: *category,
+                transform: *transform,
+            },
+        )
+        .collect();
+    stars.sort_by_key(|star| star.id);
+
+    let mut solar_systems: Vec<SolarSystemSerde> = world
+        .query_filtered::<(
+            &Oid,
+            &Transform,
+            &AstroMass,
+            &AstroRadius,
+            Option<&ContainsStars>,
+        ), With<SolarSystemMarker>>()
+        .iter(world)
+        .map(|(id, transform, mass, radius, contains)| {
+            let stars = contains
+                .into_iter()
+                .flat_map(ContainsStars::iter)
+                .filter_map(|star| world.get::<Oid>(star).copied())
+                .collect();
+            SolarSystemSerde::new(*id, *transform, *mass, *radius, stars)
+        })
+        .collect();
+    solar_systems.sort_by_key(SolarSystemSerde::id);
+
+    let fleets: Vec<Entity> = world
+        .query_filtered::<Entity, With<FleetMarker>>()
+        .iter(world)
+        .collect();
+    let mut fleets: Vec<FleetSerde> = fleets
+        .into_iter()
+        .filter_map(|fleet| FleetSerde::from_world(world, fleet))
+        .collect();
+    fleets.sort_by_key(|fleet| fleet.id);
+
+    let mut fortifications: Vec<FortificationSerde> = world
+        .query_filtered::<(&Oid, &Transform, &InterdictionRadius), With<FortificationMarker>>()
+        .iter(world)
+        .map(|(id, transform, radius)| FortificationSerde {
+            id: *id,
+            transform: *transform,
+            radius: *radius,
+        })
+        .collect();
+    fortifications.sort_by_key(|fortification| fortification.id);
+
+    Ok(SaveGame {
+        seed,
+        time,
+        player,
+        stars,
+        solar_systems,
+        fleets,
+        fortifications,
+    })
+}
+
+/// respawn all objects in `save` into the world and reconstruct their CON
+/// data.
+///
+/// all existing entities with an `Oid` are despawned first, the `OidTable` is
+/// replaced by a new one, and the `RapierCollisionEngine` is rebuilt. the
+/// autosave interval restarts from the loaded day.
+pub fn load_world(world: &mut World, save: &SaveGame) {
+    let existing: Vec<Entity> = world
+        .query_filtered::<Entity, With<Oid>>()
+        .iter(world)
+        .collect();
+    for entity in existing {
+        world.despawn(entity);
+    }
+
+    world.insert_resource(save.seed);
+    world.insert_resource(save.time);
+    if let Some(player) = save.player.clone() {
+        world.insert_resource(player);
+    } else {
+        world.remove_resource::<PlayerFaction>();
+    }
+    if let Some(mut autosave) = world.get_resource_mut::<AutosaveState>() {
+        autosave.reset();
+    }
+
+    let mut table = OidTable::new();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+
+    for star in save.stars.iter() {
+        table.insert(star.id, star.spawn(&mut commands));
+    }
+    for solar_system in save.solar_systems.iter() {
+        let entity = solar_system.spawn(&mut commands, &table);
+        table.insert(solar_system.id(), entity);
+    }
+    for fleet in save.fleets.iter() {
+        let entity = fleet.spawn(&mut commands, &mut table);
+        table.insert(fleet.id, entity);
+    }
+    for fortification in save.fortifications.iter() {
+        table.insert(fortification.id, fortification.spawn(&mut commands));
+    }
+
+    queue.apply(world);
+    world.insert_resource(table);
+    rebuild_fleet_stats(world);
+    rebuild_collision_world(world);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fleet::combat::{CombatStance, TargetPriority};
+    use crate::fleet::damage::ShipHealth;
+    use crate::fleet::design::ShipDesign;
+    use crate::fleet::fleet::{FleetOwner, FleetRadius, FleetShips, FleetStats, ShipSerde};
+    use crate::fleet::movement::FleetMovement;
+    use crate::fleet::order::{FleetOrder, OrderQueue};
+    use crate::fleet::ship::{ShipClass, ShipTemplate};
+    use crate::fleet::stats::ShipStats;
+    use crate::gen::Generative;
+    use crate::map::rapier_collider::{RapierCollider, RapierCollisionEngine};
+    use crate::map::SpaceLayer;
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256StarStar;
+
+    /// generate 16 solar systems with 2 stars in each of them
+    pub(crate) fn generate_save(seed: u64) -> SaveGame {
+        let mut rng = Xoshiro256StarStar::seed_from_u64(seed);
+
+        let stars: Vec<StarObject> = (0..32)
+            .map(|_| StarObject::new_from_rng(&mut rng))
+            .collect();
+        let solar_systems = (0..16_u32)
+            .map(|i| {
+                let translation = Vec3::new(rng.gen(), 0.0, rng.gen()) * 320.0;
+                SolarSystemSerde::new(
+                    Oid::v5(&i.to_le_bytes()),
+                    Transform::from_translation(translation),
+                    AstroMass::new(rng.gen_range(0.1..10.0)),
+                    AstroRadius::new(rng.gen_range(0.5..2.0)),
+                    vec![stars[2 * i as usize].id, stars[2 * i as usize + 1].id],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // a fleet heading to the 4th solar system then patrolling, and an
+        // escort with a combat stance following it, with a damaged ship
+        let owner = FleetOwner(Oid::v5(b"player"));
+        let template = ShipTemplate::new("corvette", ShipClass::Frigate, 100.0, 50.0);
+        let fleets = (0..2_u32)
+            .map(|i| {
+                let ships = (0..2_u32)
+                    .map(|j| ShipSerde {
+                        id: Oid::v5(&[i.to_le_bytes(), j.to_le_bytes()].concat()),
+                        design: ShipDesign::new("corvette", template.clone()),
+                        health: (i == 1 && j == 1).then(|| ShipHealth {
+                            integrity: 20.0,
+                            ..default()
+                        }),
+                    })
+                    .collect();
+                let orders = match i {
+                    0 => OrderQueue::new([
+                        FleetOrder::MoveToSystem {
+                            system: solar_systems[3].id(),
+                            hyperspace: true,
+                        },
+                        FleetOrder::Patrol {
+                            systems: vec![solar_systems[3].id(), solar_systems[5].id()],
+                            next: 1,
+                            hyperspace: false,
+                        },
+                    ]),
+                    _ => OrderQueue::new([FleetOrder::Follow {
+                        fleet: Oid::v5(b"fleet0"),
+                        distance: 2.0,
+                    }]),
+                };
+                FleetSerde {
+                    id: Oid::v5(format!("fleet{}", i).as_bytes()),
+                    owner,
+                    transform: solar_systems[i as usize].transform(),
+                    layer: SpaceLayer::Normal,
+                    radius: FleetRadius::new(1.0),
+                    movement: FleetMovement::default(),
+                    orders,
+                    stance: (i == 1).then_some(CombatStance {
+                        priority: TargetPriority::Weakest,
+                        retreat_below: 0.5,
+                    }),
+                    ships,
+                }
+            })
+            .collect();
+
+        // 2 fortifications between the solar systems
+        let fortifications = (0..2_u32)
+            .map(|i| {
+                let translation = Vec3::new(rng.gen(), 0.0, rng.gen()) * 320.0;
+                FortificationSerde {
+                    id: Oid::v5(format!("fortification{}", i).as_bytes()),
+                    transform: Transform::from_translation(translation),
+                    radius: InterdictionRadius::new(rng.gen_range(2.0..8.0)),
+                }
+            })
+            .collect();
+
+        SaveGame {
+            seed: GalaxySeed::new(seed),
+            time: GameTime::new(1234),
+            player: Some(PlayerFaction::new(Oid::v5(b"player"), "Terran Union")),
+            stars,
+            solar_systems,
+            fleets,
+            fortifications,
+        }
+    }
+
+    #[test]
+    fn save_load_save_is_identical() {
+        let mut world = World::new();
+        load_world(&mut world, &generate_save(92808428));
+        let bytes = snapshot_world(&mut world).unwrap().encode().unwrap();
+
+        let mut reloaded = World::new();
+        load_world(&mut reloaded, &SaveGame::decode(&bytes).unwrap());
+        let bytes_reloaded = snapshot_world(&mut reloaded).unwrap().encode().unwrap();
+
+        assert_eq!(bytes, bytes_reloaded);
+    }
+
+    #[test]
+    fn load_reconstructs_con_data() {
+        let save = generate_save(7);
+        let mut world = World::new();
+        load_world(&mut world, &save);
+
+        let table = world.resource::<OidTable>().clone();
+        for id in save.stars.iter().map(|star| star.id) {
+            let entity = table.query(&id).unwrap();
+            assert_eq!(world.get::<Oid>(entity), Some(&id));
+        }
+        for solar_system in save.solar_systems.iter() {
+            let entity = table.query(&solar_system.id()).unwrap();
+            assert!(world.get::<RapierCollider>(entity).is_some());
+
+            let stars: Vec<Entity> = solar_system
+                .stars()
+                .iter()
+                .map(|id| table.query(id).unwrap())
+                .collect();
+            let contains: Vec<Entity> =
+                world.get::<ContainsStars>(entity).unwrap().iter().collect();
+            assert_eq!(contains, stars);
+        }
+        for fortification in save.fortifications.iter() {
+            let entity = table.query(&fortification.id).unwrap();
+            assert!(world.get::<RapierCollider>(entity).is_some());
+            assert_eq!(
+                world.get::<InterdictionRadius>(entity),
+                Some(&fortification.radius)
+            );
+        }
+        assert_eq!(
+            world.resource::<RapierCollisionEngine>().len(),
+            save.solar_systems.len() + save.fleets.len() + save.fortifications.len()
+        );
+    }
+
+    #[test]
+    fn fleets_and_orders_survive_reload() {
+        let save = generate_save(11);
+        let mut world = World::new();
+        load_world(&mut world, &save);
+
+        let table = world.resource::<OidTable>().clone();
+        for fleet in save.fleets.iter() {
+            let entity = table.query(&fleet.id).unwrap();
+            assert!(world.get::<RapierCollider>(entity).is_some());
+            assert_eq!(world.get::<OrderQueue>(entity), Some(&fleet.orders));
+            assert_eq!(world.get::<CombatStance>(entity), fleet.stance.as_ref());
+            assert_eq!(world.get::<FleetStats>(entity).unwrap().ships, 2);
+
+            let ships: Vec<Entity> = world.get::<FleetShips>(entity).unwrap().iter().collect();
+            for (ship, saved) in ships.iter().zip(fleet.ships.iter()) {
+                assert_eq!(table.query(&saved.id), Some(*ship));
+                assert!(world.get::<ShipStats>(*ship).is_some());
+                assert_eq!(world.get::<ShipHealth>(*ship), saved.health.as_ref());
+            }
+        }
+
+        // the escort order refers to the fleet by `Oid`
+        let escort = table.query(&save.fleets[1].id).unwrap();
+        let Some(FleetOrder::Follow { fleet, .. }) =
+            world.get::<OrderQueue>(escort).unwrap().current()
+        else {
+            panic!("missing follow order");
+        };
+        assert_eq!(table.query(fleet), table.query(&save.fleets[0].id));
+    }
+
+    #[test]
+    fn reject_invalid_header() {
+        let bytes = generate_save(1).encode().unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            SaveGame::decode(&bad_magic),
+            Err(SaveError::BadMagic)
+        ));
+        assert!(matches!(
+            SaveGame::decode(&bytes[..4]),
+            Err(SaveError::Truncated)
+        ));
+    }
+}
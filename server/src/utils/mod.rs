@@ -1,4 +1,5 @@
 pub mod oid;
 pub mod sync;
+pub mod time;
 
-const SERDE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+pub(crate) const SERDE_CONFIG: bincode::config::Configuration = bincode::config::standard();
@@ -124,6 +124,12 @@ impl OidTable {
         self.data.insert(id, entity);
     }
 
+    /// remove a `OId` from the table immediately, for objects despawned by
+    /// exclusive systems which may be queried before the next rebuild.
+    pub fn remove(&mut self, id: &Oid) -> Option<Entity> {
+        self.data.remove(id)
+    }
+
     /// record the number of should removed entry. since it's ok to ignore this
     /// request until there is memory issue, we ignore it until the counter grow
     /// too large.
@@ -0,0 +1,51 @@
+//! a module for the in-game calendar.
+
+use bevy::prelude::{ResMut, Resource};
+use serde::{Deserialize, Serialize};
+
+/// the number of simulation ticks in one in-game day.
+pub const TICKS_PER_DAY: u64 = 64;
+
+/// the in-game clock, counted in simulation ticks since the galaxy is created.
+///
+/// S/L data
+#[derive(
+    Resource,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+pub struct GameTime(u64);
+
+impl GameTime {
+    pub fn new(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// the number of ticks since the galaxy is created
+    pub fn ticks(&self) -> u64 {
+        self.0
+    }
+
+    /// the number of whole in-game days since the galaxy is created
+    pub fn days(&self) -> u64 {
+        self.0 / TICKS_PER_DAY
+    }
+}
+
+/// a system to advance the in-game clock by one tick.
+///
+/// schedule requirement:
+/// - must run exactly once per simulation tick, i.e. in
+///   `CoreSchedule::FixedUpdate`
+pub fn system_game_time_advance(mut time: ResMut<GameTime>) {
+    time.0 += 1;
+}